@@ -1,9 +1,9 @@
-use flux::plugin::FluxPlugin;
+use flux_plugin::{export_plugin, Context, FluxPlugin};
+use std::io::Write;
 
 #[derive(Default)]
 pub struct ExamplePlugin {
     name: String,
-    config: Option<String>,
 }
 
 impl FluxPlugin for ExamplePlugin {
@@ -35,22 +35,30 @@ impl FluxPlugin for ExamplePlugin {
         Ok(())
     }
 
-    fn execute(&self, args: &[String]) -> Result<(), String> {
-        match args.get(0).map(String::as_str) {
-            Some("add") => self.handle_math(args, |a, b| Ok(a + b), "+"),
-            Some("sub") => self.handle_math(args, |a, b| Ok(a - b), "-"),
-            Some("mul") => self.handle_math(args, |a, b| Ok(a * b), "*"),
-            Some("div") => self.handle_math(args, |a, b| {
-                if b == 0 {
-                    return Err("Division by zero!".to_string());
-                }
-                Ok(a / b)
-            }, "/"),
+    fn execute(&self, context: &mut Context, args: &[String]) -> Result<i32, String> {
+        match args.first().map(String::as_str) {
+            Some("add") => self.handle_math(context, args, |a, b| Ok(a + b), "+"),
+            Some("sub") => self.handle_math(context, args, |a, b| Ok(a - b), "-"),
+            Some("mul") => self.handle_math(context, args, |a, b| Ok(a * b), "*"),
+            Some("div") => self.handle_math(
+                context,
+                args,
+                |a, b| {
+                    if b == 0 {
+                        return Err("Division by zero!".to_string());
+                    }
+                    Ok(a / b)
+                },
+                "/",
+            ),
             Some("help") => {
-                println!("{}", self.help());
-                Ok(())
+                writeln!(context.stdout(), "{}", self.help()).map_err(|e| e.to_string())?;
+                Ok(0)
             }
-            _ => Err(format!("Unknown command. Use '{} help' for usage", self.name()))
+            _ => Err(format!(
+                "Unknown command. Use '{} help' for usage",
+                self.name()
+            )),
         }
     }
 
@@ -58,58 +66,75 @@ impl FluxPlugin for ExamplePlugin {
         println!("Example plugin cleaned up!");
         Ok(())
     }
-
-    fn configure(&mut self, config: &str) -> Result<(), String> {
-        self.config = Some(config.to_string());
-        println!("Plugin configured with: {}", config);
-        Ok(())
-    }
 }
 
 impl ExamplePlugin {
-    fn handle_math<F>(&self, args: &[String], op: F, symbol: &str) -> Result<(), String>
+    fn handle_math<F>(
+        &self,
+        context: &mut Context,
+        args: &[String],
+        op: F,
+        symbol: &str,
+    ) -> Result<i32, String>
     where
-        F: Fn(i64, i64) -> Result<i64, String>
+        F: Fn(i64, i64) -> Result<i64, String>,
     {
         if args.len() != 3 {
             return Err(format!("Usage: {} {} <num1> <num2>", self.name(), args[0]));
         }
-        
-        let num1: i64 = args[1].parse()
+
+        let num1: i64 = args[1]
+            .parse()
             .map_err(|e: std::num::ParseIntError| e.to_string())?;
-        let num2: i64 = args[2].parse()
+        let num2: i64 = args[2]
+            .parse()
             .map_err(|e: std::num::ParseIntError| e.to_string())?;
-        
-        match op(num1, num2) {
-            Ok(result) => {
-                println!("{} {} {} = {}", num1, symbol, num2, result);
-                Ok(())
-            }
-            Err(e) => Err(e)
-        }
+
+        let result: i64 = op(num1, num2)?;
+        writeln!(
+            context.stdout(),
+            "{} {} {} = {}",
+            num1,
+            symbol,
+            num2,
+            result
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(0)
     }
 }
 
-#[no_mangle]
-pub fn create_plugin() -> Box<dyn FluxPlugin> {
-    Box::new(ExamplePlugin::default())
-}
+export_plugin!(ExamplePlugin);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use flux_plugin::{Buffers, Terminal};
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn run(args: &[&str], buffers: &mut Buffers) -> Result<i32, String> {
+        let plugin = ExamplePlugin::default();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut context = Context::new(
+            PathBuf::from("/"),
+            BTreeMap::new(),
+            Terminal::default(),
+            buffers,
+        );
+        plugin.execute(&mut context, &args)
+    }
 
     #[test]
     fn test_add() {
-        let plugin = ExamplePlugin::default();
-        let args = vec!["add".to_string(), "5".to_string(), "3".to_string()];
-        assert!(plugin.execute(&args).is_ok());
+        let mut buffers = Buffers::default();
+        assert_eq!(run(&["add", "5", "3"], &mut buffers), Ok(0));
+        assert_eq!(buffers.stdout, b"5 + 3 = 8\n");
     }
 
     #[test]
     fn test_div_by_zero() {
-        let plugin = ExamplePlugin::default();
-        let args = vec!["div".to_string(), "5".to_string(), "0".to_string()];
-        assert!(plugin.execute(&args).is_err());
+        let mut buffers = Buffers::default();
+        assert!(run(&["div", "5", "0"], &mut buffers).is_err());
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,129 @@
+#!/usr/bin/env python3
+"""An example flux plugin that runs as a separate process.
+
+flux writes one JSON-RPC 2.0 request per line to stdin and reads one
+response per line from stdout. Output for the terminal is sent as `output`
+notifications so it appears while the command is still running.
+"""
+
+import json
+import os
+import sys
+import time
+
+NAME = "countdown"
+COMMANDS = ["count", "goto", "help", "say", "upper"]
+
+
+def send(message):
+    sys.stdout.write(json.dumps(message) + "\n")
+    sys.stdout.flush()
+
+
+def output(text, stream="stdout"):
+    send({"jsonrpc": "2.0", "method": "output",
+          "params": {"stream": stream, "text": text}})
+
+
+class PluginError(Exception):
+    pass
+
+
+def execute(params):
+    """Runs a command; the result may set an exit code, variables and the
+    shell's directory."""
+    args = params.get("args", [])
+    if not args or args[0] == "help":
+        output("Commands: count <n>, goto <dir>, say <words...>, upper\n")
+    elif args[0] == "count":
+        try:
+            n = int(args[1])
+        except (IndexError, ValueError):
+            raise PluginError("usage: count <n>")
+        for i in range(n, 0, -1):
+            output(f"{i}\n")
+            time.sleep(0.5)
+        output("Liftoff!\n")
+        return {"env": {"COUNTDOWN_LAST": str(n)}}
+    elif args[0] == "say":
+        output(" ".join(args[1:]) + "\n")
+    elif args[0] == "upper":
+        # Piped input arrives whole; null when reading from the terminal
+        if params.get("stdin") is None:
+            output("upper: pipe something into me\n", "stderr")
+            return {"exit_code": 2}
+        output(params["stdin"].upper())
+    elif args[0] == "goto":
+        if len(args) != 2:
+            raise PluginError("usage: goto <dir>")
+        return {"cwd": os.path.join(params["cwd"], args[1])}
+    else:
+        raise PluginError(f"unknown command: {args[0]}")
+    return {"exit_code": 0}
+
+
+def complete(args):
+    if len(args) <= 1:
+        prefix = args[0] if args else ""
+        return [c for c in COMMANDS if c.startswith(prefix)]
+    return []
+
+
+def load_env_file(directory):
+    """Reads NAME=value lines from a .flux-env file, direnv style."""
+    env = {}
+    try:
+        with open(os.path.join(directory, ".flux-env")) as f:
+            for line in f:
+                name, sep, value = line.strip().partition("=")
+                if sep and not name.startswith("#"):
+                    env[name] = value
+    except OSError:
+        pass
+    return env
+
+
+def handle(method, params):
+    if method == "describe":
+        return {"name": NAME, "version": "1.0.0", "protocol": 1}
+    if method == "execute":
+        return execute(params)
+    if method == "complete":
+        return complete(params.get("args", []))
+    if method == "prompt_segments":
+        return {"countdown": time.strftime("%H:%M")}
+    if method == "preexec":
+        if params["command"].strip() in ("rm -rf /", "rm -rf /*"):
+            output("countdown: refusing to run that\n", "stderr")
+            return {"action": "veto"}
+        return {"action": "run"}
+    if method == "precmd":
+        if params["duration_ms"] >= 5000:
+            seconds = params["duration_ms"] / 1000
+            output(f"countdown: '{params['command']}' took {seconds:.1f}s\n", "stderr")
+        return {}
+    if method == "chpwd":
+        # Unset what the old directory set and load the new one
+        env = {name: None for name in load_env_file(params["old"])}
+        env.update(load_env_file(params["new"]))
+        return {"env": env}
+    if method == "shutdown":
+        return None
+    raise PluginError(f"unknown method: {method}")
+
+
+def main():
+    for line in sys.stdin:
+        request = json.loads(line)
+        try:
+            result = handle(request["method"], request.get("params", {}))
+            send({"jsonrpc": "2.0", "id": request["id"], "result": result})
+        except PluginError as e:
+            send({"jsonrpc": "2.0", "id": request["id"],
+                  "error": {"code": 1, "message": str(e)}})
+        if request["method"] == "shutdown":
+            break
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,300 @@
+use crate::Terminal;
+use std::ffi::c_void;
+
+/// Version of the plugin ABI
+///
+/// Bump this whenever a type in this module changes layout or meaning. The
+/// shell refuses to load plugins built for another version.
+pub const ABI_VERSION: u32 = 1;
+
+/// Symbol every plugin exports, returning a pointer to its `PluginDescriptor`
+pub const DESCRIPTOR_SYMBOL: &[u8] = b"flux_plugin_descriptor";
+
+/// Type of the function exported as `DESCRIPTOR_SYMBOL`
+pub type DescriptorFn = unsafe extern "C" fn() -> *const PluginDescriptor;
+
+/// A borrowed UTF-8 string passed across the plugin boundary
+///
+/// The memory belongs to the side that created it and is only valid for the
+/// duration of the call.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FfiStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl FfiStr {
+    /// Borrows a string slice
+    pub fn new(text: &str) -> Self {
+        FfiStr {
+            ptr: text.as_ptr(),
+            len: text.len(),
+        }
+    }
+
+    /// Copies the string, replacing invalid UTF-8
+    ///
+    /// # Safety
+    /// `ptr` must point to `len` readable bytes, or `len` must be 0.
+    pub unsafe fn read(&self) -> String {
+        if self.len == 0 {
+            return String::new();
+        }
+        let bytes: &[u8] = std::slice::from_raw_parts(self.ptr, self.len);
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+/// A string allocated by the plugin
+///
+/// The host reads it and hands it back through `PluginVTable::free_string`,
+/// so it is freed by the allocator that created it.
+#[repr(C)]
+pub struct FfiString {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+impl FfiString {
+    /// Moves a string into an FFI string
+    pub fn from_string(text: String) -> Self {
+        let boxed: Box<str> = text.into_boxed_str();
+        let len: usize = boxed.len();
+        FfiString {
+            ptr: Box::into_raw(boxed) as *mut u8,
+            len,
+        }
+    }
+
+    /// Copies the string without taking ownership
+    ///
+    /// # Safety
+    /// The string must have been created by `from_string` and not freed.
+    pub unsafe fn read(&self) -> String {
+        FfiStr {
+            ptr: self.ptr,
+            len: self.len,
+        }
+        .read()
+    }
+
+    /// Takes the string back to free it
+    ///
+    /// # Safety
+    /// The string must have been created by `from_string` in the same binary
+    /// and not freed.
+    pub unsafe fn into_string(self) -> String {
+        let raw: *mut str = std::ptr::slice_from_raw_parts_mut(self.ptr, self.len) as *mut str;
+        Box::from_raw(raw).into_string()
+    }
+}
+
+/// A list of strings allocated by the plugin, freed through
+/// `PluginVTable::free_list`
+#[repr(C)]
+pub struct FfiStringList {
+    pub ptr: *mut FfiString,
+    pub len: usize,
+}
+
+impl FfiStringList {
+    /// Moves strings into an FFI list
+    pub fn from_vec(items: Vec<String>) -> Self {
+        let boxed: Box<[FfiString]> = items.into_iter().map(FfiString::from_string).collect();
+        let len: usize = boxed.len();
+        FfiStringList {
+            ptr: Box::into_raw(boxed) as *mut FfiString,
+            len,
+        }
+    }
+
+    /// Copies the strings without taking ownership
+    ///
+    /// # Safety
+    /// The list must have been created by `from_vec` and not freed.
+    pub unsafe fn read(&self) -> Vec<String> {
+        if self.len == 0 {
+            return Vec::new();
+        }
+        std::slice::from_raw_parts(self.ptr, self.len)
+            .iter()
+            .map(|item| item.read())
+            .collect()
+    }
+
+    /// Takes the strings back to free them
+    ///
+    /// # Safety
+    /// The list must have been created by `from_vec` in the same binary and
+    /// not freed.
+    pub unsafe fn into_vec(self) -> Vec<String> {
+        let raw: *mut [FfiString] = std::ptr::slice_from_raw_parts_mut(self.ptr, self.len);
+        Box::from_raw(raw)
+            .into_vec()
+            .into_iter()
+            .map(|item| item.into_string())
+            .collect()
+    }
+}
+
+/// Outcome of a plugin call: success, or failure with an error message
+#[repr(C)]
+pub struct FfiResult {
+    pub ok: bool,
+    /// Error message; empty on success
+    pub error: FfiString,
+}
+
+impl FfiResult {
+    /// Converts a Rust result
+    pub fn from_result(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => FfiResult {
+                ok: true,
+                error: FfiString::from_string(String::new()),
+            },
+            Err(e) => FfiResult {
+                ok: false,
+                error: FfiString::from_string(e),
+            },
+        }
+    }
+}
+
+/// Exit code of a command, with the error that ended it if it failed
+#[repr(C)]
+pub struct FfiStatus {
+    /// Whether the command returned rather than failed with an error
+    pub ok: bool,
+    pub code: i32,
+    /// Error message if not `ok`, which may be empty
+    pub error: FfiString,
+}
+
+impl FfiStatus {
+    /// Converts a Rust result; an error exits with 1
+    pub fn from_result(result: Result<i32, String>) -> Self {
+        match result {
+            Ok(code) => FfiStatus {
+                ok: true,
+                code,
+                error: FfiString::from_string(String::new()),
+            },
+            Err(e) => FfiStatus {
+                ok: false,
+                code: 1,
+                error: FfiString::from_string(e),
+            },
+        }
+    }
+}
+
+/// Outcome of a hook call
+#[repr(C)]
+pub struct FfiHookResult {
+    pub ok: bool,
+    /// `hooks::CONTINUE`, `hooks::REWRITE` or `hooks::VETO`
+    pub action: i32,
+    /// The rewritten command for `REWRITE`, or the error if not `ok`
+    pub text: FfiString,
+}
+
+impl FfiHookResult {
+    /// Converts the result of `hooks::__dispatch`
+    pub fn from_result(result: Result<(i32, String), String>) -> Self {
+        match result {
+            Ok((action, text)) => FfiHookResult {
+                ok: true,
+                action,
+                text: FfiString::from_string(text),
+            },
+            Err(e) => FfiHookResult {
+                ok: false,
+                action: crate::hooks::CONTINUE,
+                text: FfiString::from_string(e),
+            },
+        }
+    }
+}
+
+/// Stream numbers for `FfiContext::write`
+pub const STDOUT: i32 = 1;
+pub const STDERR: i32 = 2;
+
+/// What the shell passes a command, valid for the duration of `execute`
+///
+/// `host` is passed back to each callback.
+#[repr(C)]
+pub struct FfiContext {
+    pub cwd: FfiStr,
+    /// Environment as `NAME=value` strings
+    pub env: *const FfiStr,
+    pub env_len: usize,
+    pub terminal: Terminal,
+    pub host: *mut c_void,
+    /// Reads stdin into `buf`; returns the bytes read, 0 at the end, -1 on error
+    pub read: unsafe extern "C" fn(host: *mut c_void, buf: *mut u8, cap: usize) -> isize,
+    /// Writes to `STDOUT` or `STDERR`; returns the bytes written, -1 on error
+    pub write:
+        unsafe extern "C" fn(host: *mut c_void, stream: i32, data: *const u8, len: usize) -> isize,
+    /// Sets a variable, or removes it when `remove` is set
+    pub set_var: unsafe extern "C" fn(host: *mut c_void, name: FfiStr, value: FfiStr, remove: bool),
+    /// Changes directory once the command finishes; false if `path` is not a
+    /// directory the plugin may change to
+    pub set_current_dir: unsafe extern "C" fn(host: *mut c_void, path: FfiStr) -> bool,
+}
+
+/// Functions the shell calls on a plugin
+///
+/// `instance` is the pointer returned by `create`. Strings and lists a
+/// function returns must be released with `free_string` and `free_list`;
+/// `FfiStr` values borrow from the instance.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginVTable {
+    /// Creates a plugin instance
+    pub create: unsafe extern "C" fn() -> *mut c_void,
+    /// Destroys an instance created by `create`
+    pub destroy: unsafe extern "C" fn(instance: *mut c_void),
+    pub name: unsafe extern "C" fn(instance: *const c_void) -> FfiStr,
+    pub version: unsafe extern "C" fn(instance: *const c_void) -> FfiStr,
+    pub description: unsafe extern "C" fn(instance: *const c_void) -> FfiStr,
+    /// Commands as a flat list of name and description pairs
+    pub commands: unsafe extern "C" fn(instance: *const c_void) -> FfiStringList,
+    /// Prompt segments as a flat list of name and value pairs
+    pub prompt_segments: unsafe extern "C" fn(instance: *const c_void) -> FfiStringList,
+    pub init: unsafe extern "C" fn(instance: *mut c_void) -> FfiResult,
+    pub execute: unsafe extern "C" fn(
+        instance: *const c_void,
+        context: *const FfiContext,
+        args: *const FfiStr,
+        len: usize,
+    ) -> FfiStatus,
+    pub cleanup: unsafe extern "C" fn(instance: *mut c_void) -> FfiResult,
+    /// Completion candidates for the last argument
+    pub complete: unsafe extern "C" fn(
+        instance: *const c_void,
+        args: *const FfiStr,
+        len: usize,
+    ) -> FfiStringList,
+    /// Calls a hook; `args` is the hook's name followed by its arguments
+    pub hook: unsafe extern "C" fn(
+        instance: *const c_void,
+        context: *const FfiContext,
+        args: *const FfiStr,
+        len: usize,
+    ) -> FfiHookResult,
+    pub free_string: unsafe extern "C" fn(text: FfiString),
+    pub free_list: unsafe extern "C" fn(list: FfiStringList),
+}
+
+/// What a plugin exports through `DESCRIPTOR_SYMBOL`
+///
+/// `abi_version` is the first field in every version of the ABI, so the
+/// shell can check it before reading anything else.
+#[repr(C)]
+pub struct PluginDescriptor {
+    pub abi_version: u32,
+    pub vtable: PluginVTable,
+}
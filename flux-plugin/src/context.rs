@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The terminal a command runs in
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Terminal {
+    /// Whether stdin is the terminal rather than a pipe
+    pub stdin_tty: bool,
+    /// Whether stdout is the terminal rather than a pipe
+    pub stdout_tty: bool,
+    /// Width in columns; 0 if unknown
+    pub columns: u16,
+    /// Height in rows; 0 if unknown
+    pub rows: u16,
+}
+
+/// Output streams of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// How a context reaches the shell; implemented once for each runtime
+pub trait Host {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+    fn write(&mut self, stream: Stream, data: &[u8]) -> io::Result<usize>;
+    /// Sets a variable, or removes it when `value` is `None`
+    fn set_var(&mut self, name: &str, value: Option<&str>);
+    /// Changes directory once the command finishes; `path` is absolute
+    fn set_current_dir(&mut self, path: &Path) -> Result<(), String>;
+}
+
+/// What a plugin command runs with
+///
+/// Commands read and write through `stdin`, `stdout` and `stderr` rather than
+/// the process's own streams, so they work in pipes and redirections like any
+/// other command. Changes made with `set_var` and `set_current_dir` apply to
+/// the shell once the command returns.
+pub struct Context<'a> {
+    cwd: PathBuf,
+    env: BTreeMap<String, String>,
+    terminal: Terminal,
+    host: &'a mut dyn Host,
+}
+
+impl<'a> Context<'a> {
+    /// Creates a context; the shell does this for each command, and tests
+    /// can with `Buffers` as the host
+    pub fn new(
+        cwd: PathBuf,
+        env: BTreeMap<String, String>,
+        terminal: Terminal,
+        host: &'a mut dyn Host,
+    ) -> Self {
+        Context {
+            cwd,
+            env,
+            terminal,
+            host,
+        }
+    }
+
+    /// The shell's working directory
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// A variable from the shell's environment
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.env.get(name).map(String::as_str)
+    }
+
+    /// The shell's environment
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn terminal(&self) -> Terminal {
+        self.terminal
+    }
+
+    /// The command's input: the previous command's output in a pipe,
+    /// otherwise the terminal
+    pub fn stdin(&mut self) -> Input<'_> {
+        Input { host: self.host }
+    }
+
+    pub fn stdout(&mut self) -> Output<'_> {
+        Output {
+            host: self.host,
+            stream: Stream::Stdout,
+        }
+    }
+
+    pub fn stderr(&mut self) -> Output<'_> {
+        Output {
+            host: self.host,
+            stream: Stream::Stderr,
+        }
+    }
+
+    /// Sets a variable in the shell's environment
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        self.env.insert(name.to_string(), value.to_string());
+        self.host.set_var(name, Some(value));
+    }
+
+    /// Removes a variable from the shell's environment
+    pub fn remove_var(&mut self, name: &str) {
+        self.env.remove(name);
+        self.host.set_var(name, None);
+    }
+
+    /// Changes the shell's working directory
+    ///
+    /// # Arguments
+    /// * `path` - Directory to change to, relative to `cwd`
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Error if the directory does not exist or the
+    ///   plugin may not change to it
+    pub fn set_current_dir(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path: PathBuf = self.cwd.join(path);
+        self.host.set_current_dir(&path)?;
+        self.cwd = path;
+        Ok(())
+    }
+}
+
+/// A command's input, from `Context::stdin`
+pub struct Input<'c> {
+    host: &'c mut dyn Host,
+}
+
+impl Read for Input<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.host.read(buf)
+    }
+}
+
+/// One of a command's output streams, from `Context::stdout` or
+/// `Context::stderr`
+pub struct Output<'c> {
+    host: &'c mut dyn Host,
+    stream: Stream,
+}
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.host.write(self.stream, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A host that keeps a command's input, output and requested changes in
+/// memory, for testing plugins
+#[derive(Debug, Default)]
+pub struct Buffers {
+    pub stdin: io::Cursor<Vec<u8>>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Variables set, or removed when `None`, in order
+    pub vars: Vec<(String, Option<String>)>,
+    /// Directory the command changed to
+    pub cwd: Option<PathBuf>,
+}
+
+impl Buffers {
+    /// Buffers with the given input
+    pub fn with_stdin(input: &str) -> Self {
+        Buffers {
+            stdin: io::Cursor::new(input.as_bytes().to_vec()),
+            ..Buffers::default()
+        }
+    }
+}
+
+impl Host for Buffers {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdin.read(buf)
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> io::Result<usize> {
+        match stream {
+            Stream::Stdout => self.stdout.write(data),
+            Stream::Stderr => self.stderr.write(data),
+        }
+    }
+
+    fn set_var(&mut self, name: &str, value: Option<&str>) {
+        self.vars.push((name.to_string(), value.map(String::from)));
+    }
+
+    fn set_current_dir(&mut self, path: &Path) -> Result<(), String> {
+        self.cwd = Some(path.to_path_buf());
+        Ok(())
+    }
+}
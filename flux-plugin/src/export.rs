@@ -0,0 +1,219 @@
+use crate::abi::{
+    FfiContext, FfiHookResult, FfiResult, FfiStatus, FfiStr, FfiString, FfiStringList,
+    PluginDescriptor, PluginVTable, ABI_VERSION, STDERR, STDOUT,
+};
+use crate::{hooks, Context, FluxPlugin, Host, Stream};
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+impl PluginDescriptor {
+    /// Builds the descriptor for a plugin type; used by `export_plugin!`
+    pub const fn new<T: FluxPlugin + Default>() -> Self {
+        PluginDescriptor {
+            abi_version: ABI_VERSION,
+            vtable: PluginVTable {
+                create: create::<T>,
+                destroy: destroy::<T>,
+                name: name::<T>,
+                version: version::<T>,
+                description: description::<T>,
+                commands: commands::<T>,
+                prompt_segments: prompt_segments::<T>,
+                init: init::<T>,
+                execute: execute::<T>,
+                cleanup: cleanup::<T>,
+                complete: complete::<T>,
+                hook: hook::<T>,
+                free_string,
+                free_list,
+            },
+        }
+    }
+}
+
+/// Runs plugin code, turning a panic into an error instead of unwinding
+/// into the shell
+fn guard<R>(call: impl FnOnce() -> Result<R, String>) -> Result<R, String> {
+    catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|panic| {
+        let message: &str = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        Err(format!("plugin panicked: {}", message))
+    })
+}
+
+unsafe fn instance<'a, T>(instance: *const c_void) -> &'a T {
+    &*(instance as *const T)
+}
+
+unsafe fn instance_mut<'a, T>(instance: *mut c_void) -> &'a mut T {
+    &mut *(instance as *mut T)
+}
+
+/// Copies the arguments passed by the shell
+unsafe fn read_args(args: *const FfiStr, len: usize) -> Vec<String> {
+    if len == 0 {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(args, len)
+        .iter()
+        .map(|arg| arg.read())
+        .collect()
+}
+
+/// Reaches the shell through the callbacks in an `FfiContext`
+struct FfiHost<'a>(&'a FfiContext);
+
+impl Host for FfiHost<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read: isize = unsafe { (self.0.read)(self.0.host, buf.as_mut_ptr(), buf.len()) };
+        usize::try_from(read).map_err(|_| io::Error::other("failed to read stdin"))
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> io::Result<usize> {
+        let stream: i32 = match stream {
+            Stream::Stdout => STDOUT,
+            Stream::Stderr => STDERR,
+        };
+        let written: isize =
+            unsafe { (self.0.write)(self.0.host, stream, data.as_ptr(), data.len()) };
+        usize::try_from(written).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    fn set_var(&mut self, name: &str, value: Option<&str>) {
+        let (value, remove): (&str, bool) = match value {
+            Some(value) => (value, false),
+            None => ("", true),
+        };
+        unsafe { (self.0.set_var)(self.0.host, FfiStr::new(name), FfiStr::new(value), remove) }
+    }
+
+    fn set_current_dir(&mut self, path: &Path) -> Result<(), String> {
+        let path_text: String = path.to_string_lossy().into_owned();
+        if unsafe { (self.0.set_current_dir)(self.0.host, FfiStr::new(&path_text)) } {
+            Ok(())
+        } else {
+            Err(format!("{}: not a directory", path.display()))
+        }
+    }
+}
+
+/// Flattens pairs into a list of alternating names and values
+fn pairs(items: Vec<(String, String)>) -> FfiStringList {
+    FfiStringList::from_vec(items.into_iter().flat_map(|(a, b)| [a, b]).collect())
+}
+
+unsafe extern "C" fn create<T: FluxPlugin + Default>() -> *mut c_void {
+    guard(|| Ok(Box::into_raw(Box::<T>::default()) as *mut c_void)).unwrap_or(std::ptr::null_mut())
+}
+
+unsafe extern "C" fn destroy<T: FluxPlugin>(plugin: *mut c_void) {
+    let _ = guard(|| {
+        drop(Box::from_raw(plugin as *mut T));
+        Ok(())
+    });
+}
+
+unsafe extern "C" fn name<T: FluxPlugin>(plugin: *const c_void) -> FfiStr {
+    guard(|| Ok(FfiStr::new(instance::<T>(plugin).name()))).unwrap_or(FfiStr::new(""))
+}
+
+unsafe extern "C" fn version<T: FluxPlugin>(plugin: *const c_void) -> FfiStr {
+    guard(|| Ok(FfiStr::new(instance::<T>(plugin).version()))).unwrap_or(FfiStr::new(""))
+}
+
+unsafe extern "C" fn description<T: FluxPlugin>(plugin: *const c_void) -> FfiStr {
+    guard(|| Ok(FfiStr::new(instance::<T>(plugin).description()))).unwrap_or(FfiStr::new(""))
+}
+
+unsafe extern "C" fn commands<T: FluxPlugin>(plugin: *const c_void) -> FfiStringList {
+    let commands: Vec<(String, String)> = guard(|| {
+        Ok(instance::<T>(plugin)
+            .commands()
+            .into_iter()
+            .map(|(command, description)| (command.to_string(), description.to_string()))
+            .collect())
+    })
+    .unwrap_or_default();
+    pairs(commands)
+}
+
+unsafe extern "C" fn prompt_segments<T: FluxPlugin>(plugin: *const c_void) -> FfiStringList {
+    pairs(guard(|| Ok(instance::<T>(plugin).prompt_segments())).unwrap_or_default())
+}
+
+unsafe extern "C" fn init<T: FluxPlugin>(plugin: *mut c_void) -> FfiResult {
+    FfiResult::from_result(guard(|| instance_mut::<T>(plugin).init()))
+}
+
+unsafe extern "C" fn execute<T: FluxPlugin>(
+    plugin: *const c_void,
+    context: *const FfiContext,
+    args: *const FfiStr,
+    len: usize,
+) -> FfiStatus {
+    FfiStatus::from_result(guard(|| {
+        with_context(&*context, |context| {
+            instance::<T>(plugin).execute(context, &read_args(args, len))
+        })
+    }))
+}
+
+unsafe extern "C" fn hook<T: FluxPlugin>(
+    plugin: *const c_void,
+    context: *const FfiContext,
+    args: *const FfiStr,
+    len: usize,
+) -> FfiHookResult {
+    FfiHookResult::from_result(guard(|| {
+        with_context(&*context, |context| {
+            hooks::__dispatch(instance::<T>(plugin), context, &read_args(args, len))
+        })
+    }))
+}
+
+/// Builds the `Context` for a call from the one the shell passed
+unsafe fn with_context<R>(context: &FfiContext, call: impl FnOnce(&mut Context) -> R) -> R {
+    let env: BTreeMap<String, String> = read_args(context.env, context.env_len)
+        .into_iter()
+        .filter_map(|entry| {
+            let (name, value) = entry.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    let mut host: FfiHost = FfiHost(context);
+    let mut context: Context = Context::new(
+        PathBuf::from(context.cwd.read()),
+        env,
+        context.terminal,
+        &mut host,
+    );
+    call(&mut context)
+}
+
+unsafe extern "C" fn cleanup<T: FluxPlugin>(plugin: *mut c_void) -> FfiResult {
+    FfiResult::from_result(guard(|| instance_mut::<T>(plugin).cleanup()))
+}
+
+unsafe extern "C" fn complete<T: FluxPlugin>(
+    plugin: *const c_void,
+    args: *const FfiStr,
+    len: usize,
+) -> FfiStringList {
+    FfiStringList::from_vec(
+        guard(|| Ok(instance::<T>(plugin).complete(&read_args(args, len)))).unwrap_or_default(),
+    )
+}
+
+unsafe extern "C" fn free_string(text: FfiString) {
+    drop(text.into_string());
+}
+
+unsafe extern "C" fn free_list(list: FfiStringList) {
+    drop(list.into_vec());
+}
@@ -0,0 +1,79 @@
+//! Events plugins can hook into
+//!
+//! A plugin lists the hooks it handles under `hooks` in its manifest, and the
+//! shell only calls those. Across the native and WebAssembly interfaces a
+//! hook call is a list of the hook's name followed by its arguments, and
+//! returns `CONTINUE`, `REWRITE` or `VETO`.
+
+use crate::{Context, FluxPlugin};
+use std::time::Duration;
+
+/// Before a command runs: `[command]`
+pub const PREEXEC: &str = "preexec";
+/// After a command finishes: `[command, status, duration in milliseconds]`
+pub const PRECMD: &str = "precmd";
+/// After the directory changes: `[old, new]`
+pub const CHPWD: &str = "chpwd";
+/// After a line is added to the history: `[entry]`
+pub const HISTORY_ADD: &str = "on_history_add";
+/// When the shell exits: `[]`
+pub const EXIT: &str = "on_exit";
+
+/// Every hook, in the order a command goes through them
+pub const HOOKS: [&str; 5] = [PREEXEC, PRECMD, CHPWD, HISTORY_ADD, EXIT];
+
+/// Results of a hook call
+pub const CONTINUE: i32 = 0;
+pub const REWRITE: i32 = 1;
+pub const VETO: i32 = 2;
+
+/// What a `preexec` hook decides about a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preexec {
+    /// Run the command as it is
+    Run,
+    /// Run this command instead
+    Rewrite(String),
+    /// Do not run the command
+    Veto,
+}
+
+/// Calls the hook named by `args[0]` with the rest as its arguments
+///
+/// # Returns
+/// * `Result<(i32, String), String>` - `CONTINUE`, `REWRITE` with the new
+///   command or `VETO`, or the hook's error
+#[doc(hidden)]
+pub fn __dispatch<T: FluxPlugin>(
+    plugin: &T,
+    context: &mut Context,
+    args: &[String],
+) -> Result<(i32, String), String> {
+    let arg = |index: usize| args.get(index).map(String::as_str).unwrap_or_default();
+    match arg(0) {
+        PREEXEC => Ok(match plugin.preexec(context, arg(1))? {
+            Preexec::Run => (CONTINUE, String::new()),
+            Preexec::Rewrite(command) => (REWRITE, command),
+            Preexec::Veto => (VETO, String::new()),
+        }),
+        PRECMD => {
+            let status: i32 = arg(2).parse().unwrap_or(0);
+            let duration: Duration = Duration::from_millis(arg(3).parse().unwrap_or(0));
+            plugin.precmd(context, arg(1), status, duration)?;
+            Ok((CONTINUE, String::new()))
+        }
+        CHPWD => {
+            plugin.chpwd(context, arg(1).as_ref(), arg(2).as_ref())?;
+            Ok((CONTINUE, String::new()))
+        }
+        HISTORY_ADD => {
+            plugin.on_history_add(context, arg(1))?;
+            Ok((CONTINUE, String::new()))
+        }
+        EXIT => {
+            plugin.on_exit(context)?;
+            Ok((CONTINUE, String::new()))
+        }
+        hook => Err(format!("unknown hook '{}'", hook)),
+    }
+}
@@ -0,0 +1,147 @@
+//! Plugin interface for the flux shell
+//!
+//! Plugins implement `FluxPlugin` and export it with `export_plugin!`. The
+//! shell talks to them through the versioned C ABI in `abi`, so a plugin
+//! built with a different compiler than the shell still loads safely.
+//!
+//! Plugins can instead be compiled to WebAssembly and exported with
+//! `export_wasm_plugin!`; the shell runs those in a sandbox, see `wasm`.
+
+/// Types shared by the shell and plugins across the C ABI
+pub mod abi;
+mod context;
+mod export;
+pub mod hooks;
+/// Interface for plugins compiled to WebAssembly
+pub mod wasm;
+
+pub use context::{Buffers, Context, Host, Input, Output, Stream, Terminal};
+pub use hooks::Preexec;
+
+use std::path::Path;
+use std::time::Duration;
+
+/// Interface every flux plugin implements
+pub trait FluxPlugin {
+    /// Get the name of the plugin
+    fn name(&self) -> &str;
+
+    /// Get the version of the plugin
+    fn version(&self) -> &str {
+        "0.1.0" // Default version
+    }
+
+    /// Get plugin description
+    fn description(&self) -> &str {
+        "No description provided"
+    }
+
+    /// Get available commands
+    fn commands(&self) -> Vec<(&str, &str)> {
+        Vec::new() // (command, description) pairs
+    }
+
+    /// Get prompt segments provided by the plugin
+    ///
+    /// Each `(name, value)` pair fills the `{name}` placeholder in prompt templates.
+    fn prompt_segments(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Initialize the plugin
+    fn init(&mut self) -> Result<(), String>;
+
+    /// Execute a plugin command
+    ///
+    /// Returns the command's exit code; an error is printed and exits with 1.
+    fn execute(&self, context: &mut Context, args: &[String]) -> Result<i32, String>;
+
+    /// Complete the last of `args`, the words typed after the plugin name
+    fn complete(&self, _args: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Called before a command runs, with the line as typed
+    ///
+    /// Return `Preexec::Rewrite` to run another command instead, or
+    /// `Preexec::Veto` to skip it. Only called if the manifest lists
+    /// `preexec` under `hooks`, as are the other hooks.
+    fn preexec(&self, _context: &mut Context, _command: &str) -> Result<Preexec, String> {
+        Ok(Preexec::Run)
+    }
+
+    /// Called after a command finishes, with its exit status and how long
+    /// it took
+    fn precmd(
+        &self,
+        _context: &mut Context,
+        _command: &str,
+        _status: i32,
+        _duration: Duration,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called after the shell's working directory changes
+    fn chpwd(&self, _context: &mut Context, _old: &Path, _new: &Path) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called after a line is added to the history
+    fn on_history_add(&self, _context: &mut Context, _entry: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called when the shell exits
+    fn on_exit(&self, _context: &mut Context) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Clean up plugin resources
+    fn cleanup(&mut self) -> Result<(), String>;
+
+    /// Handle plugin configuration
+    fn configure(&mut self) -> Result<(), String> {
+        Ok(()) // Default: no configuration needed
+    }
+
+    /// Get plugin help text
+    fn help(&self) -> String {
+        format!(
+            "Plugin: {}\nVersion: {}\n{}\n\nCommands:\n{}",
+            self.name(),
+            self.version(),
+            self.description(),
+            self.commands()
+                .iter()
+                .map(|(cmd, desc)| format!("  {} - {}", cmd, desc))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+}
+
+/// Exports a plugin type so the shell can load it
+///
+/// The type must implement `FluxPlugin` and `Default`; the shell creates one
+/// instance when it loads the library.
+///
+/// ```ignore
+/// #[derive(Default)]
+/// struct Hello;
+///
+/// impl FluxPlugin for Hello { /* ... */ }
+///
+/// flux_plugin::export_plugin!(Hello);
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:ty) => {
+        #[no_mangle]
+        pub extern "C" fn flux_plugin_descriptor() -> *const $crate::abi::PluginDescriptor {
+            static DESCRIPTOR: $crate::abi::PluginDescriptor =
+                $crate::abi::PluginDescriptor::new::<$plugin>();
+            &DESCRIPTOR
+        }
+    };
+}
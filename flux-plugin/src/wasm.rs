@@ -0,0 +1,386 @@
+//! Interface between the shell and WebAssembly plugins
+//!
+//! A WebAssembly plugin is a core module that imports its host functions from
+//! the `flux` module and exports:
+//!
+//! | Export | Signature | |
+//! | --- | --- | --- |
+//! | `memory` | | Linear memory the host passes data through |
+//! | `flux_abi_version` | `() -> i32` | Must return `WASM_ABI_VERSION` |
+//! | `flux_alloc` | `(len: i32) -> i32` | Allocates `len` bytes the next call takes ownership of |
+//! | `flux_execute` | `(ptr: i32, len: i32, context: i32, context_len: i32) -> i32` | Runs a command and returns its exit code |
+//! | `flux_init`, `flux_cleanup` | `() -> i32` | Optional |
+//! | `flux_complete` | `(ptr: i32, len: i32) -> i32` | Optional; candidates are returned with `output` |
+//! | `flux_prompt_segments` | `() -> i32` | Optional; alternating names and values are returned with `output` |
+//! | `flux_hook` | `(ptr: i32, len: i32, context: i32, context_len: i32) -> i32` | Optional; calls a hook, see `hooks`. A rewritten command is returned with `output`, and `FAILED` means the error was |
+//!
+//! Argument lists and returned lists are strings joined with NUL bytes.
+//! Calls return 0 on success; on failure the error message is the last value
+//! passed to `output`. The context passed to `flux_execute` is such a list of
+//! the working directory, whether stdin and stdout are terminals (`1` or `0`),
+//! the terminal's columns and rows, and then `NAME=value` for each variable
+//! the plugin may read.
+//!
+//! Host functions return a length or status, `FAILED` if the operation failed
+//! and `DENIED` if the plugin was not granted the capability it needs:
+//!
+//! | Import | Signature | |
+//! | --- | --- | --- |
+//! | `write` | `(stream, ptr, len)` | Writes to the command's stdout (`STDOUT`) or stderr (`STDERR`) |
+//! | `read` | `(buf, cap) -> i32` | Reads the command's stdin; 0 at the end |
+//! | `output` | `(ptr, len)` | Sets the result of the current call |
+//! | `env_var` | `(name, name_len, buf, cap) -> i32` | Length of the variable's value, copied into `buf` if it fits; `FAILED` if unset |
+//! | `read_file` | `(path, path_len, buf, cap) -> i32` | Length of the file, copied into `buf` if it fits |
+//! | `write_file` | `(path, path_len, data, data_len) -> i32` | 0 once written |
+//! | `spawn` | `(argv, argv_len) -> i32` | Runs a program and returns its exit code |
+//! | `set_env` | `(name, name_len, value, value_len) -> i32` | Sets a variable in the shell; 0 once set |
+//! | `unset_env` | `(name, name_len) -> i32` | Removes a variable from the shell; 0 once removed |
+//! | `chdir` | `(path, path_len) -> i32` | Changes the shell's directory after the command; 0 if it exists |
+
+/// Version of the WebAssembly plugin interface
+pub const WASM_ABI_VERSION: i32 = 1;
+
+/// Module the host functions are imported from
+pub const IMPORT_MODULE: &str = "flux";
+
+/// Returned by a host function that failed
+pub const FAILED: i32 = -1;
+
+/// Returned by a host function the plugin lacks the capability for
+pub const DENIED: i32 = -2;
+
+/// Stream numbers for `write`
+pub use crate::abi::{STDERR, STDOUT};
+
+#[cfg(target_arch = "wasm32")]
+pub use guest::*;
+
+#[cfg(target_arch = "wasm32")]
+mod guest {
+    use super::{DENIED, FAILED, STDERR, STDOUT};
+    use crate::{Context, Host, Stream, Terminal};
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    #[link(wasm_import_module = "flux")]
+    extern "C" {
+        fn write(stream: i32, ptr: *const u8, len: usize);
+        fn output(ptr: *const u8, len: usize);
+        fn env_var(name: *const u8, name_len: usize, buf: *mut u8, cap: usize) -> i32;
+        fn read_file(path: *const u8, path_len: usize, buf: *mut u8, cap: usize) -> i32;
+        fn write_file(path: *const u8, path_len: usize, data: *const u8, data_len: usize) -> i32;
+        fn spawn(argv: *const u8, argv_len: usize) -> i32;
+        #[link_name = "read"]
+        fn read_stdin(buf: *mut u8, cap: usize) -> i32;
+        fn set_env(name: *const u8, name_len: usize, value: *const u8, value_len: usize) -> i32;
+        fn unset_env(name: *const u8, name_len: usize) -> i32;
+        fn chdir(path: *const u8, path_len: usize) -> i32;
+    }
+
+    /// Why a host function failed
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum HostError {
+        /// The plugin was not granted the capability
+        Denied,
+        /// The operation itself failed
+        Failed,
+    }
+
+    impl fmt::Display for HostError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                HostError::Denied => write!(f, "permission denied"),
+                HostError::Failed => write!(f, "operation failed"),
+            }
+        }
+    }
+
+    fn status(code: i32) -> Result<i32, HostError> {
+        match code {
+            DENIED => Err(HostError::Denied),
+            FAILED => Err(HostError::Failed),
+            code => Ok(code),
+        }
+    }
+
+    /// Calls a host function that copies its result into a buffer, growing
+    /// the buffer until the result fits
+    fn read_into(call: impl Fn(*mut u8, usize) -> i32) -> Result<Vec<u8>, HostError> {
+        let mut buffer: Vec<u8> = vec![0; 4096];
+        loop {
+            let len: usize = status(call(buffer.as_mut_ptr(), buffer.len()))? as usize;
+            if len <= buffer.len() {
+                buffer.truncate(len);
+                return Ok(buffer);
+            }
+            buffer.resize(len, 0);
+        }
+    }
+
+    /// Writes text to the shell's stdout
+    pub fn print(text: &str) {
+        unsafe { write(STDOUT, text.as_ptr(), text.len()) }
+    }
+
+    /// Writes text to the shell's stderr
+    pub fn eprint(text: &str) {
+        unsafe { write(STDERR, text.as_ptr(), text.len()) }
+    }
+
+    /// Reads an environment variable; needs the `environment` capability
+    pub fn var(name: &str) -> Result<Option<String>, HostError> {
+        match read_into(|buf, cap| unsafe { env_var(name.as_ptr(), name.len(), buf, cap) }) {
+            Ok(value) => Ok(Some(String::from_utf8_lossy(&value).into_owned())),
+            Err(HostError::Failed) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads a file; needs the `filesystem` capability for its path
+    pub fn read(path: &str) -> Result<Vec<u8>, HostError> {
+        read_into(|buf, cap| unsafe { read_file(path.as_ptr(), path.len(), buf, cap) })
+    }
+
+    /// Writes a file; needs the `filesystem` capability for its path
+    pub fn write_all(path: &str, data: &[u8]) -> Result<(), HostError> {
+        status(unsafe { write_file(path.as_ptr(), path.len(), data.as_ptr(), data.len()) })
+            .map(|_| ())
+    }
+
+    /// Runs a program with the terminal attached and returns its exit code;
+    /// needs the `process` capability for `argv[0]`
+    pub fn run(argv: &[&str]) -> Result<i32, HostError> {
+        let joined: String = argv.join("\0");
+        status(unsafe { spawn(joined.as_ptr(), joined.len()) })
+    }
+
+    /// Reaches the shell through the host functions
+    #[doc(hidden)]
+    pub struct __Host;
+
+    impl Host for __Host {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            status(unsafe { read_stdin(buf.as_mut_ptr(), buf.len()) })
+                .map(|len| len as usize)
+                .map_err(|_| io::Error::other("failed to read stdin"))
+        }
+
+        fn write(&mut self, stream: Stream, data: &[u8]) -> io::Result<usize> {
+            let stream: i32 = match stream {
+                Stream::Stdout => STDOUT,
+                Stream::Stderr => STDERR,
+            };
+            unsafe { write(stream, data.as_ptr(), data.len()) };
+            Ok(data.len())
+        }
+
+        fn set_var(&mut self, name: &str, value: Option<&str>) {
+            // A denied change is reported by the shell
+            let _ = match value {
+                Some(value) => unsafe {
+                    set_env(name.as_ptr(), name.len(), value.as_ptr(), value.len())
+                },
+                None => unsafe { unset_env(name.as_ptr(), name.len()) },
+            };
+        }
+
+        fn set_current_dir(&mut self, path: &Path) -> Result<(), String> {
+            let path_text: String = path.to_string_lossy().into_owned();
+            status(unsafe { chdir(path_text.as_ptr(), path_text.len()) })
+                .map(|_| ())
+                .map_err(|e| match e {
+                    HostError::Denied => format!("{}: {}", path.display(), e),
+                    HostError::Failed => format!("{}: not a directory", path.display()),
+                })
+        }
+    }
+
+    /// Builds a context from the list passed to `flux_execute`
+    #[doc(hidden)]
+    pub fn __context(fields: Vec<String>, host: &mut __Host) -> Context<'_> {
+        let mut fields = fields.into_iter();
+        let mut next = || fields.next().unwrap_or_default();
+        let cwd: PathBuf = PathBuf::from(next());
+        let terminal: Terminal = Terminal {
+            stdin_tty: next() == "1",
+            stdout_tty: next() == "1",
+            columns: next().parse().unwrap_or(0),
+            rows: next().parse().unwrap_or(0),
+        };
+        let env: BTreeMap<String, String> = fields
+            .filter_map(|entry| {
+                let (name, value) = entry.split_once('=')?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        Context::new(cwd, env, terminal, host)
+    }
+
+    #[doc(hidden)]
+    pub fn __alloc(len: usize) -> *mut u8 {
+        Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8
+    }
+
+    /// Takes the list the host wrote into memory from `__alloc`
+    #[doc(hidden)]
+    pub unsafe fn __args(ptr: *mut u8, len: usize) -> Vec<String> {
+        let bytes: Box<[u8]> = Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len));
+        if bytes.is_empty() {
+            return Vec::new();
+        }
+        String::from_utf8_lossy(&bytes)
+            .split('\0')
+            .map(String::from)
+            .collect()
+    }
+
+    #[doc(hidden)]
+    pub fn __output(items: &[String]) -> i32 {
+        let joined: String = items.join("\0");
+        unsafe { output(joined.as_ptr(), joined.len()) };
+        0
+    }
+
+    #[doc(hidden)]
+    pub fn __status(result: Result<i32, String>) -> i32 {
+        match result {
+            Ok(code) => code,
+            Err(e) => {
+                unsafe { output(e.as_ptr(), e.len()) };
+                1
+            }
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn __hook(result: Result<(i32, String), String>) -> i32 {
+        match result {
+            Ok((action, text)) => {
+                unsafe { output(text.as_ptr(), text.len()) };
+                action
+            }
+            Err(e) => {
+                unsafe { output(e.as_ptr(), e.len()) };
+                FAILED
+            }
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn __result(result: Result<(), String>) -> i32 {
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                unsafe { output(e.as_ptr(), e.len()) };
+                1
+            }
+        }
+    }
+}
+
+/// Exports a plugin type from a WebAssembly module
+///
+/// The counterpart of `export_plugin!` for plugins built for
+/// `wasm32-unknown-unknown`. Use `flux_plugin::wasm::print` instead of
+/// `println!`, which has nowhere to write in WebAssembly.
+#[macro_export]
+macro_rules! export_wasm_plugin {
+    ($plugin:ty) => {
+        const _: () = {
+            ::std::thread_local! {
+                static PLUGIN: ::std::cell::RefCell<$plugin> =
+                    ::std::cell::RefCell::new(<$plugin as ::std::default::Default>::default());
+            }
+
+            #[no_mangle]
+            pub extern "C" fn flux_abi_version() -> i32 {
+                $crate::wasm::WASM_ABI_VERSION
+            }
+
+            #[no_mangle]
+            pub extern "C" fn flux_alloc(len: usize) -> *mut u8 {
+                $crate::wasm::__alloc(len)
+            }
+
+            #[no_mangle]
+            pub extern "C" fn flux_init() -> i32 {
+                PLUGIN.with(|plugin| {
+                    $crate::wasm::__result($crate::FluxPlugin::init(&mut *plugin.borrow_mut()))
+                })
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn flux_execute(
+                ptr: *mut u8,
+                len: usize,
+                context_ptr: *mut u8,
+                context_len: usize,
+            ) -> i32 {
+                let args: Vec<String> = $crate::wasm::__args(ptr, len);
+                let mut host: $crate::wasm::__Host = $crate::wasm::__Host;
+                let mut context: $crate::Context = $crate::wasm::__context(
+                    $crate::wasm::__args(context_ptr, context_len),
+                    &mut host,
+                );
+                PLUGIN.with(|plugin| {
+                    $crate::wasm::__status($crate::FluxPlugin::execute(
+                        &*plugin.borrow(),
+                        &mut context,
+                        &args,
+                    ))
+                })
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn flux_hook(
+                ptr: *mut u8,
+                len: usize,
+                context_ptr: *mut u8,
+                context_len: usize,
+            ) -> i32 {
+                let args: Vec<String> = $crate::wasm::__args(ptr, len);
+                let mut host: $crate::wasm::__Host = $crate::wasm::__Host;
+                let mut context: $crate::Context = $crate::wasm::__context(
+                    $crate::wasm::__args(context_ptr, context_len),
+                    &mut host,
+                );
+                PLUGIN.with(|plugin| {
+                    $crate::wasm::__hook($crate::hooks::__dispatch(
+                        &*plugin.borrow(),
+                        &mut context,
+                        &args,
+                    ))
+                })
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn flux_complete(ptr: *mut u8, len: usize) -> i32 {
+                let args: Vec<String> = $crate::wasm::__args(ptr, len);
+                PLUGIN.with(|plugin| {
+                    $crate::wasm::__output(&$crate::FluxPlugin::complete(&*plugin.borrow(), &args))
+                })
+            }
+
+            #[no_mangle]
+            pub extern "C" fn flux_prompt_segments() -> i32 {
+                PLUGIN.with(|plugin| {
+                    let segments: Vec<String> =
+                        $crate::FluxPlugin::prompt_segments(&*plugin.borrow())
+                            .into_iter()
+                            .flat_map(|(name, value)| [name, value])
+                            .collect();
+                    $crate::wasm::__output(&segments)
+                })
+            }
+
+            #[no_mangle]
+            pub extern "C" fn flux_cleanup() -> i32 {
+                PLUGIN.with(|plugin| {
+                    $crate::wasm::__result($crate::FluxPlugin::cleanup(&mut *plugin.borrow_mut()))
+                })
+            }
+        };
+    };
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// An abbreviation from `abbreviations`, expanded in the input line
+///
+/// Written either as the plain expansion, which expands in command position,
+/// or as a table with an explicit `position`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "AbbreviationSpec", into = "AbbreviationSpec")]
+pub struct Abbreviation {
+    /// Text the abbreviation is replaced with
+    pub expansion: String,
+    /// Where it expands: `command` (the first word of a command) or `anywhere`
+    pub position: String,
+}
+
+/// How an abbreviation is written in the configuration
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum AbbreviationSpec {
+    Expansion(String),
+    Table {
+        expansion: String,
+        #[serde(default = "default_position")]
+        position: String,
+    },
+}
+
+fn default_position() -> String {
+    "command".to_string()
+}
+
+impl From<AbbreviationSpec> for Abbreviation {
+    fn from(spec: AbbreviationSpec) -> Self {
+        match spec {
+            AbbreviationSpec::Expansion(expansion) => Abbreviation {
+                expansion,
+                position: default_position(),
+            },
+            AbbreviationSpec::Table {
+                expansion,
+                position,
+            } => Abbreviation {
+                expansion,
+                position,
+            },
+        }
+    }
+}
+
+impl From<Abbreviation> for AbbreviationSpec {
+    fn from(abbreviation: Abbreviation) -> Self {
+        if abbreviation.position == default_position() {
+            AbbreviationSpec::Expansion(abbreviation.expansion)
+        } else {
+            AbbreviationSpec::Table {
+                expansion: abbreviation.expansion,
+                position: abbreviation.position,
+            }
+        }
+    }
+}
+
+impl Abbreviation {
+    /// Creates an abbreviation
+    ///
+    /// # Arguments
+    /// * `expansion` - Text the abbreviation is replaced with
+    /// * `anywhere` - Whether it expands in any position, not only as a command
+    pub fn new(expansion: String, anywhere: bool) -> Self {
+        Abbreviation {
+            expansion,
+            position: if anywhere { "anywhere" } else { "command" }.to_string(),
+        }
+    }
+}
+
+/// Finds the abbreviation that ends at the cursor
+///
+/// The word before the cursor expands if it is an abbreviation and, unless the
+/// abbreviation expands anywhere, it is in command position: the first word
+/// of the line or the first after `|`, `&&` or `||`.
+///
+/// # Arguments
+/// * `abbreviations` - Abbreviations from the configuration
+/// * `line` - Current input line
+/// * `pos` - Cursor position in the line
+///
+/// # Returns
+/// * `Option<(usize, &str)>` - Start of the word and its expansion, or None
+///   if nothing expands
+pub fn expand_at<'a>(
+    abbreviations: &'a HashMap<String, Abbreviation>,
+    line: &str,
+    pos: usize,
+) -> Option<(usize, &'a str)> {
+    if line[pos..]
+        .chars()
+        .next()
+        .is_some_and(|c| !c.is_whitespace())
+    {
+        return None;
+    }
+    let start: usize = line[..pos]
+        .char_indices()
+        .rfind(|(_, c)| c.is_whitespace())
+        .map_or(0, |(index, c)| index + c.len_utf8());
+    let abbreviation: &Abbreviation = abbreviations.get(&line[start..pos])?;
+
+    let before: &str = line[..start].trim_end();
+    let command_position: bool =
+        before.is_empty() || before.ends_with('|') || before.ends_with("&&");
+    if abbreviation.position == "command" && !command_position {
+        return None;
+    }
+    Some((start, &abbreviation.expansion))
+}
+
+/// Expands the abbreviation at the end of a submitted line
+///
+/// # Arguments
+/// * `abbreviations` - Abbreviations from the configuration
+/// * `line` - Line as submitted
+///
+/// # Returns
+/// * `Option<String>` - The expanded line, or None if nothing expands
+pub fn expand_line(abbreviations: &HashMap<String, Abbreviation>, line: &str) -> Option<String> {
+    let (start, expansion) = expand_at(abbreviations, line, line.len())?;
+    Some(format!("{}{}", &line[..start], expansion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abbreviations() -> HashMap<String, Abbreviation> {
+        HashMap::from([
+            (
+                "gst".to_string(),
+                Abbreviation::new("git status".to_string(), false),
+            ),
+            (
+                "L".to_string(),
+                Abbreviation::new("| less".to_string(), true),
+            ),
+        ])
+    }
+
+    #[test]
+    fn expands_in_command_position() {
+        let abbreviations: HashMap<String, Abbreviation> = abbreviations();
+        assert_eq!(expand_at(&abbreviations, "gst", 3), Some((0, "git status")));
+        assert_eq!(
+            expand_at(&abbreviations, "  gst", 5),
+            Some((2, "git status"))
+        );
+        for line in ["ls | gst", "make && gst", "make || gst"] {
+            assert_eq!(
+                expand_at(&abbreviations, line, line.len()),
+                Some((line.len() - 3, "git status")),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_command_abbreviations_used_as_arguments() {
+        let abbreviations: HashMap<String, Abbreviation> = abbreviations();
+        assert_eq!(expand_at(&abbreviations, "echo gst", 8), None);
+        assert_eq!(expand_at(&abbreviations, "make & gst", 10), None);
+        assert_eq!(expand_line(&abbreviations, "echo gst"), None);
+    }
+
+    #[test]
+    fn expands_anywhere_abbreviations_after_arguments() {
+        let abbreviations: HashMap<String, Abbreviation> = abbreviations();
+        assert_eq!(
+            expand_line(&abbreviations, "cat log L"),
+            Some("cat log | less".to_string())
+        );
+    }
+
+    #[test]
+    fn expands_only_whole_words_before_the_cursor() {
+        let abbreviations: HashMap<String, Abbreviation> = abbreviations();
+        assert_eq!(expand_at(&abbreviations, "gstx", 3), None);
+        assert_eq!(expand_at(&abbreviations, "xgst", 4), None);
+        assert_eq!(
+            expand_at(&abbreviations, "gst log", 3),
+            Some((0, "git status"))
+        );
+        assert_eq!(
+            expand_line(&abbreviations, "ls && gst"),
+            Some("ls && git status".to_string())
+        );
+        assert_eq!(expand_line(&abbreviations, "gst "), None);
+    }
+}
@@ -1,29 +1,45 @@
-use super::Theme;
+use super::schema::{self, CONFIG_VERSION};
+use super::{source, Abbreviation, KeyBinding, LoadedConfig, PromptSegment, Theme};
+use crate::plugin::Capabilities;
+use colored::*;
 use dialoguer::{Confirm, Select};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use std::path::Path;
 
 /// Configuration structure for the Flux shell
 ///
 /// This struct contains all customizable settings for the shell's behavior,
 /// including visual elements, aliases, and environment variables.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FluxConfig {
+    /// Schema version of the file; older versions are migrated on load
+    #[serde(default = "default_version")]
+    pub version: u32,
     /// The basic prompt string shown to users
+    #[serde(default = "default_prompt")]
     pub prompt: String,
     /// Visual theme settings for the shell
+    #[serde(default)]
     pub theme: Theme,
     /// Command aliases mapping short names to full commands
+    #[serde(default)]
     pub aliases: HashMap<String, String>,
+    /// Abbreviations expanded in the input line on space or enter, so the
+    /// history shows the full command
+    #[serde(default)]
+    pub abbreviations: HashMap<String, Abbreviation>,
     /// Maximum number of commands to keep in history
+    #[serde(default = "default_history_size")]
     pub history_size: usize,
     /// Whether to show command execution time
+    #[serde(default)]
     pub show_execution_time: bool,
     /// Directory aliases mapping shortcuts to full paths
+    #[serde(default = "FluxConfig::default_path_aliases")]
     pub path_aliases: HashMap<String, String>,
     /// Environment variables to set on shell startup
+    #[serde(default)]
     pub environment_variables: HashMap<String, String>,
     /// Template for formatting the prompt with placeholders
     ///
@@ -32,26 +48,207 @@ pub struct FluxConfig {
     /// - {user}: Username
     /// - {host}: Hostname
     /// - {dir}: Current directory
-    /// - {git}: Git branch (if in repo)
+    /// - {git}: Git branch, or tag/short SHA on a detached HEAD (if in repo)
+    /// - {git_status}: Git status summary with a leading space (if in repo)
+    /// - {git_staged}, {git_unstaged}, {git_untracked}: File counts
+    /// - {git_ahead}, {git_behind}: Commits ahead/behind upstream
+    /// - {git_stash}: Stash count
+    /// - {git_state}: In-progress rebase, merge, cherry-pick, revert or bisect
+    /// - {mode}: Vi mode indicator (empty in emacs mode)
+    #[serde(default = "default_prompt_template")]
     pub prompt_template: String,
+    /// How the main prompt is drawn: `template` fills in `prompt_template`,
+    /// `powerline` draws `powerline_segments` as filled blocks
+    #[serde(default = "default_prompt_mode")]
+    pub prompt_mode: String,
+    /// Segments drawn in `powerline` mode, in order
+    ///
+    /// Built-in segments are `user`, `host`, `dir`, `git` and `time`; any
+    /// other name is a custom or plugin segment. Empty segments are skipped.
+    #[serde(default = "default_powerline_segments")]
+    pub powerline_segments: Vec<String>,
+    /// Separator between powerline segments: `auto`, `powerline` (the
+    /// U+E0B0 glyph of patched fonts) or `ascii`
+    #[serde(default = "default_powerline_separator")]
+    pub powerline_separator: String,
+    /// Template drawn after the powerline segments, such as `"\nλ "`
+    #[serde(default = "default_powerline_suffix")]
+    pub powerline_suffix: String,
+    /// Template for a prompt drawn flush-right on the input line
+    ///
+    /// Uses the same placeholders as `prompt_template`; empty to disable.
+    #[serde(default)]
+    pub right_prompt_template: String,
+    /// Whether to collapse submitted prompts in scrollback to
+    /// `transient_prompt_template`
+    #[serde(default)]
+    pub transient_prompt: bool,
+    /// Compact prompt template shown in scrollback for submitted commands
+    #[serde(default = "default_transient_prompt_template")]
+    pub transient_prompt_template: String,
+    /// User-defined prompt segments, shown through their `{name}` placeholder
+    #[serde(default)]
+    pub prompt_segments: HashMap<String, PromptSegment>,
+    /// Maximum number of directory components shown in `{dir}` (0 for no limit)
+    #[serde(default)]
+    pub dir_max_depth: usize,
+    /// Whether to abbreviate parent directories fish-style (`~/p/f/src`)
+    #[serde(default)]
+    pub dir_abbreviate: bool,
+    /// Whether to show `{dir}` relative to the git repository root
+    #[serde(default)]
+    pub dir_repo_relative: bool,
+    /// Whether to show `{dir}` through `path_aliases` (`@dl/x`)
+    #[serde(default)]
+    pub dir_use_path_aliases: bool,
     /// Whether to show git branch in prompt
+    #[serde(default = "default_true")]
     pub show_git_branch: bool,
     /// Whether to show time in prompt
+    #[serde(default = "default_true")]
     pub show_time: bool,
     /// Whether to show username in prompt
+    #[serde(default = "default_true")]
     pub show_username: bool,
     /// Whether to show hostname in prompt
+    #[serde(default = "default_true")]
     pub show_hostname: bool,
     /// Format string for displaying time
+    #[serde(default = "default_time_format")]
     pub time_format: String,
+    /// Milliseconds to wait for slow prompt segments (git) before drawing the
+    /// prompt; late results redraw the prompt in place
+    #[serde(default = "default_prompt_wait_ms")]
+    pub prompt_wait_ms: u64,
+    /// Milliseconds a slow prompt segment may run before it is abandoned
+    #[serde(default = "default_prompt_timeout_ms")]
+    pub prompt_timeout_ms: u64,
+    /// Whether to reload the configuration automatically when its files change
+    #[serde(default)]
+    pub watch_config: bool,
+    /// Line editing keys: `emacs` or `vi`
+    #[serde(default = "default_edit_mode")]
+    pub edit_mode: String,
+    /// Text shown by `{mode}` in vi insert mode
+    #[serde(default = "default_vi_insert_indicator")]
+    pub vi_insert_indicator: String,
+    /// Text shown by `{mode}` in vi normal (command) mode
+    #[serde(default = "default_vi_normal_indicator")]
+    pub vi_normal_indicator: String,
+    /// Text shown by `{mode}` in vi replace mode
+    #[serde(default = "default_vi_replace_indicator")]
+    pub vi_replace_indicator: String,
+    /// How Tab completes: `list` shows every match, `circular` cycles through
+    /// them in place, `menu` cycles with the matches listed below the input
+    #[serde(default = "default_completion_style")]
+    pub completion_style: String,
+    /// Key chords (`ctrl-g`, `alt-l`, `ctrl-x ctrl-e`) and what they do
+    #[serde(default)]
+    pub keybindings: HashMap<String, KeyBinding>,
+    /// Capabilities granted to each WebAssembly plugin, by plugin name
+    #[serde(default)]
+    pub plugin_permissions: HashMap<String, Capabilities>,
+    /// Path or URL of the index `flux plugin install <name>` looks plugins
+    /// up in
+    #[serde(default)]
+    pub plugin_registry: Option<String>,
+    /// Refuse to install or load plugins not signed with a key from the
+    /// trusted-keys file
+    #[serde(default)]
+    pub require_signed_plugins: bool,
+}
+
+fn default_version() -> u32 {
+    CONFIG_VERSION
+}
+
+fn default_prompt() -> String {
+    "λ ".to_string()
+}
+
+fn default_history_size() -> usize {
+    1000
+}
+
+fn default_prompt_template() -> String {
+    "{dir} λ ".to_string()
+}
+
+fn default_prompt_mode() -> String {
+    "template".to_string()
+}
+
+fn default_powerline_segments() -> Vec<String> {
+    ["user", "dir", "git"].map(String::from).to_vec()
+}
+
+fn default_powerline_separator() -> String {
+    "auto".to_string()
+}
+
+fn default_powerline_suffix() -> String {
+    " ".to_string()
+}
+
+fn default_edit_mode() -> String {
+    "emacs".to_string()
+}
+
+fn default_vi_insert_indicator() -> String {
+    "[I]".to_string()
+}
+
+fn default_vi_normal_indicator() -> String {
+    "[N]".to_string()
+}
+
+fn default_vi_replace_indicator() -> String {
+    "[R]".to_string()
+}
+
+fn default_completion_style() -> String {
+    "list".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_time_format() -> String {
+    "%H:%M:%S".to_string()
+}
+
+fn default_transient_prompt_template() -> String {
+    "λ ".to_string()
+}
+
+fn default_prompt_wait_ms() -> u64 {
+    50
+}
+
+fn default_prompt_timeout_ms() -> u64 {
+    2000
 }
 
 impl FluxConfig {
     /// Creates a minimal configuration with basic features
     pub fn minimal() -> Self {
         FluxConfig {
+            version: CONFIG_VERSION,
             prompt: "λ ".to_string(),
             prompt_template: "{dir} λ ".to_string(),
+            prompt_mode: default_prompt_mode(),
+            powerline_segments: default_powerline_segments(),
+            powerline_separator: default_powerline_separator(),
+            powerline_suffix: default_powerline_suffix(),
+            right_prompt_template: String::new(),
+            transient_prompt: false,
+            transient_prompt_template: default_transient_prompt_template(),
+            prompt_segments: HashMap::new(),
+            dir_max_depth: 0,
+            dir_abbreviate: false,
+            dir_repo_relative: false,
+            dir_use_path_aliases: false,
             show_git_branch: false,
             show_time: false,
             show_username: false,
@@ -59,10 +256,23 @@ impl FluxConfig {
             time_format: "%H:%M:%S".to_string(),
             theme: Theme::minimal(),
             aliases: HashMap::new(),
+            abbreviations: HashMap::new(),
             path_aliases: Self::default_path_aliases(),
             environment_variables: HashMap::new(),
             show_execution_time: false,
             history_size: 1000,
+            prompt_wait_ms: default_prompt_wait_ms(),
+            prompt_timeout_ms: default_prompt_timeout_ms(),
+            watch_config: false,
+            edit_mode: default_edit_mode(),
+            vi_insert_indicator: default_vi_insert_indicator(),
+            vi_normal_indicator: default_vi_normal_indicator(),
+            vi_replace_indicator: default_vi_replace_indicator(),
+            completion_style: default_completion_style(),
+            keybindings: HashMap::new(),
+            plugin_permissions: HashMap::new(),
+            plugin_registry: None,
+            require_signed_plugins: false,
         }
     }
 
@@ -130,49 +340,106 @@ impl FluxConfig {
         env_vars.insert("VISUAL".to_string(), "code".to_string());
 
         FluxConfig {
+            version: CONFIG_VERSION,
             prompt: "flux λ ".to_string(),
             theme: Theme::full(),
             aliases,
+            abbreviations: HashMap::new(),
             history_size: 10000,
             show_execution_time: true,
             path_aliases,
             environment_variables: env_vars,
-            prompt_template: "[{time}] {user}@{host} in {dir} on {git}\nλ ".to_string(),
+            prompt_template: "[{time}] {user}@{host} in {dir} on {git}{git_status}\nλ ".to_string(),
+            prompt_mode: default_prompt_mode(),
+            powerline_segments: default_powerline_segments(),
+            powerline_separator: default_powerline_separator(),
+            powerline_suffix: default_powerline_suffix(),
+            right_prompt_template: String::new(),
+            transient_prompt: false,
+            transient_prompt_template: default_transient_prompt_template(),
+            prompt_segments: HashMap::new(),
+            dir_max_depth: 0,
+            dir_abbreviate: false,
+            dir_repo_relative: false,
+            dir_use_path_aliases: true,
             show_git_branch: true,
             show_time: true,
             show_username: true,
             show_hostname: true,
             time_format: "%H:%M:%S".to_string(),
+            prompt_wait_ms: default_prompt_wait_ms(),
+            prompt_timeout_ms: default_prompt_timeout_ms(),
+            watch_config: false,
+            edit_mode: default_edit_mode(),
+            vi_insert_indicator: default_vi_insert_indicator(),
+            vi_normal_indicator: default_vi_normal_indicator(),
+            vi_replace_indicator: default_vi_replace_indicator(),
+            completion_style: default_completion_style(),
+            keybindings: HashMap::new(),
+            plugin_permissions: HashMap::new(),
+            plugin_registry: None,
+            require_signed_plugins: false,
         }
     }
 
     /// Creates a powerline-styled configuration with fancy prompts
     pub fn powerline() -> Self {
         let mut config: FluxConfig = Self::full();
-        config.prompt_template = "╭─[{time}] {user}@{host} in {dir} on {git}\n╰─λ ".to_string();
+        config.prompt_template =
+            "╭─[{time}] {user}@{host} in {dir} on {git}{git_status}\n╰─λ ".to_string();
+        config.prompt_mode = "powerline".to_string();
+        config.powerline_segments = ["time", "user", "host", "dir", "git"]
+            .map(String::from)
+            .to_vec();
+        config.powerline_suffix = "\nλ ".to_string();
         config.theme = Theme::powerline();
         config
     }
 
+    /// Creates a preset configuration by name
+    ///
+    /// # Arguments
+    /// * `name` - One of `minimal`, `full` or `powerline`
+    ///
+    /// # Returns
+    /// * `Option<FluxConfig>` - The preset, or None for an unknown name
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "minimal" => Some(Self::minimal()),
+            "full" => Some(Self::full()),
+            "powerline" => Some(Self::powerline()),
+            _ => None,
+        }
+    }
+
     /// Loads or creates configuration from the specified path
     ///
-    /// If the configuration file exists, it will be loaded.
+    /// If the configuration file exists, it is parsed, migrated to the current
+    /// schema version and validated; problems are reported with their line and
+    /// column. A file that fails to parse is left untouched and the built-in
+    /// defaults are used for this session.
     /// Otherwise, an interactive setup will guide the user through creating one.
     ///
     /// # Arguments
     /// * `config_path` - Path to the configuration file
-    pub fn load(config_path: &PathBuf) -> Self {
-        if let Ok(contents) = fs::read_to_string(config_path) {
-            if let Ok(config) = serde_json::from_str(&contents) {
-                return config;
-            }
-
-            // Try to read config type from first line
-            let first_line: Option<&str> = contents.lines().next();
-            match first_line {
-                Some("#minimal") => return FluxConfig::minimal(),
-                Some("#powerline") => return FluxConfig::powerline(),
-                _ => return FluxConfig::full(),
+    pub fn load(config_path: &Path) -> Self {
+        if config_path.exists() {
+            match Self::check(config_path) {
+                Ok(LoadedConfig {
+                    config, warnings, ..
+                }) => {
+                    for warning in &warnings {
+                        eprintln!("{} {}", "warning:".yellow(), warning);
+                    }
+                    return config;
+                }
+                Err(e) => {
+                    eprintln!("{} {}", "error:".red(), e);
+                    eprintln!(
+                        "Using the default configuration for this session; run `flux config check` after fixing the file."
+                    );
+                    return FluxConfig::full();
+                }
             }
         }
 
@@ -213,35 +480,41 @@ impl FluxConfig {
         config.show_git_branch = show_git;
         config.show_time = show_time;
 
-        // Update prompt template based on choices
-        let mut template_parts: Vec<&str> = Vec::new();
-
-        if config.show_time {
-            template_parts.push("[{time}]");
-        }
-        if config.show_username {
-            template_parts.push("{user}@{host}");
-        }
-        template_parts.push("in {dir}");
-        if config.show_git_branch {
-            template_parts.push("on {git}");
-        }
+        // Update the prompt based on choices; powerline mode draws its
+        // segments rather than the template
+        if config.prompt_mode == "powerline" {
+            let mut segments: Vec<&str> = Vec::new();
+            if config.show_time {
+                segments.push("time");
+            }
+            if config.show_username {
+                segments.extend(["user", "host"]);
+            }
+            segments.push("dir");
+            if config.show_git_branch {
+                segments.push("git");
+            }
+            config.powerline_segments = segments.into_iter().map(String::from).collect();
+        } else {
+            let mut template_parts: Vec<&str> = Vec::new();
 
-        match selection {
-            2 => {
-                // Powerline
-                config.prompt_template = format!("╭─{}\n╰─λ ", template_parts.join(" "));
+            if config.show_time {
+                template_parts.push("[{time}]");
             }
-            _ => {
-                config.prompt_template = format!("{}\nλ ", template_parts.join(" "));
+            if config.show_username {
+                template_parts.push("{user}@{host}");
             }
+            template_parts.push("in {dir}");
+            if config.show_git_branch {
+                template_parts.push("on {git}{git_status}");
+            }
+
+            config.prompt_template = format!("{}\nλ ", template_parts.join(" "));
         }
 
         // Save the config
-        if let Ok(config_str) = serde_json::to_string_pretty(&config) {
-            if let Err(e) = fs::write(config_path, config_str) {
-                eprintln!("Failed to write config: {}", e);
-            }
+        if let Err(e) = config.save(config_path) {
+            eprintln!("Failed to write config: {}", e);
         }
 
         println!(
@@ -252,6 +525,18 @@ impl FluxConfig {
 
         config
     }
+
+    /// Parses and validates a configuration file without side effects
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    ///
+    /// # Returns
+    /// * `Result<LoadedConfig, String>` - The configuration with any warnings,
+    ///   or the error that stopped it from loading
+    pub fn check(config_path: &Path) -> Result<LoadedConfig, String> {
+        schema::parse(source::resolve(config_path)?)
+    }
 }
 
 impl Default for FluxConfig {
@@ -0,0 +1,207 @@
+use rustyline::{Cmd, KeyCode, KeyEvent, Modifiers, Movement, Word};
+use serde::{Deserialize, Serialize};
+
+/// What a key chord in `keybindings` does
+///
+/// Exactly one of `action`, `run` and `insert` should be set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBinding {
+    /// Line editor action, such as `beginning-of-line` (see `editor_action`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// Flux command to run; the current input is restored afterwards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+    /// Shell command whose trimmed output is inserted at the cursor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert: Option<String>,
+}
+
+/// Converts an action name to the line editor command
+///
+/// # Arguments
+/// * `name` - Readline-style action name
+///
+/// # Returns
+/// * `Option<Cmd>` - The command, or None for an unknown name
+pub fn editor_action(name: &str) -> Option<Cmd> {
+    let cmd: Cmd = match name {
+        "accept-line" => Cmd::AcceptLine,
+        "backward-char" => Cmd::Move(Movement::BackwardChar(1)),
+        "backward-kill-line" => Cmd::Kill(Movement::BeginningOfLine),
+        "backward-kill-word" => Cmd::Kill(Movement::BackwardWord(1, Word::Emacs)),
+        "backward-word" => Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+        "beginning-of-history" => Cmd::BeginningOfHistory,
+        "beginning-of-line" => Cmd::Move(Movement::BeginningOfLine),
+        "capitalize-word" => Cmd::CapitalizeWord,
+        "clear-screen" => Cmd::ClearScreen,
+        "complete" => Cmd::Complete,
+        "delete-char" => Cmd::Kill(Movement::ForwardChar(1)),
+        "downcase-word" => Cmd::DowncaseWord,
+        "end-of-history" => Cmd::EndOfHistory,
+        "end-of-line" => Cmd::Move(Movement::EndOfLine),
+        "forward-char" => Cmd::Move(Movement::ForwardChar(1)),
+        "forward-word" => Cmd::Move(Movement::ForwardWord(
+            1,
+            rustyline::At::AfterEnd,
+            Word::Emacs,
+        )),
+        "history-search-backward" => Cmd::HistorySearchBackward,
+        "history-search-forward" => Cmd::HistorySearchForward,
+        "insert-newline" => Cmd::Newline,
+        "kill-line" => Cmd::Kill(Movement::EndOfLine),
+        "kill-word" => Cmd::Kill(Movement::ForwardWord(
+            1,
+            rustyline::At::AfterEnd,
+            Word::Emacs,
+        )),
+        "next-history" => Cmd::NextHistory,
+        "previous-history" => Cmd::PreviousHistory,
+        "reverse-search-history" => Cmd::ReverseSearchHistory,
+        "transpose-chars" => Cmd::TransposeChars,
+        "undo" => Cmd::Undo(1),
+        "upcase-word" => Cmd::UpcaseWord,
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+/// Parses a key chord such as `ctrl-g`, `alt-l` or `ctrl-x ctrl-e`
+///
+/// Modifiers (`ctrl`, `alt`/`meta`, `shift`) are joined to the key with `-`
+/// or `+`. Keys are single characters or names: `enter`, `tab`, `esc`,
+/// `space`, `backspace`, `delete`, `insert`, `up`, `down`, `left`, `right`,
+/// `home`, `end`, `pageup`, `pagedown` and `f1` to `f12`. Space-separated
+/// chords form a sequence.
+///
+/// # Arguments
+/// * `chord` - Chord as written in the configuration
+///
+/// # Returns
+/// * `Result<Vec<KeyEvent>, String>` - The key sequence, or why the chord is
+///   invalid
+pub fn parse_chord(chord: &str) -> Result<Vec<KeyEvent>, String> {
+    let keys: Vec<KeyEvent> = chord
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<_, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key chord".to_string());
+    }
+    Ok(keys)
+}
+
+/// Parses a single key with its modifiers
+fn parse_key(text: &str) -> Result<KeyEvent, String> {
+    let lower: String = text.to_lowercase();
+    let mut parts: Vec<&str> = lower.split(['-', '+']).collect();
+    // `ctrl--` and `alt-+` bind the separator characters themselves; a
+    // separator after a key, as in `ctrl-a-`, is a mistake
+    if parts.len() > 1 && parts.last() == Some(&"") {
+        parts.pop();
+        let separator: &str = &lower[lower.len() - 1..];
+        match parts.last().copied() {
+            Some("") => *parts.last_mut().expect("at least one part") = separator,
+            Some(before) if parse_modifier(before).is_some() => parts.push(separator),
+            _ => return Err(format!("unknown key '{}'", text)),
+        }
+    }
+    let key: &str = parts.pop().unwrap_or_default();
+
+    let mut modifiers: Modifiers = Modifiers::NONE;
+    for modifier in parts {
+        modifiers |= parse_modifier(modifier)
+            .ok_or_else(|| format!("unknown modifier '{}' in '{}'", modifier, text))?;
+    }
+
+    let code: KeyCode = match key {
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        f if f.len() > 1 && f.starts_with('f') => match f[1..].parse::<u8>() {
+            Ok(n @ 1..=12) => KeyCode::F(n),
+            _ => return Err(format!("unknown key '{}'", text)),
+        },
+        single if single.chars().count() == 1 => {
+            // Keep the case the user wrote for unmodified letters
+            let original: char = text.chars().last().unwrap_or_default();
+            let c: char = if modifiers.is_empty() {
+                original
+            } else {
+                single.chars().next().unwrap_or_default()
+            };
+            return Ok(KeyEvent::new(c, modifiers));
+        }
+        _ => return Err(format!("unknown key '{}'", text)),
+    };
+    Ok(KeyEvent(code, modifiers))
+}
+
+/// Parses a modifier name, lowercase
+fn parse_modifier(name: &str) -> Option<Modifiers> {
+    match name {
+        "ctrl" | "c" => Some(Modifiers::CTRL),
+        "alt" | "meta" | "m" => Some(Modifiers::ALT),
+        "shift" | "s" => Some(Modifiers::SHIFT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_separator_keys() {
+        assert_eq!(parse_key("ctrl--"), Ok(KeyEvent::new('-', Modifiers::CTRL)));
+        assert_eq!(parse_key("alt-+"), Ok(KeyEvent::new('+', Modifiers::ALT)));
+        assert_eq!(parse_key("ctrl+-"), Ok(KeyEvent::new('-', Modifiers::CTRL)));
+        assert_eq!(parse_key("-"), Ok(KeyEvent::new('-', Modifiers::NONE)));
+    }
+
+    #[test]
+    fn rejects_a_separator_after_a_key() {
+        assert!(parse_key("ctrl-a-").is_err());
+        assert!(parse_key("a+").is_err());
+    }
+
+    #[test]
+    fn parses_function_keys() {
+        assert_eq!(
+            parse_key("f1"),
+            Ok(KeyEvent(KeyCode::F(1), Modifiers::NONE))
+        );
+        assert_eq!(
+            parse_key("shift-F12"),
+            Ok(KeyEvent(KeyCode::F(12), Modifiers::SHIFT))
+        );
+        assert!(parse_key("f13").is_err());
+        assert_eq!(parse_key("f"), Ok(KeyEvent::new('f', Modifiers::NONE)));
+    }
+
+    #[test]
+    fn parses_sequences() {
+        let keys: Vec<KeyEvent> = parse_chord("ctrl-x ctrl-e").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                KeyEvent::new('x', Modifiers::CTRL),
+                KeyEvent::new('e', Modifiers::CTRL)
+            ]
+        );
+        assert!(parse_chord("  ").is_err());
+        assert!(parse_chord("hyper-a").is_err());
+    }
+}
@@ -0,0 +1,744 @@
+use super::source::{is_toml, legacy_preset};
+use super::{FluxConfig, LoadedConfig, Theme};
+use dialoguer::Confirm;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Top-level keys holding user-named entries, where `set` may add new keys
+const MAP_KEYS: [&str; 7] = [
+    "aliases",
+    "abbreviations",
+    "path_aliases",
+    "environment_variables",
+    "prompt_segments",
+    "keybindings",
+    "plugin_permissions",
+];
+
+impl FluxConfig {
+    /// Reads a setting from the effective configuration
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    /// * `key` - Dotted path such as `theme.prompt_color` or `aliases.ll`
+    ///
+    /// # Returns
+    /// * `Result<String, String>` - Strings as-is, other values as JSON
+    pub fn get_key(config_path: &Path, key: &str) -> Result<String, String> {
+        let keys: Vec<String> = split_key(key)?;
+        let effective: Value = effective_value(config_path)?;
+        let value: &Value =
+            lookup(&effective, &keys).ok_or_else(|| format!("unknown key '{}'", key))?;
+
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            other => serde_json::to_string_pretty(other).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Changes a setting in the configuration file
+    ///
+    /// The value is converted to the type of the current setting. TOML files
+    /// are edited in place so comments and layout are kept. The change is only
+    /// written if the resulting file is valid.
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    /// * `key` - Dotted path such as `theme.prompt_color` or `aliases.ll`
+    /// * `raw` - New value as typed on the command line
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, String>` - Warnings about the new file, or why
+    ///   the change was rejected
+    pub fn set_key(config_path: &Path, key: &str, raw: &str) -> Result<Vec<String>, String> {
+        let keys: Vec<String> = split_key(key)?;
+        let current: LoadedConfig = FluxConfig::check(config_path)?;
+        let effective: Value = serde_json::to_value(&current.config).map_err(|e| e.to_string())?;
+
+        let value: Value = match lookup(&effective, &keys) {
+            Some(current) => coerce(current, raw).map_err(|e| format!("{}: {}", key, e))?,
+            // New aliases and variables are strings; new segments, key bindings,
+            // abbreviations and plugin permissions may be tables
+            None if MAP_KEYS.contains(&keys[0].as_str()) && keys.len() > 1 => {
+                if [
+                    "prompt_segments",
+                    "keybindings",
+                    "abbreviations",
+                    "plugin_permissions",
+                ]
+                .contains(&keys[0].as_str())
+                {
+                    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+                } else {
+                    Value::String(raw.to_string())
+                }
+            }
+            None => return Err(format!("unknown key '{}'", key)),
+        };
+
+        write_key(config_path, &keys, value, current.warnings.len())
+    }
+
+    /// Adds or replaces an entry of a map setting, such as an abbreviation
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    /// * `map` - Map setting, one of `MAP_KEYS`
+    /// * `name` - Name of the entry
+    /// * `value` - New value of the entry
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, String>` - Warnings about the new file, or why
+    ///   the change was rejected
+    pub fn set_entry<T: Serialize>(
+        config_path: &Path,
+        map: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<Vec<String>, String> {
+        let current: LoadedConfig = FluxConfig::check(config_path)?;
+        let value: Value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        let keys: Vec<String> = vec![map.to_string(), name.to_string()];
+        write_key(config_path, &keys, value, current.warnings.len())
+    }
+
+    /// Removes an entry of a map setting, such as an abbreviation
+    ///
+    /// Only the configuration file itself is edited; entries that come from
+    /// includes or `[os]`/`[host]` tables stay in effect.
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    /// * `map` - Map setting, one of `MAP_KEYS`
+    /// * `name` - Name of the entry
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, String>` - Warnings about the new file, or why
+    ///   the entry could not be removed
+    pub fn remove_entry(config_path: &Path, map: &str, name: &str) -> Result<Vec<String>, String> {
+        let keys: Vec<String> = vec![map.to_string(), name.to_string()];
+        let current: LoadedConfig = FluxConfig::check(config_path)?;
+
+        let (contents, toml) = read_for_editing(config_path)?;
+        let updated: Option<String> = if toml {
+            remove_in_toml(&contents, &keys)?
+        } else {
+            remove_in_json(&contents, &keys)?
+        };
+        let updated: String = updated
+            .ok_or_else(|| format!("{}.{} is not set in {}", map, name, config_path.display()))?;
+
+        let loaded: LoadedConfig =
+            replace_validated(config_path, &updated, current.warnings.len())?;
+        let mut warnings: Vec<String> = loaded.warnings;
+        if let Ok(now) = serde_json::to_value(&loaded.config) {
+            if lookup(&now, &keys).is_some() {
+                warnings.push(format!(
+                    "{}.{} is still set by an include or an [os] or [host] table",
+                    map, name
+                ));
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Replaces the theme in the configuration file, leaving other settings
+    /// untouched
+    ///
+    /// TOML files keep their comments; each theme field is rewritten in
+    /// place.
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    /// * `theme` - Theme to write
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, String>` - Warnings about the new file, or why
+    ///   the change was rejected
+    pub fn apply_theme(config_path: &Path, theme: &Theme) -> Result<Vec<String>, String> {
+        let current: LoadedConfig = FluxConfig::check(config_path)?;
+        let value: Value = serde_json::to_value(theme).map_err(|e| e.to_string())?;
+
+        let (contents, toml) = read_for_editing(config_path)?;
+        let updated: String = if toml {
+            let mut updated: String = contents;
+            if let Value::Object(fields) = &value {
+                for (field, spec) in fields {
+                    let keys: [String; 2] = ["theme".to_string(), field.clone()];
+                    updated = set_in_toml(&updated, &keys, spec)?;
+                }
+            }
+            updated
+        } else {
+            set_in_json(&contents, &["theme".to_string()], value)?
+        };
+
+        let loaded: LoadedConfig =
+            replace_validated(config_path, &updated, current.warnings.len())?;
+        let mut warnings: Vec<String> = loaded.warnings;
+        if loaded.config.theme != *theme {
+            warnings.push(
+                "theme settings in an [os] or [host] table or an include still override the applied theme"
+                    .to_string(),
+            );
+        }
+        Ok(warnings)
+    }
+
+    /// Opens the configuration file in `$VISUAL` or `$EDITOR`
+    ///
+    /// A copy is edited and only replaces the file once it validates. On
+    /// errors the user can edit again or discard the changes.
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>, String>` - Warnings about the saved file, or an
+    ///   error if the changes were discarded
+    pub fn edit_file(config_path: &Path) -> Result<Vec<String>, String> {
+        let editor: String = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+        let mut parts = editor.split_whitespace();
+        let program: &str = parts.next().unwrap_or("vi");
+        let editor_args: Vec<&str> = parts.collect();
+
+        let draft: PathBuf = sibling(config_path, "edit");
+        fs::copy(config_path, &draft).map_err(|e| format!("{}: {}", config_path.display(), e))?;
+
+        loop {
+            let status = Command::new(program)
+                .args(&editor_args)
+                .arg(&draft)
+                .status()
+                .map_err(|e| format!("failed to start {}: {}", program, e))?;
+            if !status.success() {
+                let _ = fs::remove_file(&draft);
+                return Err(format!(
+                    "{} exited with {}; changes discarded",
+                    program, status
+                ));
+            }
+
+            match FluxConfig::check(&draft) {
+                Ok(loaded) => {
+                    fs::rename(&draft, config_path)
+                        .map_err(|e| format!("{}: {}", config_path.display(), e))?;
+                    return Ok(loaded.warnings);
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    let again: bool = Confirm::new()
+                        .with_prompt("Edit again?")
+                        .default(true)
+                        .interact()
+                        .unwrap_or(false);
+                    if !again {
+                        let _ = fs::remove_file(&draft);
+                        return Err("changes discarded".to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces the configuration file with a preset
+    ///
+    /// The previous file is kept next to it with a `.bak` extension.
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    /// * `preset` - One of `minimal`, `full` or `powerline`
+    ///
+    /// # Returns
+    /// * `Result<Option<PathBuf>, String>` - Path of the backup, if there was
+    ///   a file to back up
+    pub fn reset(config_path: &Path, preset: &str) -> Result<Option<PathBuf>, String> {
+        let config: FluxConfig = FluxConfig::preset(preset).ok_or_else(|| {
+            format!(
+                "unknown preset '{}'; use minimal, full or powerline",
+                preset
+            )
+        })?;
+        let contents: String = config.serialize(file_format(config_path))?;
+
+        let backup: Option<PathBuf> = if config_path.exists() {
+            let backup: PathBuf = sibling(config_path, "bak");
+            fs::copy(config_path, &backup).map_err(|e| format!("{}: {}", backup.display(), e))?;
+            Some(backup)
+        } else {
+            None
+        };
+
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(config_path, contents)
+            .map_err(|e| format!("{}: {}", config_path.display(), e))?;
+        Ok(backup)
+    }
+
+    /// Writes the configuration to a new file, as TOML for a `.toml` path
+    /// and JSON otherwise
+    ///
+    /// # Arguments
+    /// * `config_path` - Path to the configuration file
+    pub fn save(&self, config_path: &Path) -> Result<(), String> {
+        let contents: String = self.serialize(file_format(config_path))?;
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(config_path, contents).map_err(|e| format!("{}: {}", config_path.display(), e))
+    }
+
+    /// Serializes the configuration as `json` or `toml`
+    ///
+    /// # Arguments
+    /// * `format` - Output format
+    ///
+    /// # Returns
+    /// * `Result<String, String>` - Serialized configuration
+    pub fn serialize(&self, format: &str) -> Result<String, String> {
+        match format {
+            "json" => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            "toml" => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+            _ => Err(format!("unknown format '{}'; use json or toml", format)),
+        }
+    }
+}
+
+/// Writes a value to a key path of the configuration file
+///
+/// The change is only written if the resulting file is valid, and a warning
+/// is added if the effective configuration does not take the new value.
+fn write_key(
+    config_path: &Path,
+    keys: &[String],
+    value: Value,
+    allowed_warnings: usize,
+) -> Result<Vec<String>, String> {
+    let (contents, toml) = read_for_editing(config_path)?;
+    let updated: String = if toml {
+        set_in_toml(&contents, keys, &value)?
+    } else {
+        set_in_json(&contents, keys, value.clone())?
+    };
+
+    let loaded: LoadedConfig = replace_validated(config_path, &updated, allowed_warnings)?;
+    let mut warnings: Vec<String> = loaded.warnings;
+    if let Ok(now) = serde_json::to_value(&loaded.config) {
+        if lookup(&now, keys) != Some(&value) {
+            warnings.push(format!(
+                "{} is overridden by an [os] or [host] table, so the new value has no effect here",
+                keys.join(".")
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+/// Reads the configuration file for editing
+///
+/// A legacy preset file such as `#minimal` is expanded to the preset's full
+/// settings first, so an edit keeps the rest of the preset.
+///
+/// # Returns
+/// * `Result<(String, bool), String>` - Contents to edit, and whether they
+///   are TOML rather than JSON
+fn read_for_editing(config_path: &Path) -> Result<(String, bool), String> {
+    let contents: String =
+        fs::read_to_string(config_path).map_err(|e| format!("{}: {}", config_path.display(), e))?;
+    match legacy_preset(&contents) {
+        Some(preset) => {
+            let format: &str = file_format(config_path);
+            Ok((preset.serialize(format)?, format == "toml"))
+        }
+        None => {
+            let toml: bool = is_toml(config_path, &contents);
+            Ok((contents, toml))
+        }
+    }
+}
+
+/// Format a whole configuration is written in: TOML for `.toml` files,
+/// JSON otherwise
+fn file_format(config_path: &Path) -> &'static str {
+    if config_path.extension().is_some_and(|ext| ext == "toml") {
+        "toml"
+    } else {
+        "json"
+    }
+}
+
+/// Splits a dotted key path; segments containing dots can be quoted
+fn split_key(key: &str) -> Result<Vec<String>, String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut current: String = String::new();
+    let mut quoted: bool = false;
+
+    for c in key.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => keys.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    keys.push(current);
+
+    if quoted || keys.iter().any(String::is_empty) {
+        return Err(format!("invalid key '{}'", key));
+    }
+    Ok(keys)
+}
+
+/// Loads the configuration and returns it as a JSON tree
+fn effective_value(config_path: &Path) -> Result<Value, String> {
+    let loaded: LoadedConfig = FluxConfig::check(config_path)?;
+    serde_json::to_value(&loaded.config).map_err(|e| e.to_string())
+}
+
+/// Follows a key path through a JSON tree
+fn lookup<'a>(value: &'a Value, keys: &[String]) -> Option<&'a Value> {
+    keys.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// Converts a command-line value to the type of the current setting
+fn coerce(current: &Value, raw: &str) -> Result<Value, String> {
+    match current {
+        Value::String(_) => Ok(Value::String(raw.to_string())),
+        Value::Bool(_) => raw
+            .parse::<bool>()
+            .map(Value::Bool)
+            .map_err(|_| format!("expected true or false, got '{}'", raw)),
+        Value::Number(_) => raw
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| format!("expected a non-negative integer, got '{}'", raw)),
+        // Optional settings that are unset, such as `plugin_registry`
+        Value::Null => {
+            Ok(serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())))
+        }
+        _ => serde_json::from_str(raw).map_err(|e| format!("expected JSON: {}", e)),
+    }
+}
+
+/// Parses a JSON configuration file for editing
+fn json_root(contents: &str) -> Result<Value, String> {
+    serde_json::from_str(contents).map_err(|e| e.to_string())
+}
+
+/// Sets a key in a JSON configuration file
+fn set_in_json(contents: &str, keys: &[String], value: Value) -> Result<String, String> {
+    let mut root: Value = json_root(contents)?;
+
+    let mut table: &mut Value = &mut root;
+    for key in &keys[..keys.len() - 1] {
+        let object: &mut Map<String, Value> = table
+            .as_object_mut()
+            .ok_or_else(|| format!("{} is not a table", key))?;
+        table = object
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    table
+        .as_object_mut()
+        .ok_or_else(|| "parent is not a table".to_string())?
+        .insert(keys[keys.len() - 1].clone(), value);
+
+    serde_json::to_string_pretty(&root).map_err(|e| e.to_string())
+}
+
+/// Removes a map entry from a JSON configuration file
+///
+/// Returns None if the file does not set the entry.
+fn remove_in_json(contents: &str, keys: &[String]) -> Result<Option<String>, String> {
+    let mut root: Value = json_root(contents)?;
+    let removed: Option<Value> = root
+        .get_mut(&keys[0])
+        .and_then(Value::as_object_mut)
+        .and_then(|map| map.remove(&keys[1]));
+    match removed {
+        Some(_) => serde_json::to_string_pretty(&root)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Removes a map entry from a TOML configuration file, keeping comments and
+/// layout
+///
+/// Returns None if the file does not set the entry.
+fn remove_in_toml(contents: &str, keys: &[String]) -> Result<Option<String>, String> {
+    let mut document: toml_edit::DocumentMut = contents
+        .parse()
+        .map_err(|e: toml_edit::TomlError| e.message().to_string())?;
+    let removed: Option<toml_edit::Item> = document
+        .get_mut(&keys[0])
+        .and_then(toml_edit::Item::as_table_like_mut)
+        .and_then(|table| table.remove(&keys[1]));
+    Ok(removed.map(|_| document.to_string()))
+}
+
+/// Sets a key in a TOML configuration file, keeping comments and layout
+fn set_in_toml(contents: &str, keys: &[String], value: &Value) -> Result<String, String> {
+    let mut document: toml_edit::DocumentMut = contents
+        .parse()
+        .map_err(|e: toml_edit::TomlError| e.message().to_string())?;
+
+    let mut item: &mut toml_edit::Item = document.as_item_mut();
+    for key in &keys[..keys.len() - 1] {
+        let table = item
+            .as_table_like_mut()
+            .ok_or_else(|| format!("{} is not a table", key))?;
+        if table.get(key).is_none() {
+            let mut created: toml_edit::Table = toml_edit::Table::new();
+            created.set_implicit(true);
+            table.insert(key, toml_edit::Item::Table(created));
+        }
+        item = table.get_mut(key).expect("key was just inserted");
+    }
+
+    let table = item
+        .as_table_like_mut()
+        .ok_or_else(|| "parent is not a table".to_string())?;
+    let mut new: toml_edit::Value = to_toml(value)?;
+    match table.get_mut(&keys[keys.len() - 1]) {
+        // Replacing only the value keeps comments attached to the key
+        Some(toml_edit::Item::Value(old)) => {
+            *new.decor_mut() = old.decor().clone();
+            *old = new;
+        }
+        _ => {
+            table.insert(&keys[keys.len() - 1], toml_edit::Item::Value(new));
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+/// Converts a JSON value to a TOML value
+fn to_toml(value: &Value) -> Result<toml_edit::Value, String> {
+    match value {
+        Value::String(s) => Ok(s.as_str().into()),
+        Value::Bool(b) => Ok((*b).into()),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Ok(i.into()),
+            (None, Some(f)) => Ok(f.into()),
+            _ => Err(format!("number {} is out of range", n)),
+        },
+        Value::Array(items) => items
+            .iter()
+            .map(to_toml)
+            .collect::<Result<toml_edit::Array, String>>()
+            .map(toml_edit::Value::Array),
+        Value::Object(entries) => {
+            let mut table: toml_edit::InlineTable = toml_edit::InlineTable::new();
+            for (key, value) in entries {
+                table.insert(key, to_toml(value)?);
+            }
+            Ok(toml_edit::Value::InlineTable(table))
+        }
+        Value::Null => Err("TOML has no null value".to_string()),
+    }
+}
+
+/// Writes new contents over a configuration file if they validate
+///
+/// The contents are written to a sibling file and checked first, so includes
+/// resolve the same way and the original is never left half-written. The
+/// change is rejected if it adds warnings to the ones the file already had.
+///
+/// # Arguments
+/// * `config_path` - Path to the configuration file
+/// * `contents` - New file contents
+/// * `allowed_warnings` - Number of warnings the current file has
+fn replace_validated(
+    config_path: &Path,
+    contents: &str,
+    allowed_warnings: usize,
+) -> Result<LoadedConfig, String> {
+    let draft: PathBuf = sibling(config_path, "edit");
+    fs::write(&draft, contents).map_err(|e| format!("{}: {}", draft.display(), e))?;
+
+    // Report problems against the real file name rather than the draft's
+    let file_name = |path: &Path| -> String {
+        path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    let (draft_name, real_name) = (file_name(&draft), file_name(config_path));
+    let rename = |message: String| message.replace(&draft_name, &real_name);
+
+    let result: Result<LoadedConfig, String> = match FluxConfig::check(&draft) {
+        Ok(loaded) if loaded.warnings.len() > allowed_warnings => {
+            let warnings: Vec<String> = loaded.warnings.into_iter().map(rename).collect();
+            Err(warnings.join("\n"))
+        }
+        Ok(mut loaded) => {
+            loaded.warnings = loaded.warnings.into_iter().map(rename).collect();
+            return fs::rename(&draft, config_path)
+                .map(|_| loaded)
+                .map_err(|e| format!("{}: {}", config_path.display(), e));
+        }
+        Err(e) => Err(rename(e)),
+    };
+
+    let _ = fs::remove_file(&draft);
+    result
+}
+
+/// Builds a path next to the config that keeps its extension,
+/// e.g. `config.edit.toml` for `config.toml`
+fn sibling(config_path: &Path, tag: &str) -> PathBuf {
+    let stem: String = config_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "config".to_string());
+    let name: String = match config_path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, tag, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, tag),
+    };
+    config_path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    /// Writes a configuration file into a fresh temporary directory
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let path: PathBuf = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// The effective configuration as a JSON tree
+    fn effective(path: &Path) -> Value {
+        let value: Value = effective_value(path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        value
+    }
+
+    #[test]
+    fn keeps_a_legacy_preset_when_editing() {
+        let mut expected: Value = serde_json::to_value(FluxConfig::minimal()).unwrap();
+        expected["show_time"] = Value::Bool(true);
+
+        for name in ["config.fl", "config.toml"] {
+            let path: PathBuf = config_file(name, "#minimal\n");
+            FluxConfig::set_key(&path, "show_time", "true").unwrap();
+            assert_eq!(effective(&path), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn saves_a_new_file_in_the_format_of_its_extension() {
+        let mut config: FluxConfig = FluxConfig::powerline();
+        config.show_time = false;
+        let expected: Value = serde_json::to_value(&config).unwrap();
+
+        for name in ["config.toml", "config.json"] {
+            let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+            let path: PathBuf = dir.join(name);
+            config.save(&path).unwrap();
+            assert_eq!(effective(&path), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn gets_and_sets_dotted_keys() {
+        let path: PathBuf = config_file(
+            "config.fl",
+            "{\n  \"theme\": { \"prompt_color\": \"blue\" }\n}\n",
+        );
+        assert_eq!(
+            FluxConfig::get_key(&path, "theme.prompt_color").unwrap(),
+            "blue"
+        );
+
+        FluxConfig::set_key(&path, "theme.prompt_color", "green").unwrap();
+        FluxConfig::set_key(&path, "aliases.\"g.s\"", "git status").unwrap();
+        assert_eq!(
+            FluxConfig::get_key(&path, "theme.prompt_color").unwrap(),
+            "green"
+        );
+        assert_eq!(
+            FluxConfig::get_key(&path, "aliases.\"g.s\"").unwrap(),
+            "git status"
+        );
+        assert!(FluxConfig::get_key(&path, "theme.bogus").is_err());
+        assert!(FluxConfig::set_key(&path, "bogus", "1").is_err());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn edits_toml_in_place() {
+        let contents: &str =
+            "# My shell\nshow_time = true # clock\n\n# Shortcuts\n[aliases]\nll = \"ls -l\"\n";
+        let path: PathBuf = config_file("config.toml", contents);
+
+        FluxConfig::set_key(&path, "show_time", "false").unwrap();
+        FluxConfig::set_key(&path, "aliases.la", "ls -a").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# My shell\nshow_time = false # clock\n\n# Shortcuts\n[aliases]\nll = \"ls -l\"\nla = \"ls -a\"\n"
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn converts_values_to_the_type_of_the_setting() {
+        let path: PathBuf = config_file("config.toml", "");
+        FluxConfig::set_key(&path, "show_time", "false").unwrap();
+        FluxConfig::set_key(&path, "history_size", "500").unwrap();
+        FluxConfig::set_key(&path, "prompt", "42").unwrap();
+
+        assert!(FluxConfig::set_key(&path, "show_time", "yes").is_err());
+        assert!(FluxConfig::set_key(&path, "history_size", "lots").is_err());
+        let value: Value = effective(&path);
+        assert_eq!(value["show_time"], Value::Bool(false));
+        assert_eq!(value["history_size"], Value::from(500));
+        assert_eq!(value["prompt"], Value::String("42".to_string()));
+    }
+
+    #[test]
+    fn sets_and_removes_map_entries() {
+        for name in ["config.fl", "config.toml"] {
+            let path: PathBuf = config_file(name, "");
+            FluxConfig::set_key(&path, "aliases.ll", "ls -l").unwrap();
+            FluxConfig::set_entry(&path, "aliases", "la", &"ls -a").unwrap();
+
+            FluxConfig::remove_entry(&path, "aliases", "ll").unwrap();
+            assert!(FluxConfig::remove_entry(&path, "aliases", "ll").is_err());
+            let aliases: Value = effective(&path)["aliases"].clone();
+            assert_eq!(aliases, serde_json::json!({ "la": "ls -a" }), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_changes_that_add_warnings() {
+        let contents: &str = "history_size = 100\n";
+        let path: PathBuf = config_file("config.toml", contents);
+
+        let e: String = replace_validated(&path, "history_size = 0\n", 0)
+            .err()
+            .unwrap();
+        assert!(e.starts_with("config.toml:1:1: history_size: "), "{}", e);
+        assert!(replace_validated(&path, "history_size = -3\n", 0).is_err());
+        assert!(FluxConfig::set_key(&path, "history_size", "-3").is_err());
+        assert!(FluxConfig::set_key(&path, "history_size", "0").is_err());
+
+        // Neither the file nor a draft of it changed
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,6 +1,22 @@
 /// Configuration module containing theme and shell settings
+mod abbreviation;
 mod flux_config;
+mod keybinding;
+mod manage;
+mod schema;
+mod segment;
+mod source;
+mod style;
 mod theme;
+mod themes;
+mod watcher;
 
+pub use abbreviation::{expand_at, expand_line, Abbreviation};
 pub use flux_config::FluxConfig;
+pub use keybinding::{editor_action, parse_chord, KeyBinding};
+pub use schema::LoadedConfig;
+pub use segment::PromptSegment;
+pub use style::{paint, Style};
 pub use theme::Theme;
+pub use themes::ThemeFile;
+pub use watcher::ConfigWatcher;
@@ -0,0 +1,390 @@
+use super::keybinding::{editor_action, parse_chord};
+use super::source::{line_column, Document, Resolved};
+use super::style::Style;
+use super::FluxConfig;
+use chrono::format::{Item, StrftimeItems};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+
+/// Current version of the configuration schema
+///
+/// Bump this and append to `MIGRATIONS` whenever a change to `FluxConfig`
+/// needs more than a new defaulted field.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrades a configuration from version `n` to `n + 1`, indexed by `n`
+type Migration = fn(&mut Map<String, Value>);
+
+const MIGRATIONS: [Migration; 1] = [migrate_v0];
+
+/// A configuration that parsed, with any problems that did not stop it loading
+pub struct LoadedConfig {
+    /// The parsed and migrated configuration
+    pub config: FluxConfig,
+    /// Unknown keys and invalid values, formatted as `file:line:column: message`
+    pub warnings: Vec<String>,
+    /// Every file the configuration was read from, including includes
+    pub files: Vec<PathBuf>,
+}
+
+/// Migrates and validates a resolved configuration tree
+///
+/// # Arguments
+/// * `resolved` - Merged configuration and the files it came from
+///
+/// # Returns
+/// * `Result<LoadedConfig, String>` - The configuration with warnings, or an
+///   error with the line and column of the offending key
+pub fn parse(resolved: Resolved) -> Result<LoadedConfig, String> {
+    let Resolved {
+        value: mut table,
+        documents,
+    } = resolved;
+    migrate(&mut table, &documents)?;
+
+    let mut warnings: Vec<String> = Vec::new();
+    let config: FluxConfig = {
+        let mut on_ignored = |path: serde_ignored::Path| {
+            let keys: Vec<String> = path.to_string().split('.').map(String::from).collect();
+            warnings.push(located(&documents, &keys, "unknown key"));
+        };
+        let deserializer = serde_ignored::Deserializer::new(Value::Object(table), &mut on_ignored);
+        serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            let keys: Vec<String> = e
+                .path()
+                .iter()
+                .filter_map(|segment| match segment {
+                    serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+                    _ => None,
+                })
+                .collect();
+            located(&documents, &keys, &e.inner().to_string())
+        })?
+    };
+
+    validate(&config, &documents, &mut warnings);
+    let files: Vec<PathBuf> = documents.into_iter().map(|d| d.path).collect();
+    Ok(LoadedConfig {
+        config,
+        warnings,
+        files,
+    })
+}
+
+/// Applies every migration between the file's version and `CONFIG_VERSION`
+///
+/// Files without a `version` key predate versioning and count as version 0.
+fn migrate(table: &mut Map<String, Value>, documents: &[Document]) -> Result<(), String> {
+    let version: u64 = match table.get("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            located(
+                documents,
+                &["version".to_string()],
+                "expected a non-negative integer",
+            )
+        })?,
+    };
+
+    if version > CONFIG_VERSION as u64 {
+        return Err(located(
+            documents,
+            &["version".to_string()],
+            &format!(
+                "version {} is newer than this flux supports ({}); please upgrade flux",
+                version, CONFIG_VERSION
+            ),
+        ));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(table);
+    }
+    table.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    Ok(())
+}
+
+/// Version 0 files predate the `version` key but share the version 1 layout
+fn migrate_v0(_table: &mut Map<String, Value>) {}
+
+/// Checks values that deserialize fine but cannot be used as intended
+fn validate(config: &FluxConfig, documents: &[Document], warnings: &mut Vec<String>) {
+    let mut warn = |keys: &[&str], message: String| {
+        let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        warnings.push(located(documents, &keys, &message));
+    };
+
+    if let Ok(Value::Object(theme)) = serde_json::to_value(&config.theme) {
+        for (key, spec) in &theme {
+            if let Some(Err(e)) = spec.as_str().map(str::parse::<Style>) {
+                warn(&["theme", key], e);
+            }
+        }
+    }
+
+    for (name, segment) in &config.prompt_segments {
+        if segment.command.is_none() && segment.env.is_none() {
+            warn(
+                &["prompt_segments", name],
+                "needs a `command` or an `env`".to_string(),
+            );
+        }
+        if let Err(e) = segment.color.parse::<Style>() {
+            warn(&["prompt_segments", name, "color"], e);
+        }
+    }
+
+    if !["template", "powerline"].contains(&config.prompt_mode.as_str()) {
+        warn(
+            &["prompt_mode"],
+            format!(
+                "unknown mode '{}'; use template or powerline",
+                config.prompt_mode
+            ),
+        );
+    }
+    if !["auto", "powerline", "ascii"].contains(&config.powerline_separator.as_str()) {
+        warn(
+            &["powerline_separator"],
+            format!(
+                "unknown separator '{}'; use auto, powerline or ascii",
+                config.powerline_separator
+            ),
+        );
+    }
+
+    if !["emacs", "vi"].contains(&config.edit_mode.as_str()) {
+        warn(
+            &["edit_mode"],
+            format!("unknown mode '{}'; use emacs or vi", config.edit_mode),
+        );
+    }
+    if !["list", "circular", "menu"].contains(&config.completion_style.as_str()) {
+        warn(
+            &["completion_style"],
+            format!(
+                "unknown style '{}'; use list, circular or menu",
+                config.completion_style
+            ),
+        );
+    }
+    for (chord, binding) in &config.keybindings {
+        if let Err(e) = parse_chord(chord) {
+            warn(&["keybindings", chord], e);
+        }
+        let set: usize = [&binding.action, &binding.run, &binding.insert]
+            .iter()
+            .filter(|field| field.is_some())
+            .count();
+        if set != 1 {
+            warn(
+                &["keybindings", chord],
+                "needs exactly one of `action`, `run` or `insert`".to_string(),
+            );
+        }
+        if let Some(action) = &binding.action {
+            if editor_action(action).is_none() {
+                warn(
+                    &["keybindings", chord, "action"],
+                    format!("unknown editor action '{}'", action),
+                );
+            }
+        }
+    }
+
+    for (name, abbreviation) in &config.abbreviations {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            warn(
+                &["abbreviations", name],
+                "must be a single word".to_string(),
+            );
+        }
+        if !["command", "anywhere"].contains(&abbreviation.position.as_str()) {
+            warn(
+                &["abbreviations", name, "position"],
+                format!(
+                    "unknown position '{}'; use command or anywhere",
+                    abbreviation.position
+                ),
+            );
+        }
+    }
+
+    for (name, capabilities) in &config.plugin_permissions {
+        for dir in &capabilities.filesystem {
+            if !dir.starts_with('/') && dir != "~" && !dir.starts_with("~/") {
+                warn(
+                    &["plugin_permissions", name, "filesystem"],
+                    format!("'{}' must be an absolute path or start with ~", dir),
+                );
+            }
+        }
+    }
+
+    if config.history_size == 0 {
+        warn(&["history_size"], "must be greater than 0".to_string());
+    }
+
+    if StrftimeItems::new(&config.time_format).any(|item| matches!(item, Item::Error)) {
+        warn(
+            &["time_format"],
+            format!("invalid strftime format '{}'", config.time_format),
+        );
+    }
+}
+
+/// Formats a message for a key path as `file:line:column: path: message`
+///
+/// The position is that of the deepest key of the path found in the files,
+/// searched in order of precedence; values that come from defaults have no
+/// position.
+fn located(documents: &[Document], keys: &[String], message: &str) -> String {
+    let path: String = keys.join(".");
+    let subject: String = if path.is_empty() {
+        message.to_string()
+    } else {
+        format!("{}: {}", path, message)
+    };
+
+    let mut best: Option<(usize, &Document, usize)> = None;
+    for document in documents {
+        let mut offset: Option<usize> = None;
+        let mut found: usize = 0;
+        for key in keys {
+            match find_key(&document.contents, key, offset.unwrap_or(0)) {
+                Some(position) => {
+                    offset = Some(position);
+                    found += 1;
+                }
+                None => break,
+            }
+        }
+        if let Some(offset) = offset {
+            if best.is_none_or(|(most, _, _)| found > most) {
+                best = Some((found, document, offset));
+            }
+        }
+    }
+
+    match (best, documents.first()) {
+        (Some((_, document, offset)), _) => {
+            let (line, column) = line_column(&document.contents, offset);
+            format!("{}:{}:{}: {}", document.name, line, column, subject)
+        }
+        (None, Some(document)) => format!("{}: {}", document.name, subject),
+        (None, None) => subject,
+    }
+}
+
+/// Finds `key` at or after `from` as a whole word, quoted (JSON) or bare (TOML)
+fn find_key(contents: &str, key: &str, from: usize) -> Option<usize> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    contents[from..]
+        .match_indices(key)
+        .map(|(index, _)| from + index)
+        .find(|&start| {
+            let before: Option<char> = contents[..start].chars().next_back();
+            let after: Option<char> = contents[start + key.len()..].chars().next();
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolves a single file without includes
+    fn resolved(name: &str, contents: &str) -> Resolved {
+        let value: Map<String, Value> = if name.ends_with(".toml") {
+            toml::from_str(contents).unwrap()
+        } else {
+            serde_json::from_str(contents).unwrap()
+        };
+        Resolved {
+            value,
+            documents: vec![Document {
+                path: PathBuf::from(name),
+                name: name.to_string(),
+                contents: contents.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn warns_about_unknown_keys_and_unusable_values() {
+        let loaded: LoadedConfig = parse(resolved(
+            "config.toml",
+            "edit_mode = \"vim\"\nbogus = 1\nhistory_size = 0\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            loaded.warnings,
+            vec![
+                "config.toml:2:1: bogus: unknown key",
+                "config.toml:1:1: edit_mode: unknown mode 'vim'; use emacs or vi",
+                "config.toml:3:1: history_size: must be greater than 0",
+            ]
+        );
+        assert_eq!(loaded.files, vec![PathBuf::from("config.toml")]);
+    }
+
+    #[test]
+    fn locates_nested_warnings() {
+        let contents: &str = "{\n  \"theme\": {\n    \"prompt_color\": \"blurple\"\n  }\n}\n";
+        let loaded: LoadedConfig = parse(resolved("config.fl", contents)).unwrap();
+        assert_eq!(
+            loaded.warnings,
+            vec!["config.fl:3:6: theme.prompt_color: unknown color or style 'blurple'"]
+        );
+    }
+
+    #[test]
+    fn fails_on_values_of_the_wrong_type() {
+        let e: String = parse(resolved(
+            "config.toml",
+            "show_time = true\nhistory_size = \"lots\"\n",
+        ))
+        .err()
+        .unwrap();
+        assert!(e.starts_with("config.toml:2:1: history_size: "), "{}", e);
+    }
+
+    #[test]
+    fn migrates_unversioned_files() {
+        let loaded: LoadedConfig = parse(resolved("config.toml", "history_size = 10\n")).unwrap();
+        assert_eq!(loaded.config.version, CONFIG_VERSION);
+        assert!(loaded.warnings.is_empty(), "{:?}", loaded.warnings);
+    }
+
+    #[test]
+    fn rejects_newer_and_invalid_versions() {
+        let newer: String = parse(resolved("config.toml", "version = 99\n"))
+            .err()
+            .unwrap();
+        assert!(
+            newer.starts_with("config.toml:1:1: version: version 99 is newer"),
+            "{}",
+            newer
+        );
+        let negative: String = parse(resolved("config.toml", "version = -1\n"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            negative,
+            "config.toml:1:1: version: expected a non-negative integer"
+        );
+    }
+
+    #[test]
+    fn finds_whole_keys() {
+        let contents: &str = "prompt_mode = 1\nmode = 2\n";
+        assert_eq!(find_key(contents, "mode", 0), Some(16));
+        assert_eq!(line_column(contents, 16), (2, 1));
+        assert_eq!(find_key(contents, "prompt", 0), None);
+
+        let json: &str = "{\"a\": {\"b\": 1}, \"b\": 2}";
+        let a: usize = find_key(json, "a", 0).unwrap();
+        assert_eq!(find_key(json, "b", a), Some(8));
+        assert_eq!(find_key(json, "b", 9), Some(17));
+    }
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// A user-defined prompt segment, shown through its `{name}` placeholder
+///
+/// A segment reads either an environment variable or the output of a shell
+/// command. Commands run in the background with the other slow segments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptSegment {
+    /// Shell command whose trimmed output is shown
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Environment variable whose value is shown (takes precedence over `command`)
+    #[serde(default)]
+    pub env: Option<String>,
+    /// Seconds to reuse a command's output before running it again
+    #[serde(default)]
+    pub ttl_secs: u64,
+    /// Style spec for the segment, such as `"bold blue"`
+    #[serde(default = "default_segment_color")]
+    pub color: String,
+}
+
+fn default_segment_color() -> String {
+    "white".to_string()
+}
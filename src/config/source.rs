@@ -0,0 +1,283 @@
+use super::FluxConfig;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A configuration file that contributed to the merged configuration
+pub struct Document {
+    /// Path the file was read from
+    pub path: PathBuf,
+    /// File name used in messages
+    pub name: String,
+    /// Raw file contents, used to locate keys in messages
+    pub contents: String,
+}
+
+/// The merged configuration tree and the files it was read from
+pub struct Resolved {
+    /// Configuration after includes and overrides have been applied
+    pub value: Map<String, Value>,
+    /// Files in order of precedence, the main file first
+    pub documents: Vec<Document>,
+}
+
+/// Reads a configuration file together with its includes and overrides
+///
+/// Files are TOML or JSON: `.toml` files are always TOML, other files are
+/// JSON when they start with `{`. Each file is merged in this order, later
+/// entries winning:
+///
+/// 1. files listed in `include = [...]`, relative to the including file
+/// 2. the file's own settings
+/// 3. its `[os.<name>]` table for the running OS (`linux`, `macos`, `windows`)
+/// 4. its `[host."<name>"]` table for this machine's hostname
+///
+/// # Arguments
+/// * `path` - Path to the main configuration file
+///
+/// # Returns
+/// * `Result<Resolved, String>` - The merged tree, or an error with the file,
+///   line and column where reading failed
+pub fn resolve(path: &Path) -> Result<Resolved, String> {
+    let mut documents: Vec<Document> = Vec::new();
+    let mut stack: Vec<PathBuf> = Vec::new();
+    let value: Map<String, Value> = resolve_file(path, &mut stack, &mut documents)?;
+
+    // Each file is read after its includes, which it takes precedence over,
+    // and later includes over earlier ones
+    documents.reverse();
+    Ok(Resolved { value, documents })
+}
+
+/// Reads one file, recursing into its includes
+fn resolve_file(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    documents: &mut Vec<Document>,
+) -> Result<Map<String, Value>, String> {
+    let name: String = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let canonical: PathBuf = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if stack.contains(&canonical) {
+        return Err(format!("{}: include cycle detected", path.display()));
+    }
+
+    let contents: String =
+        fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut own: Map<String, Value> = parse_document(path, &name, &contents)?;
+
+    let includes: Vec<String> = match own.remove("include") {
+        None => Vec::new(),
+        Some(Value::String(file)) => vec![file],
+        Some(Value::Array(files)) => files
+            .into_iter()
+            .map(|file| match file {
+                Value::String(file) => Ok(file),
+                _ => Err(format!("{}: include: expected a list of file paths", name)),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(format!("{}: include: expected a list of file paths", name)),
+    };
+    let os_overrides: Option<Value> = own.remove("os");
+    let host_overrides: Option<Value> = own.remove("host");
+
+    let base_dir: &Path = path.parent().unwrap_or(Path::new("."));
+    let mut merged: Map<String, Value> = Map::new();
+
+    stack.push(canonical);
+    for include in &includes {
+        let included: Map<String, Value> =
+            resolve_file(&resolve_include(base_dir, include), stack, documents)?;
+        merge(&mut merged, included);
+    }
+    stack.pop();
+
+    documents.push(Document {
+        path: path.to_path_buf(),
+        name,
+        contents,
+    });
+    merge(&mut merged, own);
+
+    if let Some(table) = select(os_overrides, std::env::consts::OS) {
+        merge(&mut merged, table);
+    }
+    let hostname: String = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if let Some(table) = select(host_overrides, &hostname) {
+        merge(&mut merged, table);
+    }
+
+    Ok(merged)
+}
+
+/// Parses a single file as TOML or JSON
+fn parse_document(path: &Path, name: &str, contents: &str) -> Result<Map<String, Value>, String> {
+    if let Some(preset) = legacy_preset(contents) {
+        return match serde_json::to_value(preset) {
+            Ok(Value::Object(table)) => Ok(table),
+            _ => Err(format!("{}: failed to expand preset", name)),
+        };
+    }
+
+    if is_toml(path, contents) {
+        toml::from_str::<Map<String, Value>>(contents).map_err(|e| {
+            let (line, column) = line_column(contents, e.span().map_or(0, |span| span.start));
+            format!("{}:{}:{}: {}", name, line, column, e.message().trim())
+        })
+    } else {
+        match serde_json::from_str::<Value>(contents) {
+            Ok(Value::Object(table)) => Ok(table),
+            Ok(_) => Err(format!("{}:1:1: expected a JSON object", name)),
+            Err(e) => Err(format!(
+                "{}:{}:{}: {}",
+                name,
+                e.line(),
+                e.column(),
+                strip_position(&e.to_string())
+            )),
+        }
+    }
+}
+
+/// Returns the preset a legacy configuration file names
+///
+/// Files from before the JSON format held nothing but a preset name such as
+/// `#minimal`; in any other file `#full` is a TOML comment.
+///
+/// # Arguments
+/// * `contents` - Raw file contents
+pub fn legacy_preset(contents: &str) -> Option<FluxConfig> {
+    contents
+        .trim()
+        .strip_prefix('#')
+        .and_then(FluxConfig::preset)
+}
+
+/// Whether a configuration file is TOML rather than JSON
+///
+/// # Arguments
+/// * `path` - Path of the file
+/// * `contents` - Raw file contents
+pub fn is_toml(path: &Path, contents: &str) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml") || !contents.trim_start().starts_with('{')
+}
+
+/// Resolves an include path relative to the including file, expanding `~`
+fn resolve_include(base_dir: &Path, include: &str) -> PathBuf {
+    if let Some(rest) = include.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    base_dir.join(include)
+}
+
+/// Picks the override table for `key` out of an `os` or `host` table
+fn select(overrides: Option<Value>, key: &str) -> Option<Map<String, Value>> {
+    match overrides? {
+        Value::Object(mut tables) => match tables.remove(key) {
+            Some(Value::Object(table)) => Some(table),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Recursively merges `overlay` into `base`
+///
+/// Tables are merged key by key; any other value replaces the one in `base`.
+fn merge(base: &mut Map<String, Value>, overlay: Map<String, Value>) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(table)) => merge(existing, table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Converts a byte offset into a 1-based line and column
+pub fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before: &str = &contents[..offset.min(contents.len())];
+    let line: usize = before.matches('\n').count() + 1;
+    let column: usize = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// Removes the ` at line L column C` suffix serde_json adds to its messages
+fn strip_position(message: &str) -> &str {
+    match message.rfind(" at line ") {
+        Some(index) => &message[..index],
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn expands_a_file_holding_only_a_preset() {
+        let table: Map<String, Value> =
+            parse_document(Path::new("config.fl"), "config.fl", "#minimal\n").unwrap();
+        let minimal: Value = serde_json::to_value(FluxConfig::preset("minimal").unwrap()).unwrap();
+        assert_eq!(Value::Object(table), minimal);
+    }
+
+    #[test]
+    fn keeps_toml_after_a_preset_comment() {
+        let table: Map<String, Value> = parse_document(
+            Path::new("config.toml"),
+            "config.toml",
+            "#full\nshow_time = false\n",
+        )
+        .unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(table["show_time"], Value::Bool(false));
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let e: String =
+            parse_document(Path::new("config.toml"), "config.toml", "a = 1\nb = \n").unwrap_err();
+        assert!(e.starts_with("config.toml:2:"), "{}", e);
+        let e: String =
+            parse_document(Path::new("config.fl"), "config.fl", "{\n  \"a\": 1,\n}").unwrap_err();
+        assert!(e.starts_with("config.fl:3:"), "{}", e);
+    }
+
+    #[test]
+    fn lists_documents_in_order_of_precedence() {
+        let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("base.toml"),
+            "history_size = 1\nshow_time = true\n",
+        )
+        .unwrap();
+        fs::write(dir.join("work.toml"), "history_size = 2\n").unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "include = [\"base.toml\", \"work.toml\"]\nprompt = \"> \"\n",
+        )
+        .unwrap();
+
+        let resolved: Resolved = resolve(&dir.join("config.toml")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<&str> = resolved
+            .documents
+            .iter()
+            .map(|document| document.name.as_str())
+            .collect();
+        assert_eq!(names, ["config.toml", "work.toml", "base.toml"]);
+        assert_eq!(resolved.value["history_size"], Value::from(2));
+        assert_eq!(resolved.value["show_time"], Value::Bool(true));
+    }
+}
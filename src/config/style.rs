@@ -0,0 +1,515 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::vec::IntoIter;
+
+/// How many colors the terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorLevel {
+    /// No colors (`NO_COLOR` or a dumb terminal); text styles are kept
+    None,
+    /// The 16 standard ANSI colors
+    Basic,
+    /// The xterm 256-color palette
+    Ansi256,
+    /// 24-bit RGB colors
+    TrueColor,
+}
+
+impl ColorLevel {
+    /// Returns the color level of the terminal, detected once per process
+    pub fn current() -> Self {
+        static LEVEL: OnceLock<ColorLevel> = OnceLock::new();
+        *LEVEL.get_or_init(Self::detect)
+    }
+
+    /// Detects the color level from `NO_COLOR`, `COLORTERM` and `TERM`
+    fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default().to_lowercase();
+
+        if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return ColorLevel::None;
+        }
+        let colorterm: String = var("COLORTERM");
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorLevel::TrueColor;
+        }
+        let term: String = var("TERM");
+        if term == "dumb" {
+            ColorLevel::None
+        } else if term.contains("256color") {
+            ColorLevel::Ansi256
+        } else if term.contains("direct") || term.contains("truecolor") {
+            ColorLevel::TrueColor
+        } else {
+            ColorLevel::Basic
+        }
+    }
+}
+
+/// A foreground or background color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 16 standard colors; 0-7 normal, 8-15 bright
+    Basic(u8),
+    /// An index into the 256-color palette
+    Fixed(u8),
+    /// A 24-bit color
+    Rgb(u8, u8, u8),
+}
+
+/// Names of the 8 normal colors, in ANSI order
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Approximate RGB values of the 16 standard colors (xterm defaults)
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+impl Color {
+    /// Parses a single color token
+    ///
+    /// Accepts names (`red`, `bright_red`, `purple`, `gray`), hex (`#f80`,
+    /// `#ff8800`), `rgb(255,136,0)` and palette indexes (`0`-`255`).
+    fn parse(token: &str) -> Option<Self> {
+        if let Some(hex) = token.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+        if let Some(args) = token.strip_prefix("rgb(").and_then(|t| t.strip_suffix(')')) {
+            let parts: Vec<u8> = args
+                .split(',')
+                .map(|p| p.trim().parse::<u8>())
+                .collect::<Result<_, _>>()
+                .ok()?;
+            return match parts[..] {
+                [r, g, b] => Some(Color::Rgb(r, g, b)),
+                _ => None,
+            };
+        }
+        if let Ok(index) = token.parse::<u8>() {
+            return Some(Color::Fixed(index));
+        }
+
+        let (bright, name) = match token
+            .strip_prefix("bright_")
+            .or_else(|| token.strip_prefix("bright"))
+        {
+            Some(name) => (true, name),
+            None => (false, token),
+        };
+        let name: &str = match name {
+            "purple" => "magenta",
+            "gray" | "grey" if !bright => return Some(Color::Basic(8)),
+            other => other,
+        };
+        let index: u8 = COLOR_NAMES.iter().position(|n| *n == name)? as u8;
+        Some(Color::Basic(if bright { index + 8 } else { index }))
+    }
+
+    /// Converts the color to one the terminal can display
+    fn downgrade(self, level: ColorLevel) -> Option<Self> {
+        match (level, self) {
+            (ColorLevel::None, _) => None,
+            (ColorLevel::TrueColor, color) => Some(color),
+            (ColorLevel::Ansi256, Color::Rgb(r, g, b)) => Some(Color::Fixed(rgb_to_fixed(r, g, b))),
+            (ColorLevel::Ansi256, color) => Some(color),
+            (ColorLevel::Basic, Color::Basic(n)) => Some(Color::Basic(n)),
+            (ColorLevel::Basic, Color::Fixed(n)) => {
+                let (r, g, b) = fixed_to_rgb(n);
+                Some(Color::Basic(rgb_to_basic(r, g, b)))
+            }
+            (ColorLevel::Basic, Color::Rgb(r, g, b)) => Some(Color::Basic(rgb_to_basic(r, g, b))),
+        }
+    }
+
+    /// Returns the approximate RGB value of the color
+    fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Basic(n) => BASIC_RGB[n as usize % 16],
+            Color::Fixed(n) => fixed_to_rgb(n),
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// Returns the SGR parameters selecting this color
+    fn sgr(self, background: bool) -> String {
+        let offset: u8 = if background { 10 } else { 0 };
+        match self {
+            Color::Basic(n) if n < 8 => (30 + offset + n).to_string(),
+            Color::Basic(n) => (90 + offset + n - 8).to_string(),
+            Color::Fixed(n) => format!("{};5;{}", 38 + offset, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", 38 + offset, r, g, b),
+        }
+    }
+}
+
+/// A text style parsed from a spec such as `"bold #ff8800 on #202020"`
+///
+/// A spec is a space-separated list of text attributes (`bold`, `dim`,
+/// `italic`, `underline`, `blink`, `reverse`, `strikethrough`), a foreground
+/// color, and `on` followed by a background color. An empty spec leaves text
+/// unstyled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    /// Foreground color
+    pub fg: Option<Color>,
+    /// Background color
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub reverse: bool,
+    pub strikethrough: bool,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut style: Style = Style::default();
+        let mut tokens: Tokens = tokenize(&spec.to_lowercase()).into_iter();
+
+        while let Some(token) = tokens.next() {
+            match token.as_str() {
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "blink" => style.blink = true,
+                "reverse" => style.reverse = true,
+                "strikethrough" => style.strikethrough = true,
+                "on" => {
+                    let color: String = tokens
+                        .next()
+                        .ok_or_else(|| "'on' must be followed by a color".to_string())?;
+                    style.bg = parse_color(color, &mut tokens)?;
+                }
+                _ => style.fg = parse_color(token, &mut tokens)?,
+            }
+        }
+
+        Ok(style)
+    }
+}
+
+impl Style {
+    /// Wraps text in the escape codes for this style
+    ///
+    /// Colors are converted to what the terminal supports; with `NO_COLOR`
+    /// only text attributes are applied.
+    ///
+    /// # Arguments
+    /// * `text` - Text to style
+    pub fn paint(&self, text: &str) -> String {
+        self.paint_with(text, ColorLevel::current())
+    }
+
+    /// Wraps text in the escape codes for this style at a given color level
+    ///
+    /// # Arguments
+    /// * `text` - Text to style
+    /// * `level` - Colors the terminal can display
+    pub fn paint_with(&self, text: &str, level: ColorLevel) -> String {
+        let mut codes: Vec<String> = Vec::new();
+        let attributes: [(bool, &str); 7] = [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.blink, "5"),
+            (self.reverse, "7"),
+            (self.strikethrough, "9"),
+        ];
+        codes.extend(
+            attributes
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, code)| code.to_string()),
+        );
+        if let Some(fg) = self.fg.and_then(|c| c.downgrade(level)) {
+            codes.push(fg.sgr(false));
+        }
+        if let Some(bg) = self.bg.and_then(|c| c.downgrade(level)) {
+            codes.push(bg.sgr(true));
+        }
+
+        if codes.is_empty() || text.is_empty() {
+            text.to_string()
+        } else {
+            format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+        }
+    }
+
+    /// Returns the style used to fill a powerline segment
+    ///
+    /// Styles without a background are inverted: the foreground color becomes
+    /// the fill and the text is drawn in black or white, whichever contrasts
+    /// more.
+    pub fn powerline_fill(&self) -> Style {
+        if self.bg.is_some() {
+            return self.clone();
+        }
+        let bg: Color = self.fg.unwrap_or(Color::Basic(8));
+        let (r, g, b) = bg.to_rgb();
+        let luminance: u32 = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+        Style {
+            fg: Some(Color::Basic(if luminance > 140 { 0 } else { 15 })),
+            bg: Some(bg),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the style of a separator between two powerline segments
+    ///
+    /// # Arguments
+    /// * `from` - Fill of the segment before the separator
+    /// * `to` - Fill of the segment after it, None after the last segment
+    pub fn powerline_separator(from: &Style, to: Option<&Style>) -> Style {
+        Style {
+            fg: from.bg,
+            bg: to.and_then(|style| style.bg),
+            ..Style::default()
+        }
+    }
+}
+
+/// Applies a style spec to text
+///
+/// Specs are validated when the configuration loads, so an invalid spec here
+/// leaves the text unstyled.
+///
+/// # Arguments
+/// * `text` - Text to style
+/// * `spec` - Style spec such as `"bold cyan"`
+pub fn paint(text: &str, spec: &str) -> String {
+    match spec.parse::<Style>() {
+        Ok(style) => style.paint(text),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// Splits a spec on whitespace, keeping `rgb(r, g, b)` together
+fn tokenize(spec: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current: String = String::new();
+    let mut depth: usize = 0;
+
+    for c in spec.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                current.push(c);
+            }
+            c if c.is_whitespace() && depth == 0 => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c if c.is_whitespace() => {}
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Remaining tokens of a style spec
+type Tokens = IntoIter<String>;
+
+/// Parses a color token, taking the next token for two-word names like
+/// `bright blue`
+///
+/// # Returns
+/// * `Result<Option<Color>, String>` - The color, None for `none`/`default`
+fn parse_color(token: String, tokens: &mut Tokens) -> Result<Option<Color>, String> {
+    match token.as_str() {
+        "none" | "default" => Ok(None),
+        "bright" => {
+            let name: String = tokens
+                .next()
+                .ok_or_else(|| "'bright' must be followed by a color name".to_string())?;
+            match Color::parse(&name) {
+                Some(Color::Basic(n)) if n < 8 => Ok(Some(Color::Basic(n + 8))),
+                _ => Err(format!("unknown color 'bright {}'", name)),
+            }
+        }
+        _ => Color::parse(&token)
+            .map(Some)
+            .ok_or_else(|| format!("unknown color or style '{}'", token)),
+    }
+}
+
+/// Parses `rgb` or `rrggbb` hex digits
+fn parse_hex(hex: &str) -> Option<Color> {
+    let digits: Vec<u8> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    match digits[..] {
+        [r, g, b] => Some(Color::Rgb(r * 17, g * 17, b * 17)),
+        [r1, r2, g1, g2, b1, b2] => Some(Color::Rgb(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2)),
+        _ => None,
+    }
+}
+
+/// Finds the closest color in the 256-color palette
+fn rgb_to_fixed(r: u8, g: u8, b: u8) -> u8 {
+    // Grays map onto the finer grayscale ramp
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((r as u16 - 8) * 24 / 241) as u8,
+        };
+    }
+    let level = |v: u8| -> u8 {
+        match v {
+            0..=47 => 0,
+            48..=114 => 1,
+            _ => (v - 35) / 40,
+        }
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// Returns the RGB value of a 256-color palette entry
+fn fixed_to_rgb(n: u8) -> (u8, u8, u8) {
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match n {
+        0..=15 => BASIC_RGB[n as usize],
+        16..=231 => {
+            let n: u8 = n - 16;
+            (
+                CUBE[(n / 36) as usize],
+                CUBE[(n / 6 % 6) as usize],
+                CUBE[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let gray: u8 = 8 + (n - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// Finds the closest of the 16 standard colors
+fn rgb_to_basic(r: u8, g: u8, b: u8) -> u8 {
+    let distance = |(cr, cg, cb): (u8, u8, u8)| -> i32 {
+        let (dr, dg, db) = (
+            r as i32 - cr as i32,
+            g as i32 - cg as i32,
+            b as i32 - cb as i32,
+        );
+        dr * dr + dg * dg + db * db
+    };
+    (0..16u8)
+        .min_by_key(|&n| distance(BASIC_RGB[n as usize]))
+        .unwrap_or(7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_specs() {
+        let style: Style = "Bold #ff8800 on #202020".parse().unwrap();
+        assert_eq!(
+            style,
+            Style {
+                fg: Some(Color::Rgb(255, 136, 0)),
+                bg: Some(Color::Rgb(32, 32, 32)),
+                bold: true,
+                ..Style::default()
+            }
+        );
+        assert_eq!("".parse::<Style>(), Ok(Style::default()));
+        assert_eq!(
+            "red on none".parse::<Style>().map(|s| (s.fg, s.bg)),
+            Ok((Some(Color::Basic(1)), None))
+        );
+    }
+
+    #[test]
+    fn parses_colors() {
+        let fg = |spec: &str| spec.parse::<Style>().map(|style| style.fg);
+        assert_eq!(fg("bright blue"), Ok(Some(Color::Basic(12))));
+        assert_eq!(fg("bright_red"), Ok(Some(Color::Basic(9))));
+        assert_eq!(fg("purple"), Ok(Some(Color::Basic(5))));
+        assert_eq!(fg("gray"), Ok(Some(Color::Basic(8))));
+        assert_eq!(fg("#f80"), Ok(Some(Color::Rgb(255, 136, 0))));
+        assert_eq!(fg("rgb(1, 2, 3)"), Ok(Some(Color::Rgb(1, 2, 3))));
+        assert_eq!(fg("208"), Ok(Some(Color::Fixed(208))));
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        assert!("blurple".parse::<Style>().is_err());
+        assert!("bold on".parse::<Style>().is_err());
+        assert!("bright #fff".parse::<Style>().is_err());
+        assert!("#ff88".parse::<Style>().is_err());
+        assert!("rgb(1, 2)".parse::<Style>().is_err());
+    }
+
+    #[test]
+    fn downgrades_colors() {
+        let orange: Color = Color::Rgb(255, 136, 0);
+        assert_eq!(orange.downgrade(ColorLevel::TrueColor), Some(orange));
+        assert_eq!(
+            orange.downgrade(ColorLevel::Ansi256),
+            Some(Color::Fixed(208))
+        );
+        assert_eq!(orange.downgrade(ColorLevel::Basic), Some(Color::Basic(3)));
+        assert_eq!(orange.downgrade(ColorLevel::None), None);
+        assert_eq!(
+            Color::Rgb(128, 128, 128).downgrade(ColorLevel::Ansi256),
+            Some(Color::Fixed(243))
+        );
+        assert_eq!(
+            Color::Fixed(196).downgrade(ColorLevel::Basic),
+            Some(Color::Basic(9))
+        );
+    }
+
+    #[test]
+    fn paints_at_each_level() {
+        let style: Style = "bold #ff8800 on bright_blue".parse().unwrap();
+        assert_eq!(
+            style.paint_with("hi", ColorLevel::TrueColor),
+            "\x1b[1;38;2;255;136;0;104mhi\x1b[0m"
+        );
+        assert_eq!(
+            style.paint_with("hi", ColorLevel::Ansi256),
+            "\x1b[1;38;5;208;104mhi\x1b[0m"
+        );
+        assert_eq!(style.paint_with("hi", ColorLevel::None), "\x1b[1mhi\x1b[0m");
+        assert_eq!(
+            Style::default().paint_with("hi", ColorLevel::TrueColor),
+            "hi"
+        );
+    }
+}
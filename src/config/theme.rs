@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 
 /// Theme configuration for the shell's visual appearance
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Each field is a style spec such as `"bold #ff8800 on #202020"`. Missing
+/// fields fall back to the full theme so older configs keep loading.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Theme {
     /// Color for the main prompt text
     pub prompt_color: String,
@@ -17,6 +21,20 @@ pub struct Theme {
     pub directory_color: String,
     /// Color for displaying git branch information
     pub git_branch_color: String,
+    /// Color for the short SHA or tag shown on a detached HEAD
+    pub git_detached_color: String,
+    /// Color for the staged file count
+    pub git_staged_color: String,
+    /// Color for the unstaged file count
+    pub git_unstaged_color: String,
+    /// Color for the untracked file count
+    pub git_untracked_color: String,
+    /// Color for commits ahead/behind the upstream branch
+    pub git_ahead_behind_color: String,
+    /// Color for the stash count
+    pub git_stash_color: String,
+    /// Color for an in-progress rebase, merge or cherry-pick
+    pub git_operation_color: String,
     /// Color for displaying time
     pub time_color: String,
     /// Color for displaying command text
@@ -32,6 +50,32 @@ pub struct Theme {
 }
 
 impl Theme {
+    /// Creates a theme with every style empty, which leaves text unstyled
+    pub fn plain() -> Self {
+        Theme {
+            prompt_color: String::new(),
+            error_color: String::new(),
+            success_color: String::new(),
+            username_color: String::new(),
+            hostname_color: String::new(),
+            directory_color: String::new(),
+            git_branch_color: String::new(),
+            git_detached_color: String::new(),
+            git_staged_color: String::new(),
+            git_unstaged_color: String::new(),
+            git_untracked_color: String::new(),
+            git_ahead_behind_color: String::new(),
+            git_stash_color: String::new(),
+            git_operation_color: String::new(),
+            time_color: String::new(),
+            command_color: String::new(),
+            args_color: String::new(),
+            path_color: String::new(),
+            accent_color: String::new(),
+            separator_color: String::new(),
+        }
+    }
+
     /// Creates a minimal theme with basic monochrome colors
     pub fn minimal() -> Self {
         Theme {
@@ -42,6 +86,13 @@ impl Theme {
             hostname_color: "white".to_string(),
             directory_color: "cyan".to_string(),
             git_branch_color: "white".to_string(),
+            git_detached_color: "white".to_string(),
+            git_staged_color: "white".to_string(),
+            git_unstaged_color: "white".to_string(),
+            git_untracked_color: "white".to_string(),
+            git_ahead_behind_color: "white".to_string(),
+            git_stash_color: "white".to_string(),
+            git_operation_color: "red".to_string(),
             time_color: "white".to_string(),
             command_color: "white".to_string(),
             args_color: "white".to_string(),
@@ -61,6 +112,13 @@ impl Theme {
             hostname_color: "blue".to_string(),
             directory_color: "magenta".to_string(),
             git_branch_color: "green".to_string(),
+            git_detached_color: "yellow".to_string(),
+            git_staged_color: "green".to_string(),
+            git_unstaged_color: "red".to_string(),
+            git_untracked_color: "bright black".to_string(),
+            git_ahead_behind_color: "cyan".to_string(),
+            git_stash_color: "magenta".to_string(),
+            git_operation_color: "bright red".to_string(),
             time_color: "white".to_string(),
             command_color: "bright cyan".to_string(),
             args_color: "bright white".to_string(),
@@ -80,6 +138,13 @@ impl Theme {
             hostname_color: "bright blue".to_string(),
             directory_color: "bright magenta".to_string(),
             git_branch_color: "bright green".to_string(),
+            git_detached_color: "bright yellow".to_string(),
+            git_staged_color: "bright green".to_string(),
+            git_unstaged_color: "bright red".to_string(),
+            git_untracked_color: "bright black".to_string(),
+            git_ahead_behind_color: "bright cyan".to_string(),
+            git_stash_color: "bright magenta".to_string(),
+            git_operation_color: "bright red".to_string(),
             time_color: "bright white".to_string(),
             command_color: "bright cyan".to_string(),
             args_color: "bright white".to_string(),
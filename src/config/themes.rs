@@ -0,0 +1,211 @@
+use super::source::{is_toml, line_column};
+use super::style::Style;
+use super::Theme;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Theme files shipped with flux, by name
+const BUNDLED: [(&str, &str); 5] = [
+    ("catppuccin", include_str!("themes/catppuccin.toml")),
+    ("dracula", include_str!("themes/dracula.toml")),
+    ("gruvbox", include_str!("themes/gruvbox.toml")),
+    ("nord", include_str!("themes/nord.toml")),
+    ("solarized-dark", include_str!("themes/solarized-dark.toml")),
+];
+
+/// A named theme, bundled with flux or read from the themes directory
+///
+/// Theme files are TOML or JSON with the `Theme` fields at the top level and
+/// an optional `description`. Missing fields fall back to the full theme.
+#[derive(Debug, Clone)]
+pub struct ThemeFile {
+    /// Name used with `flux theme apply`, the file name without extension
+    pub name: String,
+    /// One-line description shown by `flux theme list`
+    pub description: String,
+    /// The theme itself
+    pub theme: Theme,
+    /// File the theme was read from, None for bundled themes
+    pub path: Option<PathBuf>,
+}
+
+impl ThemeFile {
+    /// Lists every available theme, sorted by name
+    ///
+    /// Files in the themes directory replace bundled themes of the same name.
+    ///
+    /// # Arguments
+    /// * `themes_dir` - Directory holding user theme files
+    ///
+    /// # Returns
+    /// * `(Vec<ThemeFile>, Vec<String>)` - The themes, and errors for theme
+    ///   files that could not be read
+    pub fn all(themes_dir: &Path) -> (Vec<ThemeFile>, Vec<String>) {
+        let mut themes: BTreeMap<String, ThemeFile> = BTreeMap::new();
+        let mut errors: Vec<String> = Vec::new();
+
+        for theme in Self::bundled() {
+            themes.insert(theme.name.clone(), theme);
+        }
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(themes_dir)
+            .map(|entries| entries.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default();
+        paths.retain(|p| {
+            p.extension()
+                .is_some_and(|ext| ext == "toml" || ext == "json")
+        });
+        paths.sort();
+        for path in paths {
+            match Self::read(&path) {
+                Ok(theme) => {
+                    themes.insert(theme.name.clone(), theme);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        (themes.into_values().collect(), errors)
+    }
+
+    /// Finds a theme by name
+    ///
+    /// # Arguments
+    /// * `themes_dir` - Directory holding user theme files
+    /// * `name` - Theme name
+    ///
+    /// # Returns
+    /// * `Result<ThemeFile, String>` - The theme, or an error if it does not
+    ///   exist or its file is invalid
+    pub fn find(themes_dir: &Path, name: &str) -> Result<ThemeFile, String> {
+        for ext in ["toml", "json"] {
+            let path: PathBuf = themes_dir.join(format!("{}.{}", name, ext));
+            if path.exists() {
+                return Self::read(&path);
+            }
+        }
+        Self::bundled()
+            .into_iter()
+            .find(|theme| theme.name == name)
+            .ok_or_else(|| format!("unknown theme '{}'; run `flux theme list`", name))
+    }
+
+    /// Returns the themes shipped with flux, including the three presets
+    fn bundled() -> Vec<ThemeFile> {
+        let presets: [(&str, &str, Theme); 3] = [
+            (
+                "minimal",
+                "Monochrome with a cyan directory",
+                Theme::minimal(),
+            ),
+            (
+                "full",
+                "Distinct colors for every element (default)",
+                Theme::full(),
+            ),
+            ("powerline", "Bright colors", Theme::powerline()),
+        ];
+        let mut themes: Vec<ThemeFile> = presets
+            .into_iter()
+            .map(|(name, description, theme)| ThemeFile {
+                name: name.to_string(),
+                description: description.to_string(),
+                theme,
+                path: None,
+            })
+            .collect();
+
+        themes.extend(BUNDLED.iter().map(|(name, contents)| {
+            Self::parse(name, contents, true).expect("bundled themes are valid")
+        }));
+        themes
+    }
+
+    /// Reads a theme file
+    fn read(path: &Path) -> Result<ThemeFile, String> {
+        let contents: String =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let name: String = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut theme: ThemeFile = Self::parse(&name, &contents, is_toml(path, &contents))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        theme.path = Some(path.to_path_buf());
+        Ok(theme)
+    }
+
+    /// Parses and validates the contents of a theme file
+    ///
+    /// Unlike the main configuration, unknown keys and invalid styles are
+    /// errors, since a theme file holds nothing else.
+    fn parse(name: &str, contents: &str, toml: bool) -> Result<ThemeFile, String> {
+        let mut table: Map<String, Value> = if toml {
+            toml::from_str(contents).map_err(|e: toml::de::Error| {
+                let (line, column) = line_column(contents, e.span().map_or(0, |span| span.start));
+                format!("{}:{}: {}", line, column, e.message().trim())
+            })?
+        } else {
+            serde_json::from_str(contents).map_err(|e| e.to_string())?
+        };
+        let description: String = match table.remove("description") {
+            Some(Value::String(description)) => description,
+            Some(_) => return Err("description: expected a string".to_string()),
+            None => String::new(),
+        };
+
+        let mut unknown: Vec<String> = Vec::new();
+        let mut on_ignored = |path: serde_ignored::Path| unknown.push(path.to_string());
+        let deserializer = serde_ignored::Deserializer::new(Value::Object(table), &mut on_ignored);
+        let theme: Theme = serde_path_to_error::deserialize(deserializer)
+            .map_err(|e| format!("{}: {}", e.path(), e.inner()))?;
+        if let Some(key) = unknown.first() {
+            return Err(format!("{}: unknown key", key));
+        }
+
+        if let Ok(Value::Object(fields)) = serde_json::to_value(&theme) {
+            for (key, spec) in &fields {
+                if let Some(Err(e)) = spec.as_str().map(str::parse::<Style>) {
+                    return Err(format!("{}: {}", key, e));
+                }
+            }
+        }
+
+        Ok(ThemeFile {
+            name: name.to_string(),
+            description,
+            theme,
+            path: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_bundled_theme() {
+        for (name, contents) in BUNDLED {
+            let theme: ThemeFile = ThemeFile::parse(name, contents, true)
+                .unwrap_or_else(|e| panic!("themes/{}.toml: {}", name, e));
+            assert_eq!(theme.name, name);
+            assert!(!theme.description.is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bundles_the_presets_and_theme_files() {
+        let names: Vec<String> = ThemeFile::bundled()
+            .into_iter()
+            .map(|theme| theme.name)
+            .collect();
+        assert_eq!(names.len(), 3 + BUNDLED.len());
+        for (name, _) in BUNDLED {
+            assert!(names.iter().any(|n| n == name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modification time of each watched file, `None` if it doesn't exist
+type Snapshot = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// Watches configuration files for changes in a background thread
+///
+/// The shell checks `take_changed` before drawing each prompt, so edits are
+/// applied at the next prompt. The thread exits when the watcher is dropped.
+pub struct ConfigWatcher {
+    files: Arc<Mutex<Vec<PathBuf>>>,
+    changed: Arc<AtomicBool>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching a set of files
+    ///
+    /// # Arguments
+    /// * `files` - Configuration files to watch, including includes
+    pub fn spawn(files: Vec<PathBuf>) -> Self {
+        let watcher: ConfigWatcher = ConfigWatcher {
+            files: Arc::new(Mutex::new(files)),
+            changed: Arc::new(AtomicBool::new(false)),
+        };
+
+        let files: Weak<Mutex<Vec<PathBuf>>> = Arc::downgrade(&watcher.files);
+        let changed: Weak<AtomicBool> = Arc::downgrade(&watcher.changed);
+        thread::spawn(move || {
+            let mut last: Option<Snapshot> = None;
+            loop {
+                let (Some(files), Some(changed)) = (files.upgrade(), changed.upgrade()) else {
+                    return;
+                };
+                let paths: Vec<PathBuf> = files.lock().map(|f| f.clone()).unwrap_or_default();
+                let current: Snapshot = snapshot(&paths);
+
+                // A new file list (after a reload) only sets a new baseline
+                if let Some(last) = &last {
+                    let same_files: bool = last.iter().map(|(p, _)| p).eq(paths.iter());
+                    if same_files && *last != current {
+                        changed.store(true, Ordering::SeqCst);
+                    }
+                }
+                last = Some(current);
+
+                drop((files, changed));
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        watcher
+    }
+
+    /// Replaces the set of watched files
+    ///
+    /// # Arguments
+    /// * `files` - Configuration files to watch from now on
+    pub fn watch(&self, files: Vec<PathBuf>) {
+        if let Ok(mut watched) = self.files.lock() {
+            *watched = files;
+        }
+    }
+
+    /// Returns whether a watched file changed since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Reads the modification time of each file
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    paths
+        .iter()
+        .map(|path| {
+            let modified: Option<SystemTime> = fs::metadata(path).and_then(|m| m.modified()).ok();
+            (path.clone(), modified)
+        })
+        .collect()
+}
@@ -6,10 +6,31 @@ mod utils;
 
 use shell::Shell;
 use utils::env::{list_internal_envs, remove_internal_env, store_internal_env};
+use utils::paths;
 
 /// Main entry point for the Flux shell
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // `--config <path>` may appear anywhere and applies to every subcommand
+    if let Some(index) = args
+        .iter()
+        .position(|a| a == "--config" || a.starts_with("--config="))
+    {
+        let flag: String = args.remove(index);
+        let path: Option<String> = match flag.strip_prefix("--config=") {
+            Some(path) => Some(path.to_string()),
+            None if index < args.len() => Some(args.remove(index)),
+            None => None,
+        };
+        match path {
+            Some(path) => paths::set_config_file(std::path::PathBuf::from(path)),
+            None => {
+                eprintln!("--config requires a path");
+                std::process::exit(2);
+            }
+        }
+    }
     if args.len() > 1 {
         match args[1].as_str() {
             "-h" | "--help" => {
@@ -20,7 +41,10 @@ fn main() {
                 println!("\nOPTIONS:");
                 println!("  -h, --help     Display this help message");
                 println!("  -v, --version  Display version information");
+                println!("  --config PATH  Use PATH as the configuration file");
                 println!("  config         Reconfigure the shell");
+                println!("  config <cmd>   check, get, set, edit, reset or export settings");
+                println!("  theme <cmd>    list, preview or apply themes");
                 println!("  plugin         Plugin management commands");
                 println!("  env            Environment variable management");
                 println!("\nENV SUBCOMMANDS:");
@@ -121,8 +145,81 @@ fn main() {
                 }
                 return;
             }
+            "config" if args.len() > 2 => {
+                use config::FluxConfig;
+
+                let config_path = paths::config_file();
+                let print_warnings = |warnings: &[String]| {
+                    for warning in warnings {
+                        eprintln!("warning: {}", warning);
+                    }
+                };
+                let result: Result<(), String> = match args[2].as_str() {
+                    "check" => {
+                        let config_path = args
+                            .get(3)
+                            .map(std::path::PathBuf::from)
+                            .unwrap_or(config_path);
+                        FluxConfig::check(&config_path).map(|loaded| {
+                            print_warnings(&loaded.warnings);
+                            if !loaded.warnings.is_empty() {
+                                std::process::exit(1);
+                            }
+                            println!("{}: OK", config_path.display());
+                        })
+                    }
+                    "get" if args.len() == 4 => FluxConfig::get_key(&config_path, &args[3])
+                        .map(|value| println!("{}", value)),
+                    "set" if args.len() == 5 => {
+                        FluxConfig::set_key(&config_path, &args[3], &args[4])
+                            .map(|warnings| print_warnings(&warnings))
+                    }
+                    "edit" => FluxConfig::edit_file(&config_path)
+                        .map(|warnings| print_warnings(&warnings)),
+                    "reset" => {
+                        let preset: &str = match args.get(3).map(String::as_str) {
+                            Some("--preset") => args.get(4).map(String::as_str).unwrap_or(""),
+                            Some(preset) => preset.strip_prefix("--preset=").unwrap_or(""),
+                            None => "full",
+                        };
+                        FluxConfig::reset(&config_path, preset).map(|backup| {
+                            if let Some(backup) = backup {
+                                println!("Previous configuration saved to {}", backup.display());
+                            }
+                            println!("Configuration reset to the {} preset", preset);
+                        })
+                    }
+                    "export" => {
+                        let format: &str = match args.get(3).map(String::as_str) {
+                            Some("--format") => args.get(4).map(String::as_str).unwrap_or(""),
+                            Some(format) => format.strip_prefix("--format=").unwrap_or(""),
+                            None => "json",
+                        };
+                        FluxConfig::check(&config_path)
+                            .and_then(|loaded| loaded.config.serialize(format))
+                            .map(|contents| println!("{}", contents.trim_end()))
+                    }
+                    _ => {
+                        println!("Usage: flux config [command]");
+                        println!("\nCommands:");
+                        println!("  (none)                      Re-run the setup wizard");
+                        println!("  check [path]                Validate the configuration");
+                        println!("  get <key>                   Print a setting, e.g. theme.prompt_color");
+                        println!("  set <key> <value>           Change a setting in the config file");
+                        println!("  edit                        Edit the config file and validate on save");
+                        println!("  reset --preset <name>       Replace the config with minimal, full or powerline");
+                        println!("  export [--format json|toml] Print the effective configuration");
+                        return;
+                    }
+                };
+                if let Err(e) = result {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
             "config" => {
-                let config_path = Shell::get_config_path();
+                let config_path = paths::config_file();
                 if let Err(e) = std::fs::remove_file(&config_path) {
                     if !matches!(e.kind(), std::io::ErrorKind::NotFound) {
                         eprintln!("Failed to remove config: {}", e);
@@ -131,29 +228,149 @@ fn main() {
                 }
                 println!("Reconfiguring Flux Shell...");
                 let _config = config::FluxConfig::load(&config_path);
-                println!("Reconfiguration complete. Please restart the shell to apply changes.");
+                println!("Reconfiguration complete. Run `reload` in open shells to apply changes.");
+            }
+            "theme" => {
+                use config::{paint, FluxConfig, ThemeFile};
+
+                let config_path = paths::config_file();
+                let themes_dir = paths::themes_dir();
+                let current: Option<FluxConfig> = FluxConfig::check(&config_path)
+                    .ok()
+                    .map(|loaded| loaded.config);
+                let result: Result<(), String> = match args.get(2).map(String::as_str) {
+                    Some("list") => {
+                        let (themes, errors) = ThemeFile::all(&themes_dir);
+                        for theme in &themes {
+                            let active: bool =
+                                current.as_ref().is_some_and(|c| c.theme == theme.theme);
+                            let source: String = theme
+                                .path
+                                .as_ref()
+                                .map(|p| format!("  ({})", p.display()))
+                                .unwrap_or_default();
+                            println!(
+                                "{} {:<16}{}{}",
+                                if active { "*" } else { " " },
+                                theme.name,
+                                theme.description,
+                                source
+                            );
+                        }
+                        for error in errors {
+                            eprintln!("warning: {}", error);
+                        }
+                        Ok(())
+                    }
+                    Some("preview") => {
+                        let selected: Result<Vec<ThemeFile>, String> = if args.len() > 3 {
+                            args[3..]
+                                .iter()
+                                .map(|name| ThemeFile::find(&themes_dir, name))
+                                .collect()
+                        } else {
+                            let (themes, errors) = ThemeFile::all(&themes_dir);
+                            for error in errors {
+                                eprintln!("warning: {}", error);
+                            }
+                            Ok(themes)
+                        };
+                        selected.map(|selected| {
+                            let config: FluxConfig = current.unwrap_or_default();
+                            let themes: Vec<config::Theme> =
+                                selected.iter().map(|t| t.theme.clone()).collect();
+                            let prompts: Vec<String> = shell::preview_themes(&config, &themes);
+                            for (file, prompt) in selected.iter().zip(prompts) {
+                                let theme: &config::Theme = &file.theme;
+                                let error: &str = &theme.error_color;
+                                let success: &str = &theme.success_color;
+                                println!(
+                                    "{} {}",
+                                    paint(&file.name, "bold"),
+                                    paint(&file.description, "dim")
+                                );
+                                println!(
+                                    "  {}{} {} {}",
+                                    prompt,
+                                    paint("git", &theme.command_color),
+                                    paint("add", &theme.args_color),
+                                    paint("src/main.rs", &theme.path_color)
+                                );
+                                println!(
+                                    "  {} {}",
+                                    paint("Error:", error),
+                                    paint("command not found: gti", error)
+                                );
+                                println!(
+                                    "  {} {}",
+                                    paint("Success:", success),
+                                    paint("Configuration reloaded", success)
+                                );
+                                println!();
+                            }
+                        })
+                    }
+                    Some("apply") if args.len() == 4 => ThemeFile::find(&themes_dir, &args[3])
+                        .and_then(|theme| FluxConfig::apply_theme(&config_path, &theme.theme))
+                        .map(|warnings| {
+                            for warning in warnings {
+                                eprintln!("warning: {}", warning);
+                            }
+                            println!(
+                                "Applied the {} theme. Run `reload` in open shells to apply changes.",
+                                args[3]
+                            );
+                        }),
+                    _ => {
+                        println!("Usage: flux theme <command>");
+                        println!("\nCommands:");
+                        println!(
+                            "  list              List bundled themes and those in {}",
+                            themes_dir.display()
+                        );
+                        println!("  preview [name...] Show the prompt and messages in each theme");
+                        println!("  apply <name>      Replace the theme in the config file");
+                        return;
+                    }
+                };
+                if let Err(e) = result {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+                return;
             }
             "plugin" => {
                 if args.len() < 3 {
                     println!("Usage: flux plugin <command> [args...]");
                     println!("\nCommands:");
-                    println!("  install <git-url>  Install a plugin from git");
+                    println!("  install <source>   Install a plugin from a directory, .tar.gz, git URL[@tag|rev] or registry name[@version]");
                     println!("  init <name>        Create a new plugin project");
-                    println!("  list               List installed plugins");
+                    println!("  list [--timings]   List installed plugins, optionally with how long each takes to load");
+                    println!("  enable <name>      Load a disabled plugin again");
+                    println!("  disable <name>     Stop loading a plugin without removing it");
                     println!("  remove <name>      Remove an installed plugin");
                     println!("  update <name>      Update an installed plugin");
                     return;
                 }
 
-                let plugin_manager: plugin::PluginManager =
-                    plugin::PluginManager::new_without_loading();
+                let mut plugin_manager: plugin::PluginManager =
+                    plugin::PluginManager::new(paths::plugin_dir(), paths::plugin_build_dir());
+                // Builtins and aliases, to show which plugin commands they
+                // take, and the registry plugin names are looked up in
+                let config: config::FluxConfig = config::FluxConfig::check(&paths::config_file())
+                    .map(|loaded| loaded.config)
+                    .unwrap_or_default();
+                plugin_manager.set_reserved(shell::reserved_names(&config));
+                plugin_manager.set_registry(config.plugin_registry.clone());
+                plugin_manager.set_trust(shell::trust_policy(&config));
+                plugin_manager.set_capabilities(&config.plugin_permissions);
                 match args[2].as_str() {
                     "install" => {
                         if args.len() != 4 {
-                            println!("Usage: flux plugin install <git-url>");
+                            println!("Usage: flux plugin install <dir | archive.tar.gz | git-url[@tag|rev] | name[@version]>");
                             return;
                         }
-                        if let Err(e) = plugin_manager.install_from_git(&args[3]) {
+                        if let Err(e) = plugin_manager.install(&args[3]) {
                             eprintln!("Failed to install plugin: {}", e);
                         }
                     }
@@ -167,10 +384,27 @@ fn main() {
                         }
                     }
                     "list" => {
-                        if let Err(e) = plugin_manager.list_plugins() {
+                        let timings: bool = match args.get(3).map(String::as_str) {
+                            None => false,
+                            Some("--timings") if args.len() == 4 => true,
+                            _ => {
+                                println!("Usage: flux plugin list [--timings]");
+                                return;
+                            }
+                        };
+                        if let Err(e) = plugin_manager.list_plugins(timings) {
                             eprintln!("Failed to list plugins: {}", e);
                         }
                     }
+                    "enable" | "disable" => {
+                        if args.len() != 4 {
+                            println!("Usage: flux plugin {} <name>", args[2]);
+                            return;
+                        }
+                        if let Err(e) = plugin_manager.set_enabled(&args[3], args[2] == "enable") {
+                            eprintln!("Failed to {} plugin: {}", args[2], e);
+                        }
+                    }
                     "remove" => {
                         if args.len() != 4 {
                             println!("Usage: flux plugin remove <name>");
@@ -191,7 +425,7 @@ fn main() {
                     }
                     _ => {
                         println!(
-                            "Unknown plugin command. Use: install, init, list, remove, or update"
+                            "Unknown plugin command. Use: install, init, list, enable, disable, remove, or update"
                         );
                     }
                 }
@@ -0,0 +1,159 @@
+use console::Term;
+use flux_plugin::{Host, Stream, Terminal};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+
+/// Held for writing while the shell changes its environment, and for
+/// reading while a program is started
+///
+/// Starting a program reads the whole environment, which `set_var` must not
+/// change underneath it; prompt workers start `git` on their own threads.
+static ENVIRONMENT: RwLock<()> = RwLock::new(());
+
+/// Sets an environment variable, or removes it when `value` is `None`
+///
+/// # Arguments
+/// * `name` - Name of the variable
+/// * `value` - New value of the variable
+pub fn set_env_var(name: &str, value: Option<&str>) {
+    let _guard = ENVIRONMENT.write().unwrap_or_else(PoisonError::into_inner);
+    match value {
+        Some(value) => std::env::set_var(name, value),
+        None => std::env::remove_var(name),
+    }
+}
+
+/// Runs `start` without letting the environment change meanwhile
+///
+/// # Arguments
+/// * `start` - Starts a program, or otherwise reads the environment
+pub fn with_env_unchanged<T>(start: impl FnOnce() -> T) -> T {
+    let _guard = ENVIRONMENT.read().unwrap_or_else(PoisonError::into_inner);
+    start()
+}
+
+/// What a plugin command runs with, and the changes it asks the shell to make
+///
+/// Filled in by the shell for each command and handed to the plugin whatever
+/// its runtime. Variables and the directory are only changed in the shell
+/// by `apply`, once the command has finished.
+#[derive(Default)]
+pub struct ExecutionContext {
+    cwd: PathBuf,
+    env: BTreeMap<String, String>,
+    terminal: Terminal,
+    /// Output of the previous command in a pipe; `None` reads the terminal
+    stdin: Option<io::Cursor<Vec<u8>>>,
+    /// Output kept for the next command in a pipe; `None` writes to the
+    /// terminal
+    stdout: Option<Vec<u8>>,
+    /// Variables to set, or remove when `None`, in order
+    env_changes: Vec<(String, Option<String>)>,
+    /// Directory to change to
+    new_cwd: Option<PathBuf>,
+}
+
+impl ExecutionContext {
+    /// Captures the shell's current directory, environment and terminal
+    ///
+    /// # Arguments
+    /// * `stdin` - Output of the previous command when piped into this one
+    /// * `capture` - Whether to keep the output for the next command instead
+    ///   of writing it to the terminal
+    pub fn new(stdin: Option<Vec<u8>>, capture: bool) -> Self {
+        let (rows, columns): (u16, u16) = Term::stdout().size_checked().unwrap_or((0, 0));
+        ExecutionContext {
+            cwd: std::env::current_dir().unwrap_or_default(),
+            // Plugins see text values only; variables that are not UTF-8 are
+            // left out rather than mangled
+            env: std::env::vars_os()
+                .filter_map(|(name, value)| {
+                    Some((name.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect(),
+            terminal: Terminal {
+                stdin_tty: stdin.is_none() && io::stdin().is_terminal(),
+                stdout_tty: !capture && io::stdout().is_terminal(),
+                columns,
+                rows,
+            },
+            stdin: stdin.map(io::Cursor::new),
+            stdout: capture.then(Vec::new),
+            env_changes: Vec::new(),
+            new_cwd: None,
+        }
+    }
+
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn terminal(&self) -> Terminal {
+        self.terminal
+    }
+
+    /// Input piped into the command, if any, for runtimes that send it whole
+    pub fn take_stdin(&mut self) -> Option<Vec<u8>> {
+        self.stdin.take().map(io::Cursor::into_inner)
+    }
+
+    /// Applies the requested changes to the shell
+    ///
+    /// # Returns
+    /// * `Option<Vec<u8>>` - The command's output, if it was captured
+    pub fn apply(self) -> Result<Option<Vec<u8>>, String> {
+        for (name, value) in &self.env_changes {
+            set_env_var(name, value.as_deref());
+        }
+        if let Some(dir) = &self.new_cwd {
+            std::env::set_current_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        Ok(self.stdout)
+    }
+}
+
+impl Host for ExecutionContext {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(input) => input.read(buf),
+            None => io::stdin().read(buf),
+        }
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> io::Result<usize> {
+        match (stream, &mut self.stdout) {
+            (Stream::Stdout, Some(captured)) => captured.write(data),
+            (Stream::Stdout, None) => {
+                let mut stdout = io::stdout();
+                stdout.write_all(data).and_then(|_| stdout.flush())?;
+                Ok(data.len())
+            }
+            (Stream::Stderr, _) => io::stderr().write(data),
+        }
+    }
+
+    fn set_var(&mut self, name: &str, value: Option<&str>) {
+        match value {
+            Some(value) => self.env.insert(name.to_string(), value.to_string()),
+            None => self.env.remove(name),
+        };
+        self.env_changes
+            .push((name.to_string(), value.map(String::from)));
+    }
+
+    fn set_current_dir(&mut self, path: &Path) -> Result<(), String> {
+        let path: PathBuf = self.cwd.join(path);
+        if !path.is_dir() {
+            return Err(format!("{}: not a directory", path.display()));
+        }
+        self.cwd = path.clone();
+        self.new_cwd = Some(path);
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the lockfile in the plugin directory
+pub const LOCK_FILE: &str = "plugins.lock";
+
+/// What each installed plugin was installed from, written on every install
+///
+/// `source` is what `flux plugin update` installs from again, so a plugin
+/// from the registry or an unpinned repository updates to the newest version,
+/// while `resolved` records exactly what is installed now.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub plugins: BTreeMap<String, LockedPlugin>,
+}
+
+/// One installed plugin in the lockfile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedPlugin {
+    /// Version from the plugin's manifest
+    pub version: String,
+    /// Source as given to `flux plugin install`, with directories made
+    /// absolute
+    pub source: String,
+    /// The git commit, archive or directory that was built
+    pub resolved: String,
+    /// `sha256:` digest of the installed library, module or executable,
+    /// checked each time the plugin is loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Trusted key the release archive was signed with, base64 encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_by: Option<String>,
+}
+
+impl Lockfile {
+    /// Reads the lockfile in a plugin directory; empty if there is none yet
+    ///
+    /// # Arguments
+    /// * `plugin_dir` - Directory installed plugins are kept in
+    ///
+    /// # Returns
+    /// * `Result<Lockfile, String>` - The lockfile, or why it could not be
+    ///   read
+    pub fn read(plugin_dir: &Path) -> Result<Self, String> {
+        let path: PathBuf = plugin_dir.join(LOCK_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("{}: {}", path.display(), e.message().trim())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Lockfile::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Writes the lockfile into a plugin directory
+    ///
+    /// # Arguments
+    /// * `plugin_dir` - Directory installed plugins are kept in
+    pub fn write(&self, plugin_dir: &Path) -> Result<(), String> {
+        let path: PathBuf = plugin_dir.join(LOCK_FILE);
+        let contents: String = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(
+            &path,
+            format!(
+                "# Written by `flux plugin install`; do not edit\n\n{}",
+                contents
+            ),
+        )
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
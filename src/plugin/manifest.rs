@@ -0,0 +1,406 @@
+use flux_plugin::hooks::HOOKS;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the manifest file shipped at the root of every plugin
+pub const MANIFEST_FILE: &str = "flux-plugin.toml";
+
+/// Permissions a plugin can request, with what they allow
+pub const PERMISSIONS: [(&str, &str); 4] = [
+    ("filesystem", "read and write files"),
+    ("network", "open network connections"),
+    ("process", "run other programs"),
+    ("environment", "read and change environment variables"),
+];
+
+/// Metadata a plugin declares in `flux-plugin.toml`
+///
+/// The shell reads it without loading the plugin, so listing, removing and
+/// updating plugins never runs plugin code.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginManifest {
+    /// Name the plugin is invoked and managed by
+    pub name: String,
+    /// Version of the plugin, in semver form
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// How the plugin runs: `native` (a shared library), `wasm` or `process`
+    #[serde(default = "default_runtime")]
+    pub runtime: String,
+    /// Program a `process` plugin runs, relative to the plugin's directory
+    #[serde(default)]
+    pub executable: Option<String>,
+    /// Shell command that builds a `process` plugin before it is installed
+    #[serde(default)]
+    pub build: Option<String>,
+    /// Seconds a `process` plugin's command may run without output
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Oldest flux version the plugin works with
+    #[serde(default)]
+    pub min_flux_version: Option<String>,
+    /// Commands as name and description pairs; each can be run by itself or
+    /// as `plugin::command`
+    #[serde(default)]
+    pub commands: BTreeMap<String, String>,
+    /// Permissions from `PERMISSIONS` the plugin needs
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// Events the plugin handles, from `flux_plugin::hooks::HOOKS`
+    #[serde(default)]
+    pub hooks: Vec<String>,
+    /// Prompt segments the plugin provides, as used in prompt templates
+    #[serde(default)]
+    pub prompt_segments: Vec<String>,
+    /// Other plugins this one needs, with a semver requirement on each
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl PluginManifest {
+    /// Reads the manifest in a plugin directory
+    ///
+    /// # Arguments
+    /// * `dir` - Plugin source or installation directory
+    ///
+    /// # Returns
+    /// * `Result<PluginManifest, String>` - The manifest, or why it could not
+    ///   be read or parsed
+    pub fn read(dir: &Path) -> Result<Self, String> {
+        let path: PathBuf = dir.join(MANIFEST_FILE);
+        let contents: String =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| {
+            let before: &str = &contents[..e.span().map_or(0, |span| span.start)];
+            let line: usize = before.matches('\n').count() + 1;
+            let column: usize = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                line,
+                column,
+                e.message().trim()
+            )
+        })
+    }
+
+    /// Checks the manifest's fields
+    ///
+    /// # Returns
+    /// * `Vec<String>` - Every problem found; empty if the manifest is valid
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+
+        if !is_plugin_name(&self.name) {
+            problems.push(format!(
+                "name '{}' must be lowercase letters, digits, '-' or '_'",
+                self.name
+            ));
+        }
+        if let Err(e) = Version::parse(&self.version) {
+            problems.push(format!(
+                "version '{}' is not a valid version: {}",
+                self.version, e
+            ));
+        }
+        if !["native", "wasm", "process"].contains(&self.runtime.as_str()) {
+            problems.push(format!(
+                "runtime '{}' must be native, wasm or process",
+                self.runtime
+            ));
+        }
+        match (&self.executable, self.runtime == "process") {
+            (None, true) => problems.push("a process plugin needs an `executable`".to_string()),
+            (Some(_), false) => {
+                problems.push("`executable` is only used by process plugins".to_string())
+            }
+            (Some(executable), true) => {
+                let path: &Path = Path::new(executable);
+                if path.is_absolute() || path.components().any(|part| part == Component::ParentDir)
+                {
+                    problems.push(format!(
+                        "executable '{}' must be a path inside the plugin",
+                        executable
+                    ));
+                }
+            }
+            (None, false) => {}
+        }
+        if self.timeout_secs == 0 {
+            problems.push("timeout_secs must be greater than 0".to_string());
+        }
+        if let Some(min_version) = &self.min_flux_version {
+            match Version::parse(min_version) {
+                Ok(min_version) if min_version > flux_version() => problems.push(format!(
+                    "needs flux {} or newer (this is flux {})",
+                    min_version,
+                    flux_version()
+                )),
+                Ok(_) => {}
+                Err(e) => problems.push(format!(
+                    "min_flux_version '{}' is not a valid version: {}",
+                    min_version, e
+                )),
+            }
+        }
+        for command in self.commands.keys() {
+            if command.is_empty() || command.contains(char::is_whitespace) {
+                problems.push(format!("command '{}' must be a single word", command));
+            }
+            if command.contains("::") {
+                problems.push(format!(
+                    "command '{}' cannot contain '::', which separates a plugin from its commands",
+                    command
+                ));
+            }
+        }
+        for permission in &self.permissions {
+            if !PERMISSIONS.iter().any(|(name, _)| name == permission) {
+                problems.push(format!(
+                    "unknown permission '{}' (expected one of: {})",
+                    permission,
+                    PERMISSIONS
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        for hook in &self.hooks {
+            if !HOOKS.contains(&hook.as_str()) {
+                problems.push(format!(
+                    "unknown hook '{}' (expected one of: {})",
+                    hook,
+                    HOOKS.join(", ")
+                ));
+            }
+        }
+        for (dependency, requirement) in &self.dependencies {
+            if dependency == &self.name {
+                problems.push("a plugin cannot depend on itself".to_string());
+            }
+            if let Err(e) = VersionReq::parse(requirement) {
+                problems.push(format!(
+                    "dependency '{}' has an invalid version requirement '{}': {}",
+                    dependency, requirement, e
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Whether the plugin is loaded when the shell starts, because its hooks
+    /// or prompt segments are needed before any of its commands runs
+    ///
+    /// Other plugins are loaded when one of their commands is first run.
+    pub fn loads_at_startup(&self) -> bool {
+        !self.hooks.is_empty() || !self.prompt_segments.is_empty()
+    }
+
+    /// Lists the dependencies that are not installed in a matching version
+    ///
+    /// # Arguments
+    /// * `installed` - Manifests of the installed plugins, by name
+    ///
+    /// # Returns
+    /// * `Vec<String>` - A message for each unmet dependency
+    pub fn missing_dependencies(&self, installed: &HashMap<String, PluginManifest>) -> Vec<String> {
+        self.dependencies
+            .iter()
+            .filter_map(|(dependency, requirement)| {
+                let requirement: VersionReq = VersionReq::parse(requirement).ok()?;
+                match installed.get(dependency) {
+                    None => Some(format!(
+                        "needs plugin '{}' {}, which is not installed",
+                        dependency, requirement
+                    )),
+                    Some(manifest) => match Version::parse(&manifest.version) {
+                        Ok(version) if requirement.matches(&version) => None,
+                        _ => Some(format!(
+                            "needs plugin '{}' {}, but {} is installed",
+                            dependency, requirement, manifest.version
+                        )),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+fn default_runtime() -> String {
+    "native".to_string()
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+/// Whether a name is usable as a plugin name and directory
+fn is_plugin_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Version of the running shell
+fn flux_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("package version is valid semver")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn parse(contents: &str) -> PluginManifest {
+        toml::from_str(contents).unwrap()
+    }
+
+    /// A manifest with the required fields and one extra line
+    fn with(extra: &str) -> PluginManifest {
+        parse(&format!(
+            "name = \"notes\"\nversion = \"1.2.0\"\n{}\n",
+            extra
+        ))
+    }
+
+    /// Installed plugins by name, from name and version pairs
+    fn installed(plugins: &[(&str, &str)]) -> HashMap<String, PluginManifest> {
+        plugins
+            .iter()
+            .map(|(name, version)| {
+                let manifest: PluginManifest =
+                    parse(&format!("name = \"{}\"\nversion = \"{}\"\n", name, version));
+                (name.to_string(), manifest)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_manifest() {
+        let manifest: PluginManifest = parse(
+            r#"
+            name = "notes"
+            version = "1.2.0"
+            description = "Keeps notes"
+            runtime = "process"
+            executable = "bin/notes"
+            min_flux_version = "0.1.0"
+            permissions = ["filesystem", "environment"]
+            hooks = ["precmd"]
+            prompt_segments = ["notes"]
+
+            [commands]
+            note = "Adds a note"
+
+            [dependencies]
+            storage = "^1.0"
+            "#,
+        );
+        assert_eq!(manifest.validate(), Vec::<String>::new());
+        assert_eq!(manifest.timeout_secs, 30);
+        assert!(manifest.loads_at_startup());
+        assert!(!with("").loads_at_startup());
+        assert_eq!(with("").runtime, "native");
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        let cases: [(&str, &str); 11] = [
+            ("name = \"Notes\"\nversion = \"1.0.0\"", "name 'Notes' must be"),
+            ("name = \"notes\"\nversion = \"1.0\"", "version '1.0' is not a valid version"),
+            ("name = \"notes\"\nversion = \"1.0.0\"\nruntime = \"jvm\"", "runtime 'jvm' must be"),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\nruntime = \"process\"",
+                "a process plugin needs an `executable`",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\nruntime = \"process\"\nexecutable = \"../notes\"",
+                "executable '../notes' must be a path inside the plugin",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\nexecutable = \"notes\"",
+                "`executable` is only used by process plugins",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\nmin_flux_version = \"999.0.0\"",
+                "needs flux 999.0.0 or newer",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\nmin_flux_version = \"soon\"",
+                "min_flux_version 'soon' is not a valid version",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\npermissions = [\"root\"]",
+                "unknown permission 'root'",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\nhooks = [\"on_boot\"]",
+                "unknown hook 'on_boot'",
+            ),
+            (
+                "name = \"notes\"\nversion = \"1.0.0\"\n[commands]\n\"a::b\" = \"\"",
+                "cannot contain '::'",
+            ),
+        ];
+        for (contents, expected) in cases {
+            let problems: Vec<String> = parse(contents).validate();
+            assert_eq!(problems.len(), 1, "{:?}", problems);
+            assert!(problems[0].contains(expected), "{}", problems[0]);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_dependencies() {
+        let problems: Vec<String> =
+            with("[dependencies]\nnotes = \"^1\"\nstorage = \"newest\"").validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(problems[0], "a plugin cannot depend on itself");
+        assert!(problems[1]
+            .starts_with("dependency 'storage' has an invalid version requirement 'newest'"));
+    }
+
+    #[test]
+    fn rejects_unknown_fields_with_their_location() {
+        let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(MANIFEST_FILE),
+            "name = \"notes\"\nversion = \"1.0.0\"\nauthor = \"me\"\n",
+        )
+        .unwrap();
+        let e: String = PluginManifest::read(&dir).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            e.starts_with(&format!("{}:3:1: ", dir.join(MANIFEST_FILE).display())),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn lists_missing_dependencies() {
+        let manifest: PluginManifest =
+            with("[dependencies]\nstorage = \"^1.0\"\nsync = \"^2\"\nclock = \"*\"");
+        assert_eq!(
+            manifest.missing_dependencies(&installed(&[("storage", "1.4.0"), ("sync", "1.9.0"),])),
+            vec![
+                "needs plugin 'clock' *, which is not installed".to_string(),
+                "needs plugin 'sync' ^2, but 1.9.0 is installed".to_string(),
+            ]
+        );
+        assert!(manifest
+            .missing_dependencies(&installed(&[
+                ("storage", "1.0.0"),
+                ("sync", "2.1.0"),
+                ("clock", "0.1.0"),
+            ]))
+            .is_empty());
+    }
+}
@@ -1,122 +1,567 @@
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use colored::*;
 use dialoguer::{Confirm, Select};
-use libloading::{Library, Symbol};
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use uuid::Uuid;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub trait FluxPlugin {
-    /// Get the name of the plugin
-    fn name(&self) -> &str;
+mod context;
+mod lockfile;
+mod manifest;
+mod native;
+mod process;
+mod source;
+mod state;
+mod trust;
+mod wasm;
 
-    /// Get the version of the plugin
-    fn version(&self) -> &str {
-        "0.1.0" // Default version
+pub use context::{set_env_var, with_env_unchanged, ExecutionContext};
+pub use flux_plugin::{hooks, FluxPlugin, Preexec};
+pub use lockfile::{LockedPlugin, Lockfile};
+pub use manifest::{PluginManifest, MANIFEST_FILE, PERMISSIONS};
+pub use native::NativePlugin;
+pub use process::ProcessPlugin;
+pub use source::{Checkout, PluginSource};
+pub use state::PluginState;
+pub use trust::TrustPolicy;
+pub use wasm::{Capabilities, WasmPlugin};
+
+/// Library of an installed native plugin, inside its directory
+const LIBRARY_FILE: &str = "plugin.flp";
+
+/// Module of an installed WebAssembly plugin, inside its directory
+const WASM_FILE: &str = "plugin.wasm";
+
+/// Git URL a plugin came from, inside its directory, from before the
+/// lockfile recorded it
+const SOURCE_FILE: &str = "source";
+
+/// A directory in the plugin directory and the result of reading its manifest
+type InstalledPlugin = (PathBuf, Result<PluginManifest, String>);
+
+/// A loaded plugin of either kind
+enum LoadedPlugin {
+    Native(NativePlugin),
+    Wasm(Box<WasmPlugin>),
+    /// Shared with the prompt engine's worker, which asks for its segments
+    Process(Arc<ProcessPlugin>),
+}
+
+impl LoadedPlugin {
+    fn init(&mut self) -> Result<(), String> {
+        match self {
+            LoadedPlugin::Native(plugin) => plugin.init(),
+            LoadedPlugin::Wasm(plugin) => plugin.init(),
+            // Started and checked with `describe` when loaded
+            LoadedPlugin::Process(_) => Ok(()),
+        }
+    }
+
+    fn execute(&self, context: &mut ExecutionContext, args: &[String]) -> Result<i32, String> {
+        match self {
+            LoadedPlugin::Native(plugin) => plugin.execute(context, args),
+            LoadedPlugin::Wasm(plugin) => plugin.execute(context, args),
+            LoadedPlugin::Process(plugin) => plugin.execute(context, args),
+        }
     }
 
-    /// Get plugin description
-    fn description(&self) -> &str {
-        "No description provided"
+    fn hook(&self, context: &mut ExecutionContext, args: &[String]) -> Result<Preexec, String> {
+        match self {
+            LoadedPlugin::Native(plugin) => plugin.hook(context, args),
+            LoadedPlugin::Wasm(plugin) => plugin.hook(context, args),
+            LoadedPlugin::Process(plugin) => plugin.hook(context, args),
+        }
     }
 
-    /// Get available commands
-    fn commands(&self) -> Vec<(&str, &str)> {
-        Vec::new() // (command, description) pairs
+    fn complete(&self, args: &[String]) -> Vec<String> {
+        match self {
+            LoadedPlugin::Native(plugin) => plugin.complete(args),
+            LoadedPlugin::Wasm(plugin) => plugin.complete(args),
+            LoadedPlugin::Process(plugin) => plugin.complete(args),
+        }
     }
 
-    /// Initialize the plugin
-    fn init(&mut self) -> Result<(), String>;
+    fn prompt_segments(&self) -> Vec<(String, String)> {
+        match self {
+            LoadedPlugin::Native(plugin) => plugin.prompt_segments(),
+            LoadedPlugin::Wasm(plugin) => plugin.prompt_segments(),
+            LoadedPlugin::Process(plugin) => plugin.prompt_segments(),
+        }
+    }
 
-    /// Execute a plugin command
-    fn execute(&self, args: &[String]) -> Result<(), String>;
+    fn cleanup(&mut self) -> Result<(), String> {
+        match self {
+            LoadedPlugin::Native(plugin) => plugin.cleanup(),
+            LoadedPlugin::Wasm(plugin) => plugin.cleanup(),
+            LoadedPlugin::Process(plugin) => plugin.cleanup(),
+        }
+    }
+}
 
-    /// Clean up plugin resources
-    fn cleanup(&mut self) -> Result<(), String>;
+/// Prompt segments of the plugins that declare any
+#[derive(Clone, Default)]
+pub struct PromptSegments {
+    /// Values from native and WebAssembly plugins, by placeholder name
+    pub values: HashMap<String, String>,
+    /// Process plugins, which may take a while to answer
+    processes: Vec<Arc<ProcessPlugin>>,
+}
 
-    /// Handle plugin configuration
-    #[allow(dead_code)]
-    fn configure(&mut self) -> Result<(), String> {
-        Ok(()) // Default: no configuration needed
+impl PromptSegments {
+    /// Whether there are process plugins to ask
+    pub fn has_queries(&self) -> bool {
+        !self.processes.is_empty()
     }
 
-    /// Get plugin help text
-    #[allow(dead_code)]
-    fn help(&self) -> String {
-        format!(
-            "Plugin: {}\nVersion: {}\n{}\n\nCommands:\n{}",
-            self.name(),
-            self.version(),
-            self.description(),
-            self.commands()
-                .iter()
-                .map(|(cmd, desc)| format!("  {} - {}", cmd, desc))
-                .collect::<Vec<_>>()
-                .join("\n")
-        )
+    /// Asks the process plugins for their segments, waiting for each
+    ///
+    /// # Returns
+    /// * `HashMap<String, String>` - Values by placeholder name
+    pub fn query(&self) -> HashMap<String, String> {
+        self.processes
+            .iter()
+            .flat_map(|plugin| plugin.prompt_segments())
+            .collect()
     }
 }
 
 pub struct PluginManager {
-    plugins: HashMap<String, (Library, Box<dyn FluxPlugin + 'static>)>,
+    plugins: HashMap<String, LoadedPlugin>,
+    /// Plugins loaded when one of their commands is first run, with their
+    /// directories and manifests
+    deferred: HashMap<String, (PathBuf, PluginManifest)>,
+    /// Capabilities of WebAssembly plugins, by plugin name
+    grants: HashMap<String, Capabilities>,
+    /// Plugins that handle each hook, in the order they were loaded
+    hooks: HashMap<String, Vec<String>>,
+    /// Plugins that declare each command, in the order they were loaded
+    commands: HashMap<String, Vec<String>>,
+    /// Plugins that declare prompt segments, in the order they were loaded
+    segment_providers: Vec<String>,
+    /// Builtins and aliases, which come before plugin commands, with what
+    /// each one is
+    reserved: HashMap<String, String>,
+    /// Path or URL of the registry index plugin names are looked up in
+    registry: Option<String>,
+    /// Keys plugin releases may be signed with, and whether they must be
+    trust: TrustPolicy,
     plugin_dir: PathBuf,
     temp_dir: PathBuf,
 }
 
 impl PluginManager {
-    pub fn new() -> Self {
-        let plugin_dir: PathBuf = dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("sh.lrr.flux")
-            .join("plugins");
-
-        let temp_dir: PathBuf = plugin_dir.join("temp");
-
+    /// Creates a plugin manager
+    ///
+    /// # Arguments
+    /// * `plugin_dir` - Directory installed plugins are kept in
+    /// * `temp_dir` - Directory plugins are cloned and built in
+    pub fn new(plugin_dir: PathBuf, temp_dir: PathBuf) -> Self {
         std::fs::create_dir_all(&plugin_dir).unwrap_or_default();
         std::fs::create_dir_all(&temp_dir).unwrap_or_default();
 
         PluginManager {
             plugins: HashMap::new(),
+            deferred: HashMap::new(),
+            grants: HashMap::new(),
+            hooks: HashMap::new(),
+            commands: HashMap::new(),
+            segment_providers: Vec::new(),
+            reserved: HashMap::new(),
+            registry: None,
+            trust: TrustPolicy::default(),
             plugin_dir,
             temp_dir,
         }
     }
 
-    pub fn load_plugins(&mut self) -> Result<(), String> {
-        for entry in std::fs::read_dir(&self.plugin_dir).map_err(|e| e.to_string())? {
-            let entry: fs::DirEntry = entry.map_err(|e| e.to_string())?;
-            let path: PathBuf = entry.path();
+    /// Loads every enabled plugin that has hooks or prompt segments, and
+    /// makes the commands of the others load theirs when first run
+    ///
+    /// A plugin that fails to load does not stop the others. Plugins whose
+    /// files no longer match the checksums in the lockfile are not loaded,
+    /// nor, with `require_signed_plugins`, are unsigned ones.
+    ///
+    /// # Arguments
+    /// * `grants` - Capabilities of WebAssembly plugins, by plugin name
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Ok, or the reasons plugins failed to load,
+    ///   one per line
+    pub fn load_plugins(&mut self, grants: &HashMap<String, Capabilities>) -> Result<(), String> {
+        self.grants = grants.clone();
+        let installed: Vec<InstalledPlugin> = self.installed()?;
+        let manifests: HashMap<String, PluginManifest> = manifests(&installed);
+        let state: PluginState = PluginState::read(&self.plugin_dir)?;
+        let lockfile: Lockfile = Lockfile::read(&self.plugin_dir)?;
+        let mut errors: Vec<String> = Vec::new();
 
-            if let Some(extension) = path.extension() {
-                if extension == "flp" {
-                    self.load_plugin(&path)?;
+        for (dir, manifest) in installed {
+            let manifest: PluginManifest = match manifest {
+                Ok(manifest) if state.is_enabled(&manifest.name) => manifest,
+                Ok(_) => continue,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
                 }
+            };
+            let result: Result<(), String> =
+                check_plugin(&manifest, &manifests, &state).and_then(|_| {
+                    if manifest.loads_at_startup() {
+                        let locked: Option<&LockedPlugin> = lockfile.plugins.get(&manifest.name);
+                        self.load_plugin(&dir, &manifest, locked).map(|_| ())
+                    } else {
+                        self.deferred
+                            .insert(manifest.name.clone(), (dir, manifest.clone()));
+                        Ok(())
+                    }
+                });
+            match result {
+                Ok(()) => self.register(&manifest),
+                Err(e) => errors.push(e),
+            }
+        }
+        // Bare `.flp` files predate the versioned interface, so they cannot
+        // be loaded; ask once for them to be reinstalled or removed
+        let legacy: Vec<String> = self
+            .legacy_plugins()?
+            .iter()
+            .map(|path| legacy_name(path))
+            .filter(|name| state.is_enabled(name))
+            .collect();
+        if !legacy.is_empty() {
+            eprintln!(
+                "{}",
+                format!(
+                    "Not loading plugins installed by an older flux: {}. Reinstall them with `flux plugin install`, or run `flux plugin remove` or `flux plugin disable` on them.",
+                    legacy.join(", ")
+                )
+                .bright_yellow()
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Checks an installed plugin against what the lockfile recorded when it
+    /// was installed
+    ///
+    /// # Arguments
+    /// * `manifest` - The plugin's manifest
+    /// * `file` - Its library, module or executable
+    /// * `locked` - The plugin's lockfile entry, if it has one
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Why the plugin must not be loaded, if it
+    ///   must not
+    fn verify_installed(
+        &self,
+        manifest: &PluginManifest,
+        file: &Path,
+        locked: Option<&LockedPlugin>,
+    ) -> Result<(), String> {
+        let expected: Option<&String> = locked.and_then(|locked| locked.checksum.as_ref());
+        if let Some(expected) = expected {
+            if &trust::checksum(file)? != expected {
+                return Err(format!(
+                    "{}: {} has changed since it was installed; reinstall the plugin if that was expected",
+                    manifest.name,
+                    file.display()
+                ));
+            }
+        }
+        if self.trust.require_signed() {
+            // The checksum ties the signature to the file being loaded
+            let signed: bool = locked
+                .and_then(|locked| locked.signed_by.as_deref())
+                .is_some_and(|key| self.trust.key_name(key).is_some());
+            if !signed || expected.is_none() {
+                return Err(format!(
+                    "{}: not signed with a trusted key, and require_signed_plugins is set",
+                    manifest.name
+                ));
             }
         }
         Ok(())
     }
 
-    fn load_plugin(&mut self, path: &PathBuf) -> Result<(), String> {
-        unsafe {
-            let library: Library = Library::new(path).map_err(|e| e.to_string())?;
+    /// Verifies an installed plugin and loads it
+    ///
+    /// # Arguments
+    /// * `dir` - The plugin's directory
+    /// * `manifest` - The plugin's manifest
+    /// * `locked` - The plugin's lockfile entry, if it has one
+    ///
+    /// # Returns
+    /// * `Result<Duration, String>` - How long loading and initializing the
+    ///   plugin took, or why it could not be loaded
+    fn load_plugin(
+        &mut self,
+        dir: &Path,
+        manifest: &PluginManifest,
+        locked: Option<&LockedPlugin>,
+    ) -> Result<Duration, String> {
+        let started: Instant = Instant::now();
+        self.verify_installed(manifest, &installed_file(dir, manifest), locked)?;
+        let plugin: Option<LoadedPlugin> = match manifest.runtime.as_str() {
+            "wasm" => {
+                let capabilities: Capabilities =
+                    self.grants.get(&manifest.name).cloned().unwrap_or_default();
+                Some(LoadedPlugin::Wasm(Box::new(WasmPlugin::load(
+                    &dir.join(WASM_FILE),
+                    &manifest.name,
+                    capabilities,
+                )?)))
+            }
+            "process" => Some(LoadedPlugin::Process(Arc::new(ProcessPlugin::load(
+                dir,
+                manifest.executable.as_deref().unwrap_or_default(),
+                &manifest.name,
+                Duration::from_secs(manifest.timeout_secs),
+            )?))),
+            _ => None,
+        };
+        match plugin {
+            Some(mut plugin) => {
+                plugin.init()?;
+                self.plugins.insert(manifest.name.clone(), plugin);
+            }
+            None => self.load_library(&dir.join(LIBRARY_FILE), &manifest.name)?,
+        }
+        Ok(started.elapsed())
+    }
 
-            let create_plugin: Symbol<fn() -> Box<dyn FluxPlugin>> =
-                library.get(b"create_plugin").map_err(|e| e.to_string())?;
+    /// Loads a plugin whose loading was put off until it was first used
+    ///
+    /// # Arguments
+    /// * `name` - Name of the plugin
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Ok if the plugin is loaded now or already
+    ///   was, or why it could not be loaded
+    pub fn load_on_demand(&mut self, name: &str) -> Result<(), String> {
+        let Some((dir, manifest)) = self.deferred.remove(name) else {
+            return Ok(());
+        };
+        let lockfile: Lockfile = Lockfile::read(&self.plugin_dir)?;
+        if let Err(e) = self.load_plugin(&dir, &manifest, lockfile.plugins.get(name)) {
+            // Tried again next time, so each use reports why it failed
+            self.deferred.insert(name.to_string(), (dir, manifest));
+            return Err(e);
+        }
+        Ok(())
+    }
 
-            let mut plugin: Box<dyn FluxPlugin> = create_plugin();
-            let name: String = plugin.name().to_string();
+    /// Makes a plugin's hooks and commands reachable
+    fn register(&mut self, manifest: &PluginManifest) {
+        for hook in &manifest.hooks {
+            self.hooks
+                .entry(hook.clone())
+                .or_default()
+                .push(manifest.name.clone());
+        }
+        for command in manifest.commands.keys() {
+            self.commands
+                .entry(command.clone())
+                .or_default()
+                .push(manifest.name.clone());
+        }
+        if !manifest.prompt_segments.is_empty() {
+            self.segment_providers.push(manifest.name.clone());
+        }
+    }
 
-            plugin.init()?;
-            self.plugins.insert(name, (library, plugin));
+    /// Loads and initializes a plugin library
+    ///
+    /// # Arguments
+    /// * `path` - Path to the library
+    /// * `expected_name` - Name from the plugin's manifest, which the library
+    ///   must report as well
+    fn load_library(&mut self, path: &Path, expected_name: &str) -> Result<(), String> {
+        let plugin: NativePlugin = NativePlugin::load(path)?;
+        let name: String = plugin.name();
+        if name != expected_name {
+            return Err(format!(
+                "{}: library is named '{}' but its manifest says '{}'",
+                path.display(),
+                name,
+                expected_name
+            ));
         }
+
+        let mut plugin: LoadedPlugin = LoadedPlugin::Native(plugin);
+        plugin.init()?;
+        self.plugins.insert(name, plugin);
         Ok(())
     }
 
-    pub fn execute_plugin(&self, name: &str, args: &[String]) -> Result<(), String> {
-        if let Some((_, plugin)) = self.plugins.get(name) {
-            plugin.execute(args)
+    /// Applies changed capabilities to the WebAssembly plugins, loaded now
+    /// or later
+    ///
+    /// # Arguments
+    /// * `grants` - Capabilities by plugin name
+    pub fn set_capabilities(&mut self, grants: &HashMap<String, Capabilities>) {
+        self.grants = grants.clone();
+        for (name, plugin) in &self.plugins {
+            if let LoadedPlugin::Wasm(plugin) = plugin {
+                plugin.set_capabilities(grants.get(name).cloned().unwrap_or_default());
+            }
+        }
+    }
+
+    /// Sets the names that take precedence over plugin commands
+    ///
+    /// # Arguments
+    /// * `reserved` - Builtins and aliases, each with what it is, such as
+    ///   "a shell builtin"
+    pub fn set_reserved(&mut self, reserved: HashMap<String, String>) {
+        self.reserved = reserved;
+    }
+
+    /// Sets the registry index `install` looks plugin names up in
+    ///
+    /// # Arguments
+    /// * `registry` - Path or URL of the index, if one is configured
+    pub fn set_registry(&mut self, registry: Option<String>) {
+        self.registry = registry;
+    }
+
+    /// Sets which signatures are trusted and whether plugins need one
+    ///
+    /// # Arguments
+    /// * `trust` - Trusted keys and the `require_signed_plugins` setting
+    pub fn set_trust(&mut self, trust: TrustPolicy) {
+        self.trust = trust;
+    }
+
+    /// Lets process plugins that crashed too often be started again
+    pub fn reset_crashes(&self) {
+        for plugin in self.plugins.values() {
+            if let LoadedPlugin::Process(plugin) = plugin {
+                plugin.reset_crashes();
+            }
+        }
+    }
+
+    /// Names of the plugins that are loaded or load when first used
+    pub fn plugin_names(&self) -> Vec<String> {
+        self.plugins
+            .keys()
+            .chain(self.deferred.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Asks a plugin to complete the last of `args`
+    ///
+    /// # Arguments
+    /// * `name` - Plugin name
+    /// * `args` - Words typed after the plugin name, the last one partial
+    ///
+    /// # Returns
+    /// * `Vec<String>` - Candidates; empty if the plugin is not loaded,
+    ///   including when it has not been used yet
+    pub fn complete(&self, name: &str, args: &[String]) -> Vec<String> {
+        self.plugins
+            .get(name)
+            .map(|plugin| plugin.complete(args))
+            .unwrap_or_default()
+    }
+
+    /// Names of the commands plugins declare, for completion
+    ///
+    /// # Returns
+    /// * `Vec<String>` - Each command by itself and as `plugin::command`
+    pub fn command_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for (command, plugins) in &self.commands {
+            names.push(command.clone());
+            names.extend(
+                plugins
+                    .iter()
+                    .map(|plugin| format!("{}::{}", plugin, command)),
+            );
+        }
+        names
+    }
+
+    /// Finds the plugin that runs a command
+    ///
+    /// Builtins and aliases are never plugin commands; `plugin::command`
+    /// reaches a command even so.
+    ///
+    /// # Arguments
+    /// * `word` - The command as typed
+    ///
+    /// # Returns
+    /// * `Option<(String, Option<String>)>` - The plugin, and the command to
+    ///   pass it as its first argument
+    pub fn resolve(&self, word: &str) -> Option<(String, Option<String>)> {
+        if !word.contains("::") && self.reserved.contains_key(word) {
+            return None;
+        }
+        self.lookup(word).into_iter().next()
+    }
+
+    /// Every plugin a command could run, the one that runs it first
+    ///
+    /// A plugin's name comes before other plugins' commands, and of plugins
+    /// declaring the same command, the first loaded comes first.
+    ///
+    /// # Arguments
+    /// * `word` - A plugin's name, a command from a plugin's manifest, or
+    ///   both joined as `plugin::command`
+    ///
+    /// # Returns
+    /// * `Vec<(String, Option<String>)>` - Each plugin, and the command to
+    ///   pass it as its first argument
+    pub fn lookup(&self, word: &str) -> Vec<(String, Option<String>)> {
+        if let Some((plugin, command)) = word.split_once("::") {
+            let declared: bool = self
+                .commands
+                .get(command)
+                .is_some_and(|plugins| plugins.iter().any(|name| name == plugin));
+            if !declared {
+                return Vec::new();
+            }
+            return vec![(plugin.to_string(), Some(command.to_string()))];
+        }
+        let mut found: Vec<(String, Option<String>)> = Vec::new();
+        if self.plugins.contains_key(word) || self.deferred.contains_key(word) {
+            found.push((word.to_string(), None));
+        }
+        for plugin in self.commands.get(word).into_iter().flatten() {
+            found.push((plugin.clone(), Some(word.to_string())));
+        }
+        found
+    }
+
+    /// Runs a plugin command
+    ///
+    /// # Arguments
+    /// * `name` - Name of the plugin
+    /// * `context` - What the command runs with; collects its output and
+    ///   the changes it asks for
+    /// * `args` - Arguments for the plugin, starting with the command
+    ///
+    /// # Returns
+    /// * `Result<i32, String>` - The exit code, or the error the command
+    ///   returned
+    pub fn execute_plugin(
+        &self,
+        name: &str,
+        context: &mut ExecutionContext,
+        args: &[String],
+    ) -> Result<i32, String> {
+        if let Some(plugin) = self.plugins.get(name) {
+            plugin.execute(context, args)
         } else {
             Err(format!(
                 "Plugin '{}' not found. Available plugins: {}",
@@ -130,27 +575,180 @@ impl PluginManager {
         }
     }
 
+    /// Collects the prompt segments of the plugins that declare any
+    ///
+    /// Native and WebAssembly plugins answer now; process plugins are only
+    /// asked by `PromptSegments::query`, which the prompt engine runs off
+    /// the shell thread.
+    pub fn prompt_segments(&self) -> PromptSegments {
+        let mut segments: PromptSegments = PromptSegments::default();
+        for name in &self.segment_providers {
+            match self.plugins.get(name) {
+                Some(LoadedPlugin::Process(plugin)) => segments.processes.push(Arc::clone(plugin)),
+                Some(plugin) => segments.values.extend(plugin.prompt_segments()),
+                None => {}
+            }
+        }
+        segments
+    }
+
+    /// Runs the `preexec` hooks on a command line
+    ///
+    /// Each plugin sees the command as rewritten by the ones before it.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The command to run, or None if a plugin vetoed it
+    pub fn preexec(&self, command: &str) -> Option<String> {
+        let mut command: String = command.to_string();
+        for name in self.hooks.get(hooks::PREEXEC).into_iter().flatten() {
+            match self.hook(name, &[hooks::PREEXEC, &command]) {
+                Preexec::Run => {}
+                Preexec::Rewrite(rewritten) => command = rewritten,
+                Preexec::Veto => return None,
+            }
+        }
+        Some(command)
+    }
+
+    /// Runs the `precmd` hooks after a command finishes
+    pub fn precmd(&self, command: &str, status: i32, duration: Duration) {
+        let status: String = status.to_string();
+        let duration: String = duration.as_millis().to_string();
+        self.run_hooks(hooks::PRECMD, &[command, &status, &duration]);
+    }
+
+    /// Runs the `chpwd` hooks after the working directory changes
+    pub fn chpwd(&self, old: &Path, new: &Path) {
+        let old: String = old.to_string_lossy().into_owned();
+        let new: String = new.to_string_lossy().into_owned();
+        self.run_hooks(hooks::CHPWD, &[&old, &new]);
+    }
+
+    /// Runs the `on_history_add` hooks after a line is added to the history
+    pub fn history_add(&self, entry: &str) {
+        self.run_hooks(hooks::HISTORY_ADD, &[entry]);
+    }
+
+    /// Runs the `on_exit` hooks as the shell exits
+    pub fn exit(&self) {
+        self.run_hooks(hooks::EXIT, &[]);
+    }
+
+    /// Calls a hook on every plugin that handles it
+    fn run_hooks(&self, hook: &str, args: &[&str]) {
+        let mut call: Vec<&str> = vec![hook];
+        call.extend(args);
+        for name in self.hooks.get(hook).into_iter().flatten() {
+            self.hook(name, &call);
+        }
+    }
+
+    /// Calls a hook on one plugin and applies the changes it asks for
+    ///
+    /// A failing hook is reported and otherwise ignored, so a broken plugin
+    /// cannot stop commands from running.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the plugin
+    /// * `args` - The hook's name followed by its arguments
+    fn hook(&self, name: &str, args: &[&str]) -> Preexec {
+        let Some(plugin) = self.plugins.get(name) else {
+            return Preexec::Run;
+        };
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut context: ExecutionContext = ExecutionContext::new(None, false);
+        let result: Result<Preexec, String> = plugin
+            .hook(&mut context, &args)
+            .and_then(|decision| context.apply().map(|_| decision));
+        result.unwrap_or_else(|e| {
+            eprintln!("flux: plugin '{}' {} hook failed: {}", name, args[0], e);
+            Preexec::Run
+        })
+    }
+
     pub fn cleanup(&mut self) {
-        for (_, (_, mut plugin)) in self.plugins.drain() {
+        self.deferred.clear();
+        self.hooks.clear();
+        self.commands.clear();
+        self.segment_providers.clear();
+        for (_, mut plugin) in self.plugins.drain() {
             plugin.cleanup().unwrap_or_default();
         }
     }
 
-    pub fn install_from_git(&self, url: &str) -> Result<(), String> {
-        println!("{}", "⚠️  Warning: Installing plugins can be dangerous as they run with the same permissions as the shell."
-            .bright_yellow());
-        println!(
-            "{}",
-            "Please review the code before installing.".bright_yellow()
-        );
+    /// Installs a plugin and records it in the lockfile
+    ///
+    /// # Arguments
+    /// * `spec` - A directory, a `.tar.gz` archive, a git URL with an optional
+    ///   `@<tag or rev>`, or a plugin name with an optional
+    ///   `@<version requirement>` to look up in the registry index
+    pub fn install(&self, spec: &str) -> Result<(), String> {
+        self.install_from(spec, None)
+    }
 
-        let uuid: Uuid = Uuid::new_v4();
-        let temp_path: PathBuf = self.temp_dir.join(uuid.to_string());
+    /// Fetches, checks and builds a plugin, then moves it into place
+    ///
+    /// The plugin is built in a staging directory, so a failed or cancelled
+    /// update leaves the installed version and its lockfile entry as they
+    /// were.
+    ///
+    /// # Arguments
+    /// * `spec` - Where to install the plugin from, as for `install`
+    /// * `replacing` - Name and directory of the installed plugin being
+    ///   updated, if this is an update
+    fn install_from(&self, spec: &str, replacing: Option<(&str, &Path)>) -> Result<(), String> {
+        let source: PluginSource = PluginSource::parse(spec)?;
+        let (fetch, registry_version) = match &source {
+            PluginSource::Registry { name, requirement } => {
+                let index: &str = self.registry.as_deref().ok_or_else(|| {
+                    format!(
+                        "'{}' is not a path or URL, and no plugin_registry is configured to look it up in",
+                        spec
+                    )
+                })?;
+                let (version, fetch) = source::resolve(index, name, requirement)?;
+                println!("Resolved {} to {} from {}", spec, version, fetch);
+                (fetch, Some(version))
+            }
+            other => (other.clone(), None),
+        };
+        let checkout: Checkout = fetch.fetch(&self.temp_dir)?;
+        let manifest: PluginManifest =
+            self.check_manifest(spec, &checkout.dir, replacing.map(|(name, _)| name))?;
+        if let Some(version) = registry_version {
+            if manifest.version != version.to_string() {
+                return Err(format!(
+                    "the registry lists {} {} but its manifest says version {}",
+                    manifest.name, version, manifest.version
+                ));
+            }
+        }
+        let signed_by: Option<String> = self.check_signature(&checkout)?;
 
-        Command::new("git")
-            .args(["clone", url, temp_path.to_str().unwrap()])
-            .output()
-            .map_err(|e| format!("Failed to clone repository: {}", e))?;
+        if manifest.runtime == "wasm" {
+            println!("{}", "This plugin runs sandboxed and can only use the capabilities granted to it in plugin_permissions."
+                .bright_yellow());
+        } else {
+            println!("{}", "⚠️  Warning: Installing plugins can be dangerous as they run with the same permissions as the shell."
+                .bright_yellow());
+            println!(
+                "{}",
+                "Please review the code before installing.".bright_yellow()
+            );
+        }
+        print_summary(&manifest);
+        let mut manifests: HashMap<String, PluginManifest> = manifests(&self.installed()?);
+        manifests.insert(manifest.name.clone(), manifest.clone());
+        for (command, by) in self.shadowed_commands(&manifest, &manifests) {
+            println!(
+                "{}",
+                format!(
+                    "'{}' is taken by {}; run this plugin's as {}::{}",
+                    command, by, manifest.name, command
+                )
+                .bright_yellow()
+            );
+        }
 
         let options = vec![
             "[O]pen code for review",
@@ -168,12 +766,16 @@ impl PluginManager {
             0 => {
                 if let Ok(editor) = std::env::var("EDITOR") {
                     Command::new(editor)
-                        .arg(&temp_path)
+                        .arg(&checkout.dir)
                         .status()
                         .map_err(|e| format!("Failed to open editor: {}", e))?;
                 } else {
+                    let entry: &str = match &manifest.executable {
+                        Some(executable) => executable,
+                        None => "src/lib.rs",
+                    };
                     Command::new("less")
-                        .arg(temp_path.join("src/lib.rs"))
+                        .arg(checkout.dir.join(entry))
                         .status()
                         .map_err(|e| format!("Failed to open less: {}", e))?;
                 }
@@ -184,51 +786,157 @@ impl PluginManager {
                     .interact()
                     .unwrap_or(false)
                 {
-                    fs::remove_dir_all(&temp_path).ok();
-                    return Ok(());
+                    return cancelled(replacing.is_some());
                 }
             }
             1 => {
                 println!("{}", "Proceeding without code review...".bright_yellow());
             }
-            _ => {
-                fs::remove_dir_all(&temp_path).ok();
-                return Ok(());
-            }
+            _ => return cancelled(replacing.is_some()),
         }
 
-        Command::new("cargo")
-            .args(["build", "--release"])
-            .current_dir(&temp_path)
-            .status()
-            .map_err(|e| format!("Failed to build plugin: {}", e))?;
-
-        let encoded_url: String = BASE64.encode(url);
-        let plugin_file: String = format!("{}.{}.flp", uuid, encoded_url);
-        let target_path: PathBuf = self.plugin_dir.join(&plugin_file);
-
-        let lib_prefix: &str = "lib";
-        let lib_name: &str = "test_lib";
-        let lib_ext: &str = if cfg!(target_os = "windows") {
-            "dll"
-        } else if cfg!(target_os = "macos") {
-            "dylib"
+        // Built next to the plugins, so moving it into place is a rename
+        let staging_dir: PathBuf = self.plugin_dir.join(format!(".{}.staging", manifest.name));
+        fs::remove_dir_all(&staging_dir).ok();
+        let installed: Result<(), String> = if manifest.runtime == "process" {
+            install_process_plugin(&manifest, &checkout.dir, &staging_dir)
         } else {
-            "so"
+            install_compiled_plugin(&manifest, &checkout.dir, &staging_dir)
         };
+        let checksum: String = installed
+            .and_then(|_| trust::checksum(&installed_file(&staging_dir, &manifest)))
+            .and_then(|checksum| {
+                let target_dir: PathBuf = self.plugin_dir.join(&manifest.name);
+                replace_dir(
+                    &staging_dir,
+                    &target_dir,
+                    replacing.map_or(target_dir.as_path(), |(_, dir)| dir),
+                )?;
+                Ok(checksum)
+            })
+            .inspect_err(|_| {
+                fs::remove_dir_all(&staging_dir).ok();
+            })?;
 
-        let lib_file: String = format!("{}{}.{}", lib_prefix, lib_name, lib_ext);
-        let source_path: PathBuf = temp_path.join("target").join("release").join(lib_file);
-
-        fs::copy(&source_path, &target_path)
-            .map_err(|e| format!("Failed to install plugin: {}", e))?;
-
-        fs::remove_dir_all(&temp_path).ok();
+        let mut lockfile: Lockfile = Lockfile::read(&self.plugin_dir)?;
+        lockfile.plugins.insert(
+            manifest.name.clone(),
+            LockedPlugin {
+                version: manifest.version.clone(),
+                source: match &source {
+                    PluginSource::Local(_) | PluginSource::Archive(_) => checkout.resolved.clone(),
+                    other => other.to_string(),
+                },
+                resolved: checkout.resolved.clone(),
+                checksum: Some(checksum),
+                signed_by,
+            },
+        );
+        lockfile.write(&self.plugin_dir)?;
 
         println!("{}", "Plugin installed successfully!".green());
         Ok(())
     }
 
+    /// Verifies the signature published with a plugin's release, if any
+    ///
+    /// # Arguments
+    /// * `checkout` - The plugin's fetched source
+    ///
+    /// # Returns
+    /// * `Result<Option<String>, String>` - The trusted key the release was
+    ///   signed with, or why it cannot be installed: a malformed signature,
+    ///   or no trusted signature when one is required
+    fn check_signature(&self, checkout: &Checkout) -> Result<Option<String>, String> {
+        let signed_by: Option<String> = match &checkout.signature {
+            Some(signature) => {
+                let archive: Vec<u8> = fs::read(&signature.archive)
+                    .map_err(|e| format!("{}: {}", signature.archive.display(), e))?;
+                let key: Option<String> = self.trust.verify(&archive, &signature.signature)?;
+                if key.is_none() {
+                    println!(
+                        "{}",
+                        "The release is signed, but not with a trusted key.".bright_yellow()
+                    );
+                }
+                key
+            }
+            None => None,
+        };
+        match &signed_by {
+            Some(key) => println!(
+                "{}",
+                format!("Signed by {}", self.trust.key_name(key).unwrap_or(key)).green()
+            ),
+            None if self.trust.require_signed() => {
+                return Err("the plugin is not signed with a trusted key, and require_signed_plugins is set; only signed .tar.gz releases can be installed".to_string());
+            }
+            None => {}
+        }
+        Ok(signed_by)
+    }
+
+    /// Reads and validates the manifest of a plugin about to be installed
+    ///
+    /// # Arguments
+    /// * `spec` - Where the plugin comes from, for messages
+    /// * `source_dir` - Checkout of the plugin's source
+    /// * `updating` - Name of the installed plugin being updated, which the
+    ///   new manifest must have, if this is an update
+    ///
+    /// # Returns
+    /// * `Result<PluginManifest, String>` - The manifest, or every problem
+    ///   that prevents installing it
+    fn check_manifest(
+        &self,
+        spec: &str,
+        source_dir: &Path,
+        updating: Option<&str>,
+    ) -> Result<PluginManifest, String> {
+        if !source_dir.join(MANIFEST_FILE).is_file() {
+            return Err(format!("{} has no {} manifest", spec, MANIFEST_FILE));
+        }
+        let manifest: PluginManifest = PluginManifest::read(source_dir)
+            .map_err(|e| e.replacen(&source_dir.display().to_string(), spec, 1))?;
+
+        if let Some(name) = updating {
+            if manifest.name != name {
+                return Err(format!(
+                    "{} now provides plugin '{}' instead of '{}'",
+                    spec, manifest.name, name
+                ));
+            }
+        }
+        let installed: Vec<InstalledPlugin> = self.installed()?;
+        if updating.is_none()
+            && installed.iter().any(|(_, other)| {
+                other
+                    .as_ref()
+                    .is_ok_and(|other| other.name == manifest.name)
+            })
+        {
+            return Err(format!(
+                "Plugin '{}' is already installed; use `flux plugin update {}`",
+                manifest.name, manifest.name
+            ));
+        }
+
+        let mut problems: Vec<String> = manifest.validate();
+        problems.extend(manifest.missing_dependencies(&manifests(&installed)));
+        if !problems.is_empty() {
+            return Err(format!(
+                "invalid {}:\n{}",
+                MANIFEST_FILE,
+                problems
+                    .iter()
+                    .map(|problem| format!("  - {}", problem))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+        }
+        Ok(manifest)
+    }
+
     pub fn init_plugin(&self, name: &str) -> Result<(), String> {
         // Check if the plugin already exists
         if self.plugins.contains_key(name) {
@@ -236,7 +944,7 @@ impl PluginManager {
         }
 
         // Check if rust is installed
-        if !which::which("cargo").is_ok() {
+        if which::which("cargo").is_err() {
             return Err("Cargo is not installed".to_string());
         }
 
@@ -255,7 +963,7 @@ edition = "2021"
 crate-type = ["cdylib"]
 
 [dependencies]
-flux = {{ git = "https://github.com/0x800a6/flux" }}
+flux-plugin = {{ git = "https://github.com/0x800a6/flux" }}
 "#,
             name
         );
@@ -263,146 +971,955 @@ flux = {{ git = "https://github.com/0x800a6/flux" }}
         fs::write(PathBuf::from(name).join("Cargo.toml"), cargo_toml)
             .map_err(|e| format!("Failed to write Cargo.toml: {}", e))?;
 
+        // The example is named "example" in both its code and its manifest
+        let plugin_name: String = format!("\"{}\"", name);
         let example_code = include_str!("../../examples/plugin/src/lib.rs");
-        fs::write(PathBuf::from(name).join("src/lib.rs"), example_code)
-            .map_err(|e| format!("Failed to write lib.rs: {}", e))?;
+        fs::write(
+            PathBuf::from(name).join("src/lib.rs"),
+            example_code.replace("\"example\"", &plugin_name),
+        )
+        .map_err(|e| format!("Failed to write lib.rs: {}", e))?;
+
+        let example_manifest = include_str!("../../examples/plugin/flux-plugin.toml");
+        fs::write(
+            PathBuf::from(name).join(MANIFEST_FILE),
+            example_manifest.replace("\"example\"", &plugin_name),
+        )
+        .map_err(|e| format!("Failed to write {}: {}", MANIFEST_FILE, e))?;
 
         println!("{}", "Plugin project created successfully!".green());
         println!("You can find it in the '{}' directory", name);
         Ok(())
     }
 
-    pub fn list_plugins(&self) -> Result<(), String> {
+    /// Prints the installed plugins from their manifests
+    ///
+    /// # Arguments
+    /// * `timings` - Whether to load each enabled plugin and report how long
+    ///   that takes; otherwise no plugin code runs
+    pub fn list_plugins(&mut self, timings: bool) -> Result<(), String> {
         println!("{}", "Installed plugins:".bright_yellow());
-        for entry in std::fs::read_dir(&self.plugin_dir).map_err(|e| e.to_string())? {
-            let entry: fs::DirEntry = entry.map_err(|e| e.to_string())?;
-            let path: PathBuf = entry.path();
+        let installed: Vec<InstalledPlugin> = self.installed()?;
+        let manifests: HashMap<String, PluginManifest> = manifests(&installed);
+        let lockfile: Lockfile = Lockfile::read(&self.plugin_dir)?;
+        let state: PluginState = PluginState::read(&self.plugin_dir)?;
+        let mut startup: Duration = Duration::ZERO;
+        for (dir, manifest) in installed {
+            let manifest: PluginManifest = match manifest {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    println!("  {}", e.red());
+                    println!();
+                    continue;
+                }
+            };
 
-            if let Some(extension) = path.extension() {
-                if extension == "flp" && path.file_name().unwrap().to_string_lossy() != "temp" {
-                    if let Some(name) = path.file_stem() {
-                        let name_str = name.to_string_lossy();
-                        let parts: Vec<&str> = name_str.split('.').collect();
-                        if parts.len() >= 2 {
-                            if let Ok(url) = BASE64.decode(parts[1]) {
-                                if let Ok(url_str) = String::from_utf8(url) {
-                                    if let Ok(info) = self.get_plugin_info(&path) {
-                                        println!(
-                                            "  {} (v{}) - {}",
-                                            info.name, info.version, info.description
-                                        );
-                                        println!("    Source: {}", url_str);
-                                        println!("    Commands:");
-                                        for (cmd, desc) in info.commands {
-                                            println!("      {} - {}", cmd, desc);
-                                        }
-                                        println!();
-                                    }
-                                }
-                            }
+            let enabled: bool = state.is_enabled(&manifest.name);
+            println!(
+                "  {} (v{}) - {}{}",
+                manifest.name,
+                manifest.version,
+                manifest.description,
+                if enabled {
+                    String::new()
+                } else {
+                    format!(" {}", "(disabled)".bright_yellow())
+                }
+            );
+            match lockfile.plugins.get(&manifest.name) {
+                Some(locked) if locked.resolved != locked.source => {
+                    println!("    Source: {} ({})", locked.source, locked.resolved)
+                }
+                Some(locked) => println!("    Source: {}", locked.source),
+                None => {
+                    if let Ok(url) = fs::read_to_string(dir.join(SOURCE_FILE)) {
+                        println!("    Source: {}", url.trim());
+                    }
+                }
+            }
+            let locked: Option<&LockedPlugin> = lockfile.plugins.get(&manifest.name);
+            if let Some(key) = locked.and_then(|locked| locked.signed_by.as_deref()) {
+                match self.trust.key_name(key) {
+                    Some(name) => println!("    Signed by: {}", name),
+                    None => println!(
+                        "    Signed by: {} {}",
+                        key,
+                        "(no longer trusted)".bright_yellow()
+                    ),
+                }
+            }
+            let verified: Result<(), String> =
+                self.verify_installed(&manifest, &installed_file(&dir, &manifest), locked);
+            if let Err(e) = &verified {
+                let reason: &str = e.split_once(": ").map_or(e.as_str(), |(_, reason)| reason);
+                println!("    {}", format!("Will not load: {}", reason).red());
+            }
+            if timings && enabled && verified.is_ok() {
+                let when: &str = if manifest.loads_at_startup() {
+                    "at startup"
+                } else {
+                    "on first use"
+                };
+                match self.load_plugin(&dir, &manifest, locked) {
+                    Ok(took) => {
+                        if manifest.loads_at_startup() {
+                            startup += took;
                         }
+                        println!("    Load time: {} ({})", format_duration(took), when)
                     }
+                    Err(e) => println!("    {}", format!("Failed to load: {}", e).red()),
                 }
             }
+            match manifest.runtime.as_str() {
+                "wasm" => println!("    Runtime: WebAssembly (sandboxed)"),
+                "process" => println!("    Runtime: external process"),
+                _ => {}
+            }
+            if !manifest.permissions.is_empty() {
+                println!("    Permissions: {}", manifest.permissions.join(", "));
+            }
+            if !manifest.dependencies.is_empty() {
+                println!(
+                    "    Requires: {}",
+                    manifest
+                        .dependencies
+                        .iter()
+                        .map(|(name, requirement)| format!("{} {}", name, requirement))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            println!("    Commands:");
+            let shadowed: HashMap<String, String> = self
+                .shadowed_commands(&manifest, &manifests)
+                .into_iter()
+                .collect();
+            for (cmd, desc) in &manifest.commands {
+                match shadowed.get(cmd) {
+                    Some(by) => println!(
+                        "      {} - {} {}",
+                        cmd,
+                        desc,
+                        format!("(taken by {}; run it as {}::{})", by, manifest.name, cmd)
+                            .bright_yellow()
+                    ),
+                    None => println!("      {} - {}", cmd, desc),
+                }
+            }
+            println!();
         }
-        Ok(())
-    }
 
-    fn get_plugin_info(&self, path: &PathBuf) -> Result<PluginInfo, String> {
-        unsafe {
-            let library: Library = Library::new(path).map_err(|e| e.to_string())?;
-            let create_plugin: Symbol<fn() -> Box<dyn FluxPlugin>> =
-                library.get(b"create_plugin").map_err(|e| e.to_string())?;
-
-            let plugin: Box<dyn FluxPlugin> = create_plugin();
-            Ok(PluginInfo {
-                name: plugin.name().to_string(),
-                version: plugin.version().to_string(),
-                description: plugin.description().to_string(),
-                commands: plugin
-                    .commands()
-                    .into_iter()
-                    .map(|(cmd, desc)| (cmd.to_string(), desc.to_string()))
-                    .collect(),
-            })
+        for path in self.legacy_plugins()? {
+            let name: String = legacy_name(&path);
+            println!(
+                "  {} - {}{}",
+                name,
+                "no manifest; reinstall it with `flux plugin install`".bright_yellow(),
+                if state.is_enabled(&name) {
+                    String::new()
+                } else {
+                    format!(" {}", "(disabled)".bright_yellow())
+                }
+            );
+            if let Some(source) = legacy_source(&path) {
+                println!("    Source: {}", source);
+            }
+            println!();
+        }
+
+        if timings {
+            println!(
+                "Loading plugins at startup takes {}",
+                format_duration(startup)
+            );
+            self.cleanup();
         }
+        Ok(())
     }
 
     pub fn remove_plugin(&self, name: &str) -> Result<(), String> {
-        for entry in std::fs::read_dir(&self.plugin_dir).map_err(|e| e.to_string())? {
-            let entry: fs::DirEntry = entry.map_err(|e| e.to_string())?;
-            let path: PathBuf = entry.path();
-
-            if let Some(extension) = path.extension() {
-                if extension == "flp" {
-                    if let Ok(info) = self.get_plugin_info(&path) {
-                        if info.name == name {
-                            return std::fs::remove_file(path)
-                                .map_err(|e| format!("Failed to remove plugin: {}", e));
-                        }
-                    }
+        let dir: PathBuf = match self.find_plugin(name) {
+            Ok(dir) => dir,
+            Err(e) => {
+                let path: PathBuf = self.find_legacy(name)?.ok_or(e)?;
+                fs::remove_file(path).map_err(|e| format!("Failed to remove plugin: {}", e))?;
+                let mut state: PluginState = PluginState::read(&self.plugin_dir)?;
+                if state.disabled.remove(name) {
+                    state.write(&self.plugin_dir)?;
                 }
+                return Ok(());
             }
+        };
+
+        let dependents: Vec<String> = self.dependents(name)?;
+        if !dependents.is_empty() {
+            return Err(format!(
+                "Plugin '{}' is required by: {}",
+                name,
+                dependents.join(", ")
+            ));
+        }
+
+        fs::remove_dir_all(dir).map_err(|e| format!("Failed to remove plugin: {}", e))?;
+        let mut lockfile: Lockfile = Lockfile::read(&self.plugin_dir)?;
+        if lockfile.plugins.remove(name).is_some() {
+            lockfile.write(&self.plugin_dir)?;
+        }
+        let mut state: PluginState = PluginState::read(&self.plugin_dir)?;
+        if state.disabled.remove(name) {
+            state.write(&self.plugin_dir)?;
         }
-        Err(format!("Plugin '{}' not found", name))
+        Ok(())
     }
 
+    /// Turns an installed plugin on or off, for shells started from now on
+    ///
+    /// # Arguments
+    /// * `name` - Name of the installed plugin
+    /// * `enabled` - Whether it is loaded
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), String> {
+        self.find_plugin(name)
+            .or_else(|e| self.find_legacy(name)?.ok_or(e))?;
+        let mut state: PluginState = PluginState::read(&self.plugin_dir)?;
+        if enabled {
+            state.disabled.remove(name);
+        } else {
+            let dependents: Vec<String> = self
+                .dependents(name)?
+                .into_iter()
+                .filter(|dependent| state.is_enabled(dependent))
+                .collect();
+            if !dependents.is_empty() {
+                return Err(format!(
+                    "Plugin '{}' is required by: {}; disable those first",
+                    name,
+                    dependents.join(", ")
+                ));
+            }
+            state.disabled.insert(name.to_string());
+        }
+        state.write(&self.plugin_dir)?;
+        println!(
+            "{}",
+            format!(
+                "Plugin '{}' {}; new shells will pick this up",
+                name,
+                if enabled { "enabled" } else { "disabled" }
+            )
+            .green()
+        );
+        Ok(())
+    }
+
+    /// Names of the installed plugins that need a plugin
+    fn dependents(&self, name: &str) -> Result<Vec<String>, String> {
+        Ok(self
+            .installed()?
+            .into_iter()
+            .filter_map(|(_, manifest)| manifest.ok())
+            .filter(|manifest| manifest.dependencies.contains_key(name))
+            .map(|manifest| manifest.name)
+            .collect())
+    }
+
+    /// Installs a plugin again from the source it was installed from
+    ///
+    /// # Arguments
+    /// * `name` - Name of the installed plugin
     pub fn update_plugin(&self, name: &str) -> Result<(), String> {
-        // Find the plugin and its URL
-        for entry in std::fs::read_dir(&self.plugin_dir).map_err(|e| e.to_string())? {
+        let dir: PathBuf = self.find_plugin(name)?;
+        let source: String = match Lockfile::read(&self.plugin_dir)?.plugins.get(name) {
+            Some(locked) => locked.source.clone(),
+            None => fs::read_to_string(dir.join(SOURCE_FILE))
+                .map(|url| url.trim().to_string())
+                .map_err(|_| format!("Plugin '{}' has no recorded source to update from", name))?,
+        };
+
+        self.install_from(&source, Some((name, &dir)))
+    }
+
+    /// Commands of a plugin that run something else when typed by themselves
+    ///
+    /// # Arguments
+    /// * `manifest` - The plugin's manifest
+    /// * `manifests` - Manifests of every installed plugin, by name
+    ///
+    /// # Returns
+    /// * `Vec<(String, String)>` - Each such command, and what it runs
+    ///   instead
+    fn shadowed_commands(
+        &self,
+        manifest: &PluginManifest,
+        manifests: &HashMap<String, PluginManifest>,
+    ) -> Vec<(String, String)> {
+        manifest
+            .commands
+            .keys()
+            .filter_map(|command| {
+                let by: String = if let Some(what) = self.reserved.get(command) {
+                    what.clone()
+                } else if command != &manifest.name && manifests.contains_key(command) {
+                    format!("plugin '{}'", command)
+                } else {
+                    // Plugins load in order of name
+                    let first: &String = manifests
+                        .values()
+                        .filter(|other| other.commands.contains_key(command))
+                        .map(|other| &other.name)
+                        .min()
+                        .filter(|first| *first != &manifest.name)?;
+                    format!("plugin '{}', which declares it too", first)
+                };
+                Some((command.clone(), by))
+            })
+            .collect()
+    }
+
+    /// Reads the manifest of every plugin directory
+    fn installed(&self) -> Result<Vec<InstalledPlugin>, String> {
+        let mut installed: Vec<InstalledPlugin> = Vec::new();
+        for entry in fs::read_dir(&self.plugin_dir).map_err(|e| e.to_string())? {
             let entry: fs::DirEntry = entry.map_err(|e| e.to_string())?;
             let path: PathBuf = entry.path();
+            // Plugins being built are staged in hidden directories
+            let hidden: bool = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden {
+                let manifest: Result<PluginManifest, String> = PluginManifest::read(&path);
+                installed.push((path, manifest));
+            }
+        }
+        installed.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(installed)
+    }
 
-            if let Some(extension) = path.extension() {
-                if extension == "flp" {
-                    if let Ok(info) = self.get_plugin_info(&path) {
-                        if info.name == name {
-                            let name_str = path.file_stem().unwrap().to_string_lossy();
-                            let parts: Vec<&str> = name_str.split('.').collect();
-                            if parts.len() >= 2 {
-                                if let Ok(url) = BASE64.decode(parts[1]) {
-                                    if let Ok(url_str) = String::from_utf8(url) {
-                                        // Remove old plugin
-                                        std::fs::remove_file(&path).map_err(|e| {
-                                            format!("Failed to remove old plugin: {}", e)
-                                        })?;
-
-                                        // Install updated version
-                                        return self.install_from_git(&url_str);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    /// Finds the directory of an installed plugin by its manifest name
+    fn find_plugin(&self, name: &str) -> Result<PathBuf, String> {
+        self.installed()?
+            .into_iter()
+            .find(|(_, manifest)| {
+                manifest
+                    .as_ref()
+                    .is_ok_and(|manifest| manifest.name == name)
+            })
+            .map(|(dir, _)| dir)
+            .ok_or_else(|| format!("Plugin '{}' not found", name))
+    }
+
+    /// Finds plugins installed by older versions as bare `.flp` files, which
+    /// have no manifest
+    fn legacy_plugins(&self) -> Result<Vec<PathBuf>, String> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for entry in fs::read_dir(&self.plugin_dir).map_err(|e| e.to_string())? {
+            let entry: fs::DirEntry = entry.map_err(|e| e.to_string())?;
+            let path: PathBuf = entry.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "flp") {
+                paths.push(path);
             }
         }
-        Err(format!("Plugin '{}' not found", name))
+        paths.sort();
+        Ok(paths)
     }
 
-    pub fn new_without_loading() -> Self {
-        let plugin_dir: PathBuf = dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("sh.lrr.flux")
-            .join("plugins");
+    /// Finds a plugin installed as a bare `.flp` file by its name
+    fn find_legacy(&self, name: &str) -> Result<Option<PathBuf>, String> {
+        Ok(self
+            .legacy_plugins()?
+            .into_iter()
+            .find(|path| legacy_name(path) == name))
+    }
+}
 
-        let temp_dir: PathBuf = plugin_dir.join("temp");
+/// Git URL a bare `.flp` file was installed from, encoded in its file name
+/// as `<uuid>.<base64 url>.flp`
+fn legacy_source(path: &Path) -> Option<String> {
+    let stem: String = path.file_stem()?.to_string_lossy().to_string();
+    let url: Vec<u8> = BASE64.decode(stem.split('.').nth(1)?).ok()?;
+    String::from_utf8(url).ok()
+}
 
-        std::fs::create_dir_all(&plugin_dir).unwrap_or_default();
-        std::fs::create_dir_all(&temp_dir).unwrap_or_default();
+/// Name a bare `.flp` file is listed, disabled and removed by: the last
+/// part of the URL it came from, or its file name if that is unknown
+fn legacy_name(path: &Path) -> String {
+    let stem: String = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    legacy_source(path)
+        .and_then(|url| {
+            let name: &str = url
+                .trim_end_matches('/')
+                .rsplit(['/', ':'])
+                .next()?
+                .trim_end_matches(".git");
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .unwrap_or(stem)
+}
 
-        PluginManager {
-            plugins: HashMap::new(),
-            plugin_dir,
-            temp_dir,
+/// The file an installed plugin runs from: its library, module or executable
+fn installed_file(dir: &Path, manifest: &PluginManifest) -> PathBuf {
+    match manifest.runtime.as_str() {
+        "wasm" => dir.join(WASM_FILE),
+        "process" => dir.join(manifest.executable.as_deref().unwrap_or_default()),
+        _ => dir.join(LIBRARY_FILE),
+    }
+}
+
+/// Checks an enabled plugin's manifest and that the plugins it needs are
+/// installed and enabled
+///
+/// # Arguments
+/// * `manifest` - The plugin's manifest
+/// * `manifests` - Manifests of every installed plugin, by name
+/// * `state` - Which plugins are disabled
+fn check_plugin(
+    manifest: &PluginManifest,
+    manifests: &HashMap<String, PluginManifest>,
+    state: &PluginState,
+) -> Result<(), String> {
+    let mut problems: Vec<String> = manifest.validate();
+    problems.extend(manifest.missing_dependencies(manifests));
+    for dependency in manifest.dependencies.keys() {
+        if manifests.contains_key(dependency) && !state.is_enabled(dependency) {
+            problems.push(format!("needs plugin '{}', which is disabled", dependency));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(format!("{}: {}", manifest.name, problems.join("; ")));
+    }
+    Ok(())
+}
+
+/// The result of declining the installation prompt
+///
+/// # Arguments
+/// * `updating` - Whether an installed plugin was being updated, which is
+///   then left as it was
+fn cancelled(updating: bool) -> Result<(), String> {
+    if updating {
+        return Err("update cancelled; the installed version was kept".to_string());
+    }
+    Ok(())
+}
+
+/// Moves a freshly built plugin into place, replacing the installed one
+///
+/// The old directory is kept until the new one is in place, and put back if
+/// that fails.
+///
+/// # Arguments
+/// * `staging_dir` - Where the plugin was built
+/// * `target_dir` - Where it is installed
+/// * `old_dir` - The installed version's directory, if any, usually
+///   `target_dir`
+fn replace_dir(staging_dir: &Path, target_dir: &Path, old_dir: &Path) -> Result<(), String> {
+    let backup: PathBuf = staging_dir.with_extension("old");
+    let had_old: bool = old_dir.exists();
+    if had_old {
+        fs::remove_dir_all(&backup).ok();
+        fs::rename(old_dir, &backup)
+            .map_err(|e| format!("Failed to move {} aside: {}", old_dir.display(), e))?;
+    }
+    if let Err(e) = fs::rename(staging_dir, target_dir) {
+        if had_old {
+            fs::rename(&backup, old_dir).ok();
         }
+        return Err(format!(
+            "Failed to install into {}: {}",
+            target_dir.display(),
+            e
+        ));
     }
+    if had_old {
+        fs::remove_dir_all(&backup).ok();
+    }
+    Ok(())
+}
+
+/// A load time in milliseconds, to a tenth of one
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Collects the manifests that could be read, by plugin name
+fn manifests(installed: &[InstalledPlugin]) -> HashMap<String, PluginManifest> {
+    installed
+        .iter()
+        .filter_map(|(_, manifest)| manifest.as_ref().ok())
+        .map(|manifest| (manifest.name.clone(), manifest.clone()))
+        .collect()
+}
+
+/// Shows what a plugin is and the permissions it asks for before installing
+fn print_summary(manifest: &PluginManifest) {
+    println!(
+        "\n{} (v{}) - {}",
+        manifest.name.bold(),
+        manifest.version,
+        manifest.description
+    );
+    if manifest.permissions.is_empty() {
+        println!("Requests no permissions");
+    } else {
+        println!("Requests permission to:");
+        for permission in &manifest.permissions {
+            let description: &str = PERMISSIONS
+                .iter()
+                .find(|(name, _)| name == permission)
+                .map_or("", |(_, description)| description);
+            println!("  {} - {}", permission.bright_yellow(), description);
+        }
+    }
+    println!();
+}
+
+/// Builds a native or WebAssembly plugin with cargo and copies the result
+/// into its install directory
+///
+/// # Arguments
+/// * `manifest` - The plugin's manifest
+/// * `source_dir` - Checkout of the plugin's source
+/// * `target_dir` - Directory the plugin is installed into
+fn install_compiled_plugin(
+    manifest: &PluginManifest,
+    source_dir: &Path,
+    target_dir: &Path,
+) -> Result<(), String> {
+    let wasm: bool = manifest.runtime == "wasm";
+    let mut build_args: Vec<&str> = vec!["build", "--release"];
+    if wasm {
+        build_args.extend(["--target", "wasm32-unknown-unknown"]);
+    }
+    let status: std::process::ExitStatus = Command::new("cargo")
+        .args(&build_args)
+        .current_dir(source_dir)
+        .status()
+        .map_err(|e| format!("Failed to build plugin: {}", e))?;
+    if !status.success() {
+        return Err(format!(
+            "Failed to build plugin: cargo exited with {}",
+            status
+        ));
+    }
+
+    let source_path: PathBuf = cargo_artifact(source_dir, wasm)?;
+    let artifact: &str = if wasm { WASM_FILE } else { LIBRARY_FILE };
+    fs::create_dir_all(target_dir)
+        .and_then(|_| fs::copy(&source_path, target_dir.join(artifact)))
+        .and_then(|_| {
+            fs::copy(
+                source_dir.join(MANIFEST_FILE),
+                target_dir.join(MANIFEST_FILE),
+            )
+        })
+        .map(|_| ())
+        .map_err(|e| format!("Failed to install plugin: {}", e))
 }
 
-#[derive(Debug)]
-struct PluginInfo {
+/// Output of `cargo metadata`, as far as it is needed to find a library
+#[derive(Deserialize)]
+struct CargoMetadata {
+    packages: Vec<CargoPackage>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+    manifest_path: PathBuf,
+    targets: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
     name: String,
-    version: String,
-    description: String,
-    commands: Vec<(String, String)>,
+    kind: Vec<String>,
+}
+
+/// Finds the library `cargo build --release` makes of a plugin's package
+///
+/// The package may be part of a larger workspace, whose target directory
+/// the library is then built into.
+///
+/// # Arguments
+/// * `source_dir` - Directory holding the plugin's `Cargo.toml`
+/// * `wasm` - Whether it was built for WebAssembly
+///
+/// # Returns
+/// * `Result<PathBuf, String>` - Path of the built library
+fn cargo_artifact(source_dir: &Path, wasm: bool) -> Result<PathBuf, String> {
+    let output: std::process::Output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .current_dir(source_dir)
+        .output()
+        .map_err(|e| format!("Failed to run cargo metadata: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let metadata: CargoMetadata = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to read cargo metadata: {}", e))?;
+
+    let manifest_path: PathBuf = source_dir
+        .join("Cargo.toml")
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", source_dir.join("Cargo.toml").display(), e))?;
+    let package: &CargoPackage = metadata
+        .packages
+        .iter()
+        .find(|package| package.manifest_path == manifest_path)
+        .ok_or_else(|| format!("{} has no package", manifest_path.display()))?;
+    let target: &CargoTarget = package
+        .targets
+        .iter()
+        .find(|target| target.kind.iter().any(|kind| kind == "cdylib"))
+        .ok_or_else(|| {
+            format!(
+                "package '{}' builds no library plugin; set crate-type = [\"cdylib\"] under [lib]",
+                package.name
+            )
+        })?;
+
+    let lib_name: String = target.name.replace('-', "_");
+    Ok(if wasm {
+        metadata
+            .target_directory
+            .join("wasm32-unknown-unknown/release")
+            .join(format!("{}.wasm", lib_name))
+    } else {
+        metadata.target_directory.join("release").join(format!(
+            "{}{}{}",
+            std::env::consts::DLL_PREFIX,
+            lib_name,
+            std::env::consts::DLL_SUFFIX
+        ))
+    })
+}
+
+/// Runs a process plugin's `build` command, if any, and copies its checkout
+/// into its install directory
+///
+/// # Arguments
+/// * `manifest` - The plugin's manifest
+/// * `source_dir` - Checkout of the plugin's source
+/// * `target_dir` - Directory the plugin is installed into
+fn install_process_plugin(
+    manifest: &PluginManifest,
+    source_dir: &Path,
+    target_dir: &Path,
+) -> Result<(), String> {
+    if let Some(build) = &manifest.build {
+        println!("Running {}", build.bold());
+        let status = Command::new("sh")
+            .args(["-c", build])
+            .current_dir(source_dir)
+            .status()
+            .map_err(|e| format!("Failed to build plugin: {}", e))?;
+        if !status.success() {
+            return Err(format!("Failed to build plugin: `{}` {}", build, status));
+        }
+    }
+
+    copy_tree(source_dir, target_dir).map_err(|e| format!("Failed to install plugin: {}", e))?;
+
+    let executable: &str = manifest.executable.as_deref().unwrap_or_default();
+    if !target_dir.join(executable).is_file() {
+        return Err(format!(
+            "Failed to install plugin: executable '{}' does not exist",
+            executable
+        ));
+    }
+    Ok(())
+}
+
+/// Copies a directory recursively, leaving out `.git`
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry: fs::DirEntry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let destination: PathBuf = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use source::ArchiveSignature;
+    use uuid::Uuid;
+
+    /// A plugin manager in a fresh temporary directory that trusts one key
+    fn trusting_manager(key: &SigningKey, require_signed: bool) -> (PluginManager, PathBuf) {
+        let root: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let keys_file: PathBuf = root.join("trusted_keys");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            &keys_file,
+            format!(
+                "{} release\n",
+                BASE64.encode(key.verifying_key().as_bytes())
+            ),
+        )
+        .unwrap();
+
+        let mut manager: PluginManager = PluginManager::new(root.join("plugins"), root.join("tmp"));
+        let (trust, warnings) = TrustPolicy::load(&keys_file, require_signed);
+        assert!(warnings.is_empty());
+        manager.set_trust(trust);
+        (manager, root)
+    }
+
+    /// A checkout of a local directory, signed with a key if one is given
+    fn checkout(root: &Path, signer: Option<&SigningKey>) -> Checkout {
+        let archive: PathBuf = root.join("notes-1.0.0.tar.gz");
+        fs::write(&archive, "archive").unwrap();
+        let mut checkout: Checkout = PluginSource::Local(root.to_path_buf())
+            .fetch(&root.join("tmp"))
+            .unwrap();
+        checkout.signature = signer.map(|key| ArchiveSignature {
+            archive,
+            signature: BASE64.encode(key.sign(b"archive").to_bytes()),
+        });
+        checkout
+    }
+
+    fn manifest() -> PluginManifest {
+        toml::from_str("name = \"notes\"\nversion = \"1.0.0\"\nruntime = \"wasm\"\n").unwrap()
+    }
+
+    /// Installs a module for the manifest and returns its lockfile entry
+    fn install(root: &Path, signed_by: Option<String>) -> (PathBuf, LockedPlugin) {
+        let file: PathBuf = root.join(WASM_FILE);
+        fs::write(&file, "module").unwrap();
+        let locked: LockedPlugin = LockedPlugin {
+            version: "1.0.0".to_string(),
+            source: "notes".to_string(),
+            resolved: "notes".to_string(),
+            checksum: Some(trust::checksum(&file).unwrap()),
+            signed_by,
+        };
+        (file, locked)
+    }
+
+    #[test]
+    fn accepts_releases_signed_with_a_trusted_key() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (manager, root) = trusting_manager(&key, true);
+
+        let signed_by: Option<String> = manager
+            .check_signature(&checkout(&root, Some(&key)))
+            .unwrap();
+        assert_eq!(
+            signed_by,
+            Some(BASE64.encode(key.verifying_key().as_bytes()))
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_unsigned_releases_when_signatures_are_required() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let other: SigningKey = SigningKey::from_bytes(&[2; 32]);
+
+        let (manager, root) = trusting_manager(&key, false);
+        assert_eq!(manager.check_signature(&checkout(&root, None)), Ok(None));
+        assert_eq!(
+            manager.check_signature(&checkout(&root, Some(&other))),
+            Ok(None)
+        );
+        fs::remove_dir_all(root).unwrap();
+
+        let (manager, root) = trusting_manager(&key, true);
+        assert!(manager.check_signature(&checkout(&root, None)).is_err());
+        assert!(manager
+            .check_signature(&checkout(&root, Some(&other)))
+            .is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_installed_files_that_changed() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (manager, root) = trusting_manager(&key, false);
+        let (file, locked) = install(&root, None);
+        assert!(manager
+            .verify_installed(&manifest(), &file, Some(&locked))
+            .is_ok());
+
+        fs::write(&file, "modified").unwrap();
+        let error: String = manager
+            .verify_installed(&manifest(), &file, Some(&locked))
+            .unwrap_err();
+        assert!(error.contains("has changed since it was installed"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_unsigned_installed_plugins_when_signatures_are_required() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (manager, root) = trusting_manager(&key, true);
+
+        let (file, unsigned) = install(&root, None);
+        assert!(manager
+            .verify_installed(&manifest(), &file, Some(&unsigned))
+            .is_err());
+        assert!(manager.verify_installed(&manifest(), &file, None).is_err());
+
+        let signed_by: String = BASE64.encode(key.verifying_key().as_bytes());
+        let (file, signed) = install(&root, Some(signed_by));
+        assert!(manager
+            .verify_installed(&manifest(), &file, Some(&signed))
+            .is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_plugins_whose_dependencies_are_missing_or_disabled() {
+        let notes: PluginManifest = toml::from_str(
+            "name = \"notes\"\nversion = \"1.0.0\"\n[dependencies]\nstorage = \"^1\"\n",
+        )
+        .unwrap();
+        let storage: PluginManifest =
+            toml::from_str("name = \"storage\"\nversion = \"1.2.0\"\n").unwrap();
+        let mut manifests: HashMap<String, PluginManifest> = HashMap::new();
+        let mut state: PluginState = PluginState::default();
+
+        manifests.insert(notes.name.clone(), notes.clone());
+        let e: String = check_plugin(&notes, &manifests, &state).unwrap_err();
+        assert_eq!(
+            e,
+            "notes: needs plugin 'storage' ^1, which is not installed"
+        );
+
+        manifests.insert(storage.name.clone(), storage);
+        assert!(check_plugin(&notes, &manifests, &state).is_ok());
+
+        state.disabled.insert("storage".to_string());
+        let e: String = check_plugin(&notes, &manifests, &state).unwrap_err();
+        assert_eq!(e, "notes: needs plugin 'storage', which is disabled");
+    }
+
+    #[test]
+    fn skips_disables_and_removes_legacy_plugin_files() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (mut manager, root) = trusting_manager(&key, false);
+        let url: String = BASE64.encode("https://github.com/user/notes.git");
+        let file: PathBuf = root
+            .join("plugins")
+            .join(format!("{}.{}.flp", Uuid::new_v4(), url));
+        fs::write(&file, "not a library").unwrap();
+
+        assert_eq!(legacy_name(&file), "notes");
+        assert!(manager.load_plugins(&HashMap::new()).is_ok());
+        assert!(manager.plugin_names().is_empty());
+
+        manager.set_enabled("notes", false).unwrap();
+        let state: PluginState = PluginState::read(&root.join("plugins")).unwrap();
+        assert!(!state.is_enabled("notes"));
+
+        manager.remove_plugin("notes").unwrap();
+        assert!(!file.exists());
+        assert!(PluginState::read(&root.join("plugins"))
+            .unwrap()
+            .is_enabled("notes"));
+        assert!(manager.remove_plugin("notes").is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Installs a manifest without a library, for a plugin that is only
+    /// loaded once one of its commands runs
+    fn install_manifest(plugin_dir: &Path, name: &str, commands: &[&str]) {
+        let dir: PathBuf = plugin_dir.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let commands: String = commands
+            .iter()
+            .map(|command| format!("{} = \"\"\n", command))
+            .collect();
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            format!(
+                "name = \"{}\"\nversion = \"1.0.0\"\n[commands]\n{}",
+                name, commands
+            ),
+        )
+        .unwrap();
+    }
+
+    /// A plugin manager with three plugins whose commands overlap
+    fn manager_with_commands() -> (PluginManager, PathBuf) {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (mut manager, root) = trusting_manager(&key, false);
+        let plugin_dir: PathBuf = root.join("plugins");
+        install_manifest(&plugin_dir, "notes", &["note", "todo"]);
+        install_manifest(&plugin_dir, "tasks", &["todo", "notes"]);
+        install_manifest(&plugin_dir, "extras", &["cd"]);
+        manager.set_reserved(HashMap::from([(
+            "cd".to_string(),
+            "a shell builtin".to_string(),
+        )]));
+        manager.load_plugins(&HashMap::new()).unwrap();
+        (manager, root)
+    }
+
+    fn runs(plugin: &str, command: Option<&str>) -> (String, Option<String>) {
+        (plugin.to_string(), command.map(String::from))
+    }
+
+    #[test]
+    fn resolves_commands_by_name_and_qualified_name() {
+        let (manager, root) = manager_with_commands();
+        assert_eq!(manager.resolve("note"), Some(runs("notes", Some("note"))));
+        assert_eq!(
+            manager.resolve("tasks::todo"),
+            Some(runs("tasks", Some("todo")))
+        );
+        assert_eq!(manager.resolve("tasks::note"), None);
+        assert_eq!(manager.resolve("missing::todo"), None);
+        assert_eq!(manager.resolve("missing"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn resolves_shared_names_to_the_first_plugin() {
+        let (manager, root) = manager_with_commands();
+        // Of plugins declaring the same command, the first loaded runs it
+        assert_eq!(
+            manager.lookup("todo"),
+            vec![runs("notes", Some("todo")), runs("tasks", Some("todo"))]
+        );
+        assert_eq!(manager.resolve("todo"), Some(runs("notes", Some("todo"))));
+        // A plugin's own name comes before another plugin's command
+        assert_eq!(
+            manager.lookup("notes"),
+            vec![runs("notes", None), runs("tasks", Some("notes"))]
+        );
+        assert_eq!(
+            manager.resolve("tasks::notes"),
+            Some(runs("tasks", Some("notes")))
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn leaves_reserved_names_to_the_shell() {
+        let (manager, root) = manager_with_commands();
+        assert_eq!(manager.resolve("cd"), None);
+        assert_eq!(manager.lookup("cd"), vec![runs("extras", Some("cd"))]);
+        assert_eq!(
+            manager.resolve("extras::cd"),
+            Some(runs("extras", Some("cd")))
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
 }
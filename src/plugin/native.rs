@@ -0,0 +1,240 @@
+use super::ExecutionContext;
+use flux_plugin::abi::{
+    DescriptorFn, FfiContext, FfiHookResult, FfiResult, FfiStatus, FfiStr, FfiStringList,
+    PluginDescriptor, PluginVTable, ABI_VERSION, DESCRIPTOR_SYMBOL, STDERR,
+};
+use flux_plugin::hooks::{REWRITE, VETO};
+use flux_plugin::{Host, Preexec, Stream};
+use libloading::Library;
+use std::ffi::c_void;
+use std::path::Path;
+
+/// A plugin loaded from a shared library through the `flux_plugin` ABI
+///
+/// Every call goes through the library's function table; strings returned by
+/// the plugin are copied and handed back to it to be freed.
+pub struct NativePlugin {
+    instance: *mut c_void,
+    vtable: PluginVTable,
+    /// Kept last so the library is unloaded after the instance is destroyed
+    _library: Library,
+}
+
+impl NativePlugin {
+    /// Loads a plugin library and creates its instance
+    ///
+    /// # Arguments
+    /// * `path` - Path to the shared library
+    ///
+    /// # Returns
+    /// * `Result<NativePlugin, String>` - The plugin, or why it cannot be
+    ///   loaded, such as an incompatible ABI version
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let library: Library =
+            unsafe { Library::new(path) }.map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let descriptor: *const PluginDescriptor =
+            match unsafe { library.get::<DescriptorFn>(DESCRIPTOR_SYMBOL) } {
+                Ok(descriptor) => unsafe { descriptor() },
+                Err(_) if unsafe { library.get::<*const c_void>(b"create_plugin") }.is_ok() => {
+                    return Err(format!(
+                    "{}: plugin uses the old unversioned interface; rebuild it against flux-plugin",
+                    path.display()
+                ));
+                }
+                Err(e) => return Err(format!("{}: not a flux plugin ({})", path.display(), e)),
+            };
+        if descriptor.is_null() {
+            return Err(format!("{}: plugin returned no descriptor", path.display()));
+        }
+
+        // The version is the first field of every ABI version
+        let abi_version: u32 = unsafe { *(descriptor as *const u32) };
+        if abi_version != ABI_VERSION {
+            return Err(format!(
+                "{}: plugin ABI version {} is not supported (this flux uses version {}); rebuild the plugin against a matching flux-plugin",
+                path.display(),
+                abi_version,
+                ABI_VERSION
+            ));
+        }
+
+        let vtable: PluginVTable = unsafe { (*descriptor).vtable };
+        let instance: *mut c_void = unsafe { (vtable.create)() };
+        if instance.is_null() {
+            return Err(format!(
+                "{}: plugin failed to create an instance",
+                path.display()
+            ));
+        }
+
+        Ok(NativePlugin {
+            instance,
+            vtable,
+            _library: library,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        unsafe { (self.vtable.name)(self.instance).read() }
+    }
+
+    /// Prompt segments as (name, value) pairs
+    pub fn prompt_segments(&self) -> Vec<(String, String)> {
+        self.pairs(unsafe { (self.vtable.prompt_segments)(self.instance) })
+    }
+
+    pub fn init(&mut self) -> Result<(), String> {
+        self.result(unsafe { (self.vtable.init)(self.instance) })
+    }
+
+    /// Runs a command
+    ///
+    /// # Returns
+    /// * `Result<i32, String>` - The exit code, or the error the command
+    ///   returned
+    pub fn execute(&self, context: &mut ExecutionContext, args: &[String]) -> Result<i32, String> {
+        let args: Vec<FfiStr> = args.iter().map(|arg| FfiStr::new(arg)).collect();
+        let FfiStatus { ok, code, error } = with_ffi_context(context, |ffi_context| unsafe {
+            (self.vtable.execute)(self.instance, ffi_context, args.as_ptr(), args.len())
+        });
+        let message: String = unsafe { error.read() };
+        unsafe { (self.vtable.free_string)(error) };
+        if ok {
+            Ok(code)
+        } else {
+            Err(message)
+        }
+    }
+
+    /// Calls a hook
+    ///
+    /// # Arguments
+    /// * `context` - What the hook runs with
+    /// * `args` - The hook's name followed by its arguments
+    ///
+    /// # Returns
+    /// * `Result<Preexec, String>` - What to do with the command, for
+    ///   `preexec`, or the hook's error
+    pub fn hook(&self, context: &mut ExecutionContext, args: &[String]) -> Result<Preexec, String> {
+        let args: Vec<FfiStr> = args.iter().map(|arg| FfiStr::new(arg)).collect();
+        let FfiHookResult { ok, action, text } = with_ffi_context(context, |ffi_context| unsafe {
+            (self.vtable.hook)(self.instance, ffi_context, args.as_ptr(), args.len())
+        });
+        let text_value: String = unsafe { text.read() };
+        unsafe { (self.vtable.free_string)(text) };
+        match (ok, action) {
+            (false, _) => Err(text_value),
+            (true, REWRITE) => Ok(Preexec::Rewrite(text_value)),
+            (true, VETO) => Ok(Preexec::Veto),
+            (true, _) => Ok(Preexec::Run),
+        }
+    }
+
+    /// Completion candidates for the last of `args`
+    pub fn complete(&self, args: &[String]) -> Vec<String> {
+        let args: Vec<FfiStr> = args.iter().map(|arg| FfiStr::new(arg)).collect();
+        let list: FfiStringList =
+            unsafe { (self.vtable.complete)(self.instance, args.as_ptr(), args.len()) };
+        let items: Vec<String> = unsafe { list.read() };
+        unsafe { (self.vtable.free_list)(list) };
+        items
+    }
+
+    pub fn cleanup(&mut self) -> Result<(), String> {
+        self.result(unsafe { (self.vtable.cleanup)(self.instance) })
+    }
+
+    /// Copies a result and frees its message
+    fn result(&self, result: FfiResult) -> Result<(), String> {
+        let FfiResult { ok, error } = result;
+        let message: String = unsafe { error.read() };
+        unsafe { (self.vtable.free_string)(error) };
+        if ok {
+            Ok(())
+        } else {
+            Err(message)
+        }
+    }
+
+    /// Copies a flat list of pairs and frees it
+    fn pairs(&self, list: FfiStringList) -> Vec<(String, String)> {
+        let items: Vec<String> = unsafe { list.read() };
+        unsafe { (self.vtable.free_list)(list) };
+        items
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+}
+
+impl Drop for NativePlugin {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.instance) };
+    }
+}
+
+/// Passes a context to a plugin call as an `FfiContext`
+fn with_ffi_context<R>(context: &mut ExecutionContext, call: impl FnOnce(&FfiContext) -> R) -> R {
+    let cwd: String = context.cwd().to_string_lossy().into_owned();
+    let env: Vec<String> = context
+        .env()
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let env: Vec<FfiStr> = env.iter().map(|entry| FfiStr::new(entry)).collect();
+    let ffi_context: FfiContext = FfiContext {
+        cwd: FfiStr::new(&cwd),
+        env: env.as_ptr(),
+        env_len: env.len(),
+        terminal: context.terminal(),
+        host: context as *mut ExecutionContext as *mut c_void,
+        read: host_read,
+        write: host_write,
+        set_var: host_set_var,
+        set_current_dir: host_set_current_dir,
+    };
+    call(&ffi_context)
+}
+
+/// Callbacks a native plugin reaches the `ExecutionContext` passed as `host`
+/// through, for the duration of `execute`
+unsafe extern "C" fn host_read(host: *mut c_void, buf: *mut u8, cap: usize) -> isize {
+    if cap == 0 {
+        return 0;
+    }
+    let context: &mut ExecutionContext = &mut *(host as *mut ExecutionContext);
+    let buf: &mut [u8] = std::slice::from_raw_parts_mut(buf, cap);
+    context.read(buf).map_or(-1, |len| len as isize)
+}
+
+unsafe extern "C" fn host_write(
+    host: *mut c_void,
+    stream: i32,
+    data: *const u8,
+    len: usize,
+) -> isize {
+    let context: &mut ExecutionContext = &mut *(host as *mut ExecutionContext);
+    let stream: Stream = if stream == STDERR {
+        Stream::Stderr
+    } else {
+        Stream::Stdout
+    };
+    let data: &[u8] = if len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, len)
+    };
+    context.write(stream, data).map_or(-1, |len| len as isize)
+}
+
+unsafe extern "C" fn host_set_var(host: *mut c_void, name: FfiStr, value: FfiStr, remove: bool) {
+    let context: &mut ExecutionContext = &mut *(host as *mut ExecutionContext);
+    let value: Option<String> = (!remove).then(|| value.read());
+    context.set_var(&name.read(), value.as_deref());
+}
+
+unsafe extern "C" fn host_set_current_dir(host: *mut c_void, path: FfiStr) -> bool {
+    let context: &mut ExecutionContext = &mut *(host as *mut ExecutionContext);
+    context.set_current_dir(Path::new(&path.read())).is_ok()
+}
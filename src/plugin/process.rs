@@ -0,0 +1,557 @@
+use super::ExecutionContext;
+use flux_plugin::{hooks, Host, Preexec, Stream};
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Version of the JSON-RPC protocol, returned by `describe`
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// How long a plugin has to start and answer `describe`
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long completion and prompt segment requests may take, so a slow
+/// plugin cannot stall typing or the prompt
+const QUERY_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// How long a hook may take, since the shell waits for it
+const HOOK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a plugin has to exit after `shutdown`
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Crashes within `CRASH_WINDOW` after which the plugin is not restarted
+const MAX_CRASHES: usize = 3;
+const CRASH_WINDOW: Duration = Duration::from_secs(60);
+
+/// A plugin process the shell talks to over stdin and stdout
+struct Connection {
+    child: Child,
+    stdin: ChildStdin,
+    /// Messages read from the plugin's stdout by a reader thread; closed
+    /// when the plugin exits
+    messages: Receiver<Value>,
+    next_id: u64,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A plugin running as a separate process, spoken to with JSON-RPC 2.0
+///
+/// Messages are single lines of JSON. A crash or hang only costs the current
+/// request: the process is killed and started again on the next one.
+///
+/// Prompt segments are asked for from the prompt engine's worker thread, so
+/// requests from different threads take turns.
+pub struct ProcessPlugin {
+    name: String,
+    executable: PathBuf,
+    dir: PathBuf,
+    /// How long `execute` may go without producing output
+    timeout: Duration,
+    connection: Mutex<Option<Connection>>,
+    /// When the plugin recently crashed or timed out
+    crashes: Mutex<Vec<Instant>>,
+    /// Held for the whole of a request, so only one is in flight at a time
+    requests: Mutex<()>,
+}
+
+impl ProcessPlugin {
+    /// Starts a plugin process and checks it answers `describe`
+    ///
+    /// # Arguments
+    /// * `dir` - Directory the plugin is installed in; its working directory
+    /// * `executable` - Program to run, relative to `dir`
+    /// * `name` - Name from the manifest, which the plugin must report
+    /// * `timeout` - How long a command may run without output
+    ///
+    /// # Returns
+    /// * `Result<ProcessPlugin, String>` - The running plugin, or why it
+    ///   failed to start
+    pub fn load(
+        dir: &Path,
+        executable: &str,
+        name: &str,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let plugin: ProcessPlugin = ProcessPlugin {
+            name: name.to_string(),
+            executable: dir.join(executable),
+            dir: dir.to_path_buf(),
+            timeout,
+            connection: Mutex::new(None),
+            crashes: Mutex::new(Vec::new()),
+            requests: Mutex::new(()),
+        };
+        plugin.connect()?;
+        Ok(plugin)
+    }
+
+    /// Runs a command, streaming its output as it arrives
+    ///
+    /// The plugin is sent the context along with the arguments, and its
+    /// result may carry an exit code and changes to the environment and
+    /// directory.
+    ///
+    /// # Returns
+    /// * `Result<i32, String>` - The exit code, or the error the command
+    ///   returned
+    pub fn execute(&self, context: &mut ExecutionContext, args: &[String]) -> Result<i32, String> {
+        let mut params: Map<String, Value> = context_params(context);
+        params.insert("args".to_string(), json!(args));
+        let result: Value = self.request(
+            "execute",
+            Value::Object(params),
+            self.timeout,
+            Some(&mut *context),
+        )?;
+        apply_result(context, &result)?;
+        Ok(result["exit_code"].as_i64().unwrap_or(0) as i32)
+    }
+
+    /// Calls a hook, as a request named after it
+    ///
+    /// # Arguments
+    /// * `context` - What the hook runs with
+    /// * `args` - The hook's name followed by its arguments
+    ///
+    /// # Returns
+    /// * `Result<Preexec, String>` - What to do with the command, for
+    ///   `preexec`, or the hook's error
+    pub fn hook(&self, context: &mut ExecutionContext, args: &[String]) -> Result<Preexec, String> {
+        let arg = |index: usize| args.get(index).cloned().unwrap_or_default();
+        let hook_params: Value = match args[0].as_str() {
+            hooks::PREEXEC => json!({ "command": arg(1) }),
+            hooks::PRECMD => json!({
+                "command": arg(1),
+                "status": arg(2).parse::<i32>().unwrap_or(0),
+                "duration_ms": arg(3).parse::<u64>().unwrap_or(0),
+            }),
+            hooks::CHPWD => json!({ "old": arg(1), "new": arg(2) }),
+            hooks::HISTORY_ADD => json!({ "entry": arg(1) }),
+            _ => json!({}),
+        };
+        let mut params: Map<String, Value> = context_params(context);
+        if let Value::Object(hook_params) = hook_params {
+            params.extend(hook_params);
+        }
+        let result: Value = self.request(
+            &args[0],
+            Value::Object(params),
+            HOOK_TIMEOUT,
+            Some(&mut *context),
+        )?;
+        apply_result(context, &result)?;
+        Ok(match result["action"].as_str() {
+            Some("rewrite") => {
+                Preexec::Rewrite(result["command"].as_str().unwrap_or_default().to_string())
+            }
+            Some("veto") => Preexec::Veto,
+            _ => Preexec::Run,
+        })
+    }
+
+    /// Completion candidates for the last of `args`
+    pub fn complete(&self, args: &[String]) -> Vec<String> {
+        self.request("complete", json!({ "args": args }), QUERY_TIMEOUT, None)
+            .ok()
+            .and_then(|result| serde_json::from_value(result).ok())
+            .unwrap_or_default()
+    }
+
+    /// Prompt segments as (name, value) pairs
+    pub fn prompt_segments(&self) -> Vec<(String, String)> {
+        let segments: Option<serde_json::Map<String, Value>> = self
+            .request("prompt_segments", json!({}), QUERY_TIMEOUT, None)
+            .ok()
+            .and_then(|result| serde_json::from_value(result).ok());
+        segments
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| {
+                let value: String = match value {
+                    Value::String(text) => text,
+                    other => other.to_string(),
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Asks the plugin to exit, killing it if it does not
+    pub fn cleanup(&self) -> Result<(), String> {
+        let _turn: MutexGuard<()> = lock(&self.requests);
+        if self.connection().is_some() {
+            let _ = self.call("shutdown", json!({}), SHUTDOWN_TIMEOUT, None);
+        }
+        self.connection().take();
+        Ok(())
+    }
+
+    /// Forgets recent crashes, so a plugin that was stopped is started again
+    pub fn reset_crashes(&self) {
+        self.crashes().clear();
+    }
+
+    /// Starts the process and performs the `describe` handshake
+    fn connect(&self) -> Result<(), String> {
+        let mut child: Child = Command::new(&self.executable)
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("{}: {}", self.executable.display(), e))?;
+        let stdin: ChildStdin = child.stdin.take().ok_or("plugin has no stdin")?;
+        let stdout = child.stdout.take().ok_or("plugin has no stdout")?;
+
+        let (sender, messages) = mpsc::channel::<Value>();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let message: Value = serde_json::from_str(&line).unwrap_or_else(|e| {
+                    json!({ "method": "invalid", "params": { "line": line, "error": e.to_string() } })
+                });
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        *self.connection() = Some(Connection {
+            child,
+            stdin,
+            messages,
+            next_id: 1,
+        });
+
+        let description: Value = self.call("describe", json!({}), DESCRIBE_TIMEOUT, None)?;
+        let protocol: u64 = description["protocol"].as_u64().unwrap_or(0);
+        if protocol != PROTOCOL_VERSION {
+            self.connection().take();
+            return Err(format!(
+                "{}: plugin speaks protocol version {} (this flux uses version {})",
+                self.name, protocol, PROTOCOL_VERSION
+            ));
+        }
+        if description["name"].as_str() != Some(self.name.as_str()) {
+            self.connection().take();
+            return Err(format!(
+                "{}: plugin describes itself as {} but its manifest says '{}'",
+                self.name, description["name"], self.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sends a request, restarting the plugin first if it is not running
+    fn request(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+        context: Option<&mut ExecutionContext>,
+    ) -> Result<Value, String> {
+        let _turn: MutexGuard<()> = lock(&self.requests);
+        let exited: bool = self
+            .connection()
+            .as_mut()
+            .is_some_and(|connection| !matches!(connection.child.try_wait(), Ok(None)));
+        if exited {
+            // Died between requests: restart it without failing this one
+            eprintln!("flux: plugin '{}' exited; restarting it", self.name);
+            self.crashed("exited");
+        }
+        if self.connection().is_none() {
+            let now: Instant = Instant::now();
+            let mut crashes: MutexGuard<Vec<Instant>> = self.crashes();
+            crashes.retain(|crash| now.duration_since(*crash) < CRASH_WINDOW);
+            if crashes.len() >= MAX_CRASHES {
+                return Err(format!(
+                    "plugin '{}' keeps crashing and was stopped; run `reload` to start it again",
+                    self.name
+                ));
+            }
+            drop(crashes);
+            self.connect()?;
+        }
+        self.call(method, params, timeout, context)
+    }
+
+    /// Sends a request and waits for its response
+    ///
+    /// `output` notifications received meanwhile are written to the command's
+    /// output when a context is given, and each one restarts the timeout. If
+    /// the plugin exits or times out, it is killed and the crash recorded.
+    ///
+    /// # Returns
+    /// * `Result<Value, String>` - The result, or the error the plugin
+    ///   returned or what went wrong with it
+    fn call(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+        mut context: Option<&mut ExecutionContext>,
+    ) -> Result<Value, String> {
+        let mut slot: MutexGuard<Option<Connection>> = self.connection();
+        let connection: &mut Connection = slot.as_mut().ok_or("plugin is not running")?;
+        let id: u64 = connection.next_id;
+        connection.next_id += 1;
+
+        let request: Value =
+            json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let sent: std::io::Result<()> =
+            writeln!(connection.stdin, "{}", request).and_then(|_| connection.stdin.flush());
+        if sent.is_err() {
+            drop(slot);
+            return Err(self.crashed("exited"));
+        }
+
+        loop {
+            let message: Value = match connection.messages.recv_timeout(timeout) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    drop(slot);
+                    return Err(self.crashed(&format!(
+                        "did not respond to '{}' within {}ms",
+                        method,
+                        timeout.as_millis()
+                    )));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    drop(slot);
+                    return Err(self.crashed("exited"));
+                }
+            };
+
+            match message["method"].as_str() {
+                Some("output") => {
+                    if let Some(context) = context.as_deref_mut() {
+                        write_output(context, &message["params"]);
+                    }
+                    continue;
+                }
+                Some("invalid") => {
+                    eprintln!(
+                        "flux: plugin '{}' wrote invalid JSON: {}",
+                        self.name, message["params"]["line"]
+                    );
+                    continue;
+                }
+                _ => {}
+            }
+            if message["id"].as_u64() != Some(id) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                return Err(error["message"]
+                    .as_str()
+                    .map(String::from)
+                    .unwrap_or_else(|| error.to_string()));
+            }
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    /// The connection to the running process, if there is one
+    fn connection(&self) -> MutexGuard<'_, Option<Connection>> {
+        lock(&self.connection)
+    }
+
+    /// When the plugin recently crashed or timed out
+    fn crashes(&self) -> MutexGuard<'_, Vec<Instant>> {
+        lock(&self.crashes)
+    }
+
+    /// Stops a plugin that crashed or hung so the next request restarts it
+    fn crashed(&self, reason: &str) -> String {
+        self.connection().take();
+        self.crashes().push(Instant::now());
+        format!("plugin '{}' {}; it will be restarted", self.name, reason)
+    }
+}
+
+/// Locks a mutex, carrying on with the data if another thread panicked while
+/// holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The context sent with `execute` and hooks
+fn context_params(context: &mut ExecutionContext) -> Map<String, Value> {
+    let terminal = context.terminal();
+    let stdin: Option<String> = context
+        .take_stdin()
+        .map(|input| String::from_utf8_lossy(&input).into_owned());
+    let params: Value = json!({
+        "cwd": context.cwd().to_string_lossy(),
+        "env": context.env(),
+        "terminal": {
+            "stdin_tty": terminal.stdin_tty,
+            "stdout_tty": terminal.stdout_tty,
+            "columns": terminal.columns,
+            "rows": terminal.rows,
+        },
+        "stdin": stdin,
+    });
+    match params {
+        Value::Object(params) => params,
+        _ => Map::new(),
+    }
+}
+
+/// Records the changes to the environment and directory a result asks for
+fn apply_result(context: &mut ExecutionContext, result: &Value) -> Result<(), String> {
+    if let Some(env) = result["env"].as_object() {
+        for (name, value) in env {
+            context.set_var(name, value.as_str());
+        }
+    }
+    if let Some(dir) = result["cwd"].as_str() {
+        context.set_current_dir(Path::new(dir))?;
+    }
+    Ok(())
+}
+
+/// Writes the text of an `output` notification to the command's stdout or
+/// stderr
+fn write_output(context: &mut ExecutionContext, params: &Value) {
+    let text: &str = params["text"].as_str().unwrap_or_default();
+    let stream: Stream = if params["stream"].as_str() == Some("stderr") {
+        Stream::Stderr
+    } else {
+        Stream::Stdout
+    };
+    let _ = context.write(stream, text.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use uuid::Uuid;
+
+    /// A plugin written in `sh` that describes itself with a name and
+    /// protocol version, and whose `execute` does what its argument says
+    fn stub(name: &str, protocol: u64) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let script: String = format!(
+            r#"#!/bin/sh
+while read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  method=$(printf '%s\n' "$line" | sed -n 's/.*"method":"\([^"]*\)".*/\1/p')
+  case "$method" in
+    describe)
+      echo "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{{\"name\":\"{}\",\"protocol\":{}}}}}" ;;
+    execute)
+      case "$line" in
+        *'"args":["hello"]'*)
+          echo '{{"jsonrpc":"2.0","method":"output","params":{{"text":"hello "}}}}'
+          printf '%s\n' '{{"jsonrpc":"2.0","method":"output","params":{{"text":"world\n"}}}}'
+          echo "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{{\"exit_code\":3}}}}" ;;
+        *'"args":["fail"]'*)
+          echo "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"error\":{{\"code\":1,\"message\":\"no such note\"}}}}" ;;
+        *'"args":["hang"]'*)
+          read -r _ ;;
+        *'"args":["crash"]'*)
+          exit 1 ;;
+      esac ;;
+    shutdown)
+      exit 0 ;;
+  esac
+done
+"#,
+            name, protocol
+        );
+        let executable: PathBuf = dir.join("plugin.sh");
+        fs::write(&executable, script).unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    fn load(dir: &Path) -> Result<ProcessPlugin, String> {
+        ProcessPlugin::load(dir, "plugin.sh", "notes", Duration::from_millis(500))
+    }
+
+    /// Runs a command, returning its result and captured output
+    fn run(plugin: &ProcessPlugin, arg: &str) -> (Result<i32, String>, String) {
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        let result: Result<i32, String> = plugin.execute(&mut context, &[arg.to_string()]);
+        let output: Vec<u8> = context.apply().unwrap().unwrap_or_default();
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn streams_output_and_returns_the_exit_code() {
+        let dir: PathBuf = stub("notes", PROTOCOL_VERSION);
+        let plugin: ProcessPlugin = load(&dir).unwrap();
+        assert_eq!(run(&plugin, "hello"), (Ok(3), "hello world\n".to_string()));
+        assert_eq!(run(&plugin, "fail").0, Err("no such note".to_string()));
+        plugin.cleanup().unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_plugins_that_describe_themselves_differently() {
+        let dir: PathBuf = stub("tasks", PROTOCOL_VERSION);
+        let e: String = load(&dir).err().unwrap();
+        assert!(e.contains("describes itself as \"tasks\""), "{}", e);
+        fs::remove_dir_all(dir).unwrap();
+
+        let dir: PathBuf = stub("notes", PROTOCOL_VERSION + 1);
+        let e: String = load(&dir).err().unwrap();
+        assert!(e.contains("speaks protocol version 2"), "{}", e);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn restarts_a_plugin_that_timed_out() {
+        let dir: PathBuf = stub("notes", PROTOCOL_VERSION);
+        let plugin: ProcessPlugin = load(&dir).unwrap();
+        let e: String = run(&plugin, "hang").0.unwrap_err();
+        assert!(
+            e.contains("did not respond to 'execute' within 500ms"),
+            "{}",
+            e
+        );
+        assert_eq!(run(&plugin, "hello").0, Ok(3));
+        plugin.cleanup().unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stops_restarting_a_plugin_that_keeps_crashing() {
+        let dir: PathBuf = stub("notes", PROTOCOL_VERSION);
+        let plugin: ProcessPlugin = load(&dir).unwrap();
+
+        let e: String = run(&plugin, "crash").0.unwrap_err();
+        assert_eq!(e, "plugin 'notes' exited; it will be restarted");
+        assert_eq!(run(&plugin, "hello").0, Ok(3));
+
+        for _ in 1..MAX_CRASHES {
+            assert!(run(&plugin, "crash").0.is_err());
+        }
+        let e: String = run(&plugin, "hello").0.unwrap_err();
+        assert!(e.contains("keeps crashing and was stopped"), "{}", e);
+
+        plugin.reset_crashes();
+        assert_eq!(run(&plugin, "hello").0, Ok(3));
+        plugin.cleanup().unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+/// Where `flux plugin install` gets a plugin from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginSource {
+    /// A directory on this machine, built where it is
+    Local(PathBuf),
+    /// A `.tar.gz` archive, as a path or an http(s) URL
+    Archive(String),
+    /// A git repository, at a tag, branch or revision when one is given
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+    /// A plugin listed in the registry index, in the newest version
+    /// matching the requirement
+    Registry {
+        name: String,
+        requirement: VersionReq,
+    },
+}
+
+impl PluginSource {
+    /// Works out what kind of source a `flux plugin install` argument is
+    ///
+    /// Archives end in `.tar.gz` or `.tgz`; git repositories are URLs,
+    /// `git@host:path` or end in `.git`, and may end in `@<tag or rev>`;
+    /// directories are paths containing `/` or starting with `~` or `.`;
+    /// anything else is a plugin name, with an optional `@<requirement>`.
+    ///
+    /// # Arguments
+    /// * `spec` - The argument as given
+    ///
+    /// # Returns
+    /// * `Result<PluginSource, String>` - The source, or why it is not one
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if spec.ends_with(".tar.gz") || spec.ends_with(".tgz") {
+            return Ok(PluginSource::Archive(spec.to_string()));
+        }
+        if spec.contains("://") || spec.starts_with("git@") || spec.ends_with(".git") {
+            // A pin follows the last `/` and the `:` of `git@host:`, so the
+            // `@` of `git@` is not mistaken for one
+            let host_end: usize = match spec.strip_prefix("git@") {
+                Some(rest) => rest.find(':').map_or(0, |colon| "git@".len() + colon),
+                None => 0,
+            };
+            let path_start: usize = spec.rfind('/').unwrap_or(0).max(host_end);
+            return Ok(match spec[path_start..].rfind('@') {
+                Some(at) => PluginSource::Git {
+                    url: spec[..path_start + at].to_string(),
+                    reference: Some(spec[path_start + at + 1..].to_string()),
+                },
+                None => PluginSource::Git {
+                    url: spec.to_string(),
+                    reference: None,
+                },
+            });
+        }
+        if spec.contains('/') || spec.starts_with('~') || spec.starts_with('.') {
+            return Ok(PluginSource::Local(expand_home(spec)));
+        }
+
+        let (name, requirement) = spec.split_once('@').unwrap_or((spec, "*"));
+        let requirement: VersionReq = VersionReq::parse(requirement).map_err(|e| {
+            format!(
+                "'{}' is not a valid version requirement: {}",
+                requirement, e
+            )
+        })?;
+        Ok(PluginSource::Registry {
+            name: name.to_string(),
+            requirement,
+        })
+    }
+
+    /// Gets the plugin's source ready to build
+    ///
+    /// # Arguments
+    /// * `temp_dir` - Directory to clone, download and unpack into
+    ///
+    /// # Returns
+    /// * `Result<Checkout, String>` - The source, or why it could not be
+    ///   fetched
+    pub fn fetch(&self, temp_dir: &Path) -> Result<Checkout, String> {
+        let temp: PathBuf = temp_dir.join(Uuid::new_v4().to_string());
+        match self {
+            PluginSource::Local(path) => {
+                let dir: PathBuf = path
+                    .canonicalize()
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                Ok(Checkout {
+                    resolved: dir.display().to_string(),
+                    dir,
+                    temp: None,
+                    signature: None,
+                })
+            }
+            PluginSource::Archive(archive) => {
+                // Removes the directory again if anything below fails
+                let mut checkout: Checkout = Checkout {
+                    dir: temp.clone(),
+                    temp: Some(temp.clone()),
+                    resolved: archive.clone(),
+                    signature: None,
+                };
+                fs::create_dir_all(&temp).map_err(|e| e.to_string())?;
+                // A release may be signed, with the signature next to it
+                let signature_url: String = format!("{}.sig", archive);
+                let (file, signature): (PathBuf, Option<String>) = if is_url(archive) {
+                    let file: PathBuf = temp.join("plugin.tar.gz");
+                    run(Command::new("curl")
+                        .args(["-fsSL", "-o"])
+                        .arg(&file)
+                        .arg(archive))
+                    .map_err(|e| format!("Failed to download {}: {}", archive, e))?;
+                    let signature: Option<String> =
+                        run(Command::new("curl").args(["-fsSL", &signature_url])).ok();
+                    (file, signature)
+                } else {
+                    let file: PathBuf = PathBuf::from(archive)
+                        .canonicalize()
+                        .map_err(|e| format!("{}: {}", archive, e))?;
+                    checkout.resolved = file.display().to_string();
+                    (file, fs::read_to_string(&signature_url).ok())
+                };
+                checkout.signature = signature.map(|signature| ArchiveSignature {
+                    archive: file.clone(),
+                    signature,
+                });
+                let unpacked: PathBuf = temp.join("source");
+                fs::create_dir_all(&unpacked).map_err(|e| e.to_string())?;
+                run(Command::new("tar")
+                    .arg("-xzf")
+                    .arg(&file)
+                    .arg("-C")
+                    .arg(&unpacked))
+                .map_err(|e| format!("Failed to unpack {}: {}", archive, e))?;
+                checkout.dir = single_directory(&unpacked).unwrap_or(unpacked);
+                Ok(checkout)
+            }
+            PluginSource::Git { url, reference } => {
+                let mut checkout: Checkout = Checkout {
+                    dir: temp.clone(),
+                    temp: Some(temp.clone()),
+                    resolved: String::new(),
+                    signature: None,
+                };
+                run(Command::new("git").arg("clone").arg(url).arg(&temp))
+                    .map_err(|e| format!("Failed to clone {}: {}", url, e))?;
+                if let Some(reference) = reference {
+                    run(Command::new("git")
+                        .args(["checkout", "--quiet", reference])
+                        .current_dir(&temp))
+                    .map_err(|e| format!("Failed to check out '{}': {}", reference, e))?;
+                }
+                let commit: String = run(Command::new("git")
+                    .args(["rev-parse", "HEAD"])
+                    .current_dir(&temp))
+                .map_err(|e| format!("Failed to read the checked out commit: {}", e))?;
+                checkout.resolved = format!("{}@{}", url, commit.trim());
+                Ok(checkout)
+            }
+            PluginSource::Registry { name, .. } => Err(format!(
+                "'{}' must be looked up in the registry before it is fetched",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PluginSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginSource::Local(path) => write!(f, "{}", path.display()),
+            PluginSource::Archive(archive) => write!(f, "{}", archive),
+            PluginSource::Git {
+                url,
+                reference: Some(reference),
+            } => write!(f, "{}@{}", url, reference),
+            PluginSource::Git { url, .. } => write!(f, "{}", url),
+            PluginSource::Registry { name, requirement } => write!(f, "{}@{}", name, requirement),
+        }
+    }
+}
+
+/// A plugin's source on disk, removed when dropped if it was fetched into a
+/// temporary directory
+pub struct Checkout {
+    /// Directory holding the plugin's manifest
+    pub dir: PathBuf,
+    /// Temporary directory the source was fetched into
+    temp: Option<PathBuf>,
+    /// Exactly what was fetched: the commit of a git repository, otherwise
+    /// the archive or directory, with local paths made absolute
+    pub resolved: String,
+    /// Signature published alongside an archive, if there was one
+    pub signature: Option<ArchiveSignature>,
+}
+
+/// A release archive and the detached signature found next to it
+pub struct ArchiveSignature {
+    /// The archive as downloaded
+    pub archive: PathBuf,
+    /// Base64 Ed25519 signature of the archive
+    pub signature: String,
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        if let Some(temp) = &self.temp {
+            fs::remove_dir_all(temp).ok();
+        }
+    }
+}
+
+/// Looks a plugin up in the registry index
+///
+/// The index is a TOML file, local or at an http(s) URL, listing each
+/// plugin's versions and where to get them:
+///
+/// ```toml
+/// [example]
+/// "1.0.0" = "https://github.com/user/example@v1.0.0"
+/// "1.1.0" = "https://example.com/example-1.1.0.tar.gz"
+/// ```
+///
+/// # Arguments
+/// * `index` - Path or URL of the index
+/// * `name` - Plugin name
+/// * `requirement` - Versions that may be installed
+///
+/// # Returns
+/// * `Result<(Version, PluginSource), String>` - The newest matching version
+///   and its source
+pub fn resolve(
+    index: &str,
+    name: &str,
+    requirement: &VersionReq,
+) -> Result<(Version, PluginSource), String> {
+    let contents: String = if is_url(index) {
+        run(Command::new("curl").args(["-fsSL", index]))
+            .map_err(|e| format!("Failed to download {}: {}", index, e))?
+    } else {
+        fs::read_to_string(expand_home(index)).map_err(|e| format!("{}: {}", index, e))?
+    };
+    let plugins: BTreeMap<String, BTreeMap<String, String>> =
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", index, e.message().trim()))?;
+    let versions: &BTreeMap<String, String> = plugins
+        .get(name)
+        .ok_or_else(|| format!("no plugin named '{}' in {}", name, index))?;
+
+    let (version, spec) = versions
+        .iter()
+        .filter_map(|(version, spec)| Some((Version::parse(version).ok()?, spec)))
+        .filter(|(version, _)| requirement.matches(version))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .ok_or_else(|| {
+            format!(
+                "no version of '{}' matching {} in {} (it has: {})",
+                name,
+                requirement,
+                index,
+                versions.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+    match PluginSource::parse(spec)? {
+        PluginSource::Registry { .. } => Err(format!(
+            "{}: '{}' {} must point to a repository, archive or directory, not '{}'",
+            index, name, version, spec
+        )),
+        source => Ok((version, source)),
+    }
+}
+
+/// A path with a leading `~` replaced by the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) => dirs::home_dir()
+            .unwrap_or_default()
+            .join(rest.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+fn is_url(spec: &str) -> bool {
+    spec.starts_with("https://") || spec.starts_with("http://")
+}
+
+/// The directory an archive unpacked into, if it holds nothing else
+fn single_directory(dir: &Path) -> Option<PathBuf> {
+    let entries: Vec<fs::DirEntry> = fs::read_dir(dir).ok()?.filter_map(Result::ok).collect();
+    match entries.as_slice() {
+        [entry] if entry.path().is_dir() => Some(entry.path()),
+        _ => None,
+    }
+}
+
+/// Runs a command to completion
+///
+/// # Returns
+/// * `Result<String, String>` - Its output, or its error output if it failed
+fn run(command: &mut Command) -> Result<String, String> {
+    let output: Output = command.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(url: &str, reference: Option<&str>) -> PluginSource {
+        PluginSource::Git {
+            url: url.to_string(),
+            reference: reference.map(String::from),
+        }
+    }
+
+    #[test]
+    fn parses_git_pins() {
+        assert_eq!(
+            PluginSource::parse("https://github.com/user/notes@v1.2.0").unwrap(),
+            git("https://github.com/user/notes", Some("v1.2.0"))
+        );
+        assert_eq!(
+            PluginSource::parse("https://github.com/user/notes.git").unwrap(),
+            git("https://github.com/user/notes.git", None)
+        );
+        assert_eq!(
+            PluginSource::parse("git@github.com:user/notes.git@main").unwrap(),
+            git("git@github.com:user/notes.git", Some("main"))
+        );
+    }
+
+    #[test]
+    fn parses_scp_urls_without_a_slash() {
+        assert_eq!(
+            PluginSource::parse("git@myhost:plugin.git").unwrap(),
+            git("git@myhost:plugin.git", None)
+        );
+        assert_eq!(
+            PluginSource::parse("git@myhost:plugin.git@v1").unwrap(),
+            git("git@myhost:plugin.git", Some("v1"))
+        );
+    }
+
+    #[test]
+    fn parses_other_sources() {
+        assert_eq!(
+            PluginSource::parse("notes-1.2.0.tar.gz").unwrap(),
+            PluginSource::Archive("notes-1.2.0.tar.gz".to_string())
+        );
+        assert_eq!(
+            PluginSource::parse("./notes").unwrap(),
+            PluginSource::Local(PathBuf::from("./notes"))
+        );
+        assert_eq!(
+            PluginSource::parse("notes@^1.2").unwrap(),
+            PluginSource::Registry {
+                name: "notes".to_string(),
+                requirement: VersionReq::parse("^1.2").unwrap(),
+            }
+        );
+        assert!(PluginSource::parse("notes@nonsense").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the state file in the plugin directory
+pub const STATE_FILE: &str = "plugins.state";
+
+/// Which installed plugins are turned off, kept by `flux plugin disable` and
+/// `flux plugin enable`
+///
+/// Disabled plugins stay installed but are neither loaded nor offered as
+/// commands.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PluginState {
+    #[serde(default)]
+    pub disabled: BTreeSet<String>,
+}
+
+impl PluginState {
+    /// Reads the state file in a plugin directory; every plugin is enabled if
+    /// there is none yet
+    ///
+    /// # Arguments
+    /// * `plugin_dir` - Directory installed plugins are kept in
+    ///
+    /// # Returns
+    /// * `Result<PluginState, String>` - The state, or why it could not be
+    ///   read
+    pub fn read(plugin_dir: &Path) -> Result<Self, String> {
+        let path: PathBuf = plugin_dir.join(STATE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("{}: {}", path.display(), e.message().trim())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(PluginState::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Writes the state file into a plugin directory
+    ///
+    /// # Arguments
+    /// * `plugin_dir` - Directory installed plugins are kept in
+    pub fn write(&self, plugin_dir: &Path) -> Result<(), String> {
+        let path: PathBuf = plugin_dir.join(STATE_FILE);
+        let contents: String = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(
+            &path,
+            format!(
+                "# Written by `flux plugin enable` and `flux plugin disable`\n\n{}",
+                contents
+            ),
+        )
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Whether a plugin is loaded
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(name)
+    }
+}
@@ -0,0 +1,236 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Which keys plugin releases may be signed with, and whether they must be
+///
+/// Keys are read from a trusted-keys file with one key per line: the base64
+/// Ed25519 public key, then a name for it. Blank lines and lines starting
+/// with `#` are ignored.
+#[derive(Debug, Default, Clone)]
+pub struct TrustPolicy {
+    /// Trusted keys, base64 encoded, with their names
+    keys: Vec<(String, String)>,
+    /// Whether plugins not signed with a trusted key are refused
+    require_signed: bool,
+}
+
+impl TrustPolicy {
+    /// Reads the trusted keys
+    ///
+    /// A missing file means no keys are trusted.
+    ///
+    /// # Arguments
+    /// * `keys_file` - Path to the trusted-keys file
+    /// * `require_signed` - Whether plugins must be signed with a trusted key
+    ///
+    /// # Returns
+    /// * `(TrustPolicy, Vec<String>)` - The policy, with the keys that could
+    ///   be read, and a warning for each line that could not
+    pub fn load(keys_file: &Path, require_signed: bool) -> (Self, Vec<String>) {
+        let mut policy: TrustPolicy = TrustPolicy {
+            keys: Vec::new(),
+            require_signed,
+        };
+        let contents: String = match fs::read_to_string(keys_file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (policy, Vec::new()),
+            Err(e) => return (policy, vec![format!("{}: {}", keys_file.display(), e)]),
+        };
+
+        let mut warnings: Vec<String> = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match parse_key(key) {
+                Ok(_) => {
+                    let name: &str = match name.trim() {
+                        "" => key,
+                        name => name,
+                    };
+                    policy.keys.push((key.to_string(), name.to_string()));
+                }
+                Err(e) => warnings.push(format!("{}:{}: {}", keys_file.display(), index + 1, e)),
+            }
+        }
+        (policy, warnings)
+    }
+
+    /// Whether plugins not signed with a trusted key are refused
+    pub fn require_signed(&self) -> bool {
+        self.require_signed
+    }
+
+    /// Finds the trusted key a detached signature was made with
+    ///
+    /// # Arguments
+    /// * `data` - What was signed
+    /// * `signature` - The base64 Ed25519 signature
+    ///
+    /// # Returns
+    /// * `Result<Option<String>, String>` - The key, base64 encoded; None if
+    ///   no trusted key made the signature, or an error if the signature is
+    ///   malformed
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<Option<String>, String> {
+        let bytes: Vec<u8> = BASE64
+            .decode(signature.trim())
+            .map_err(|e| format!("signature is not base64: {}", e))?;
+        let signature: Signature = Signature::from_slice(&bytes)
+            .map_err(|_| "signature is not an Ed25519 signature".to_string())?;
+        Ok(self
+            .keys
+            .iter()
+            .find(|(key, _)| {
+                parse_key(key).is_ok_and(|key| key.verify_strict(data, &signature).is_ok())
+            })
+            .map(|(key, _)| key.clone()))
+    }
+
+    /// Name of a trusted key
+    ///
+    /// # Arguments
+    /// * `key` - The key, base64 encoded
+    ///
+    /// # Returns
+    /// * `Option<&str>` - Its name, or None if it is no longer trusted
+    pub fn key_name(&self, key: &str) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|(trusted, _)| trusted == key)
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+/// Hashes a file for the lockfile
+///
+/// # Returns
+/// * `Result<String, String>` - `sha256:` followed by the hex digest
+pub fn checksum(path: &Path) -> Result<String, String> {
+    let data: Vec<u8> = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let hex: String = Sha256::digest(&data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(format!("sha256:{}", hex))
+}
+
+/// Decodes a base64 Ed25519 public key
+fn parse_key(key: &str) -> Result<VerifyingKey, String> {
+    let bytes: Vec<u8> = BASE64
+        .decode(key)
+        .map_err(|e| format!("key is not base64: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "key is not a 32 byte Ed25519 public key".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("invalid key: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    /// Base64 public key of a signing key
+    fn public_key(key: &SigningKey) -> String {
+        BASE64.encode(key.verifying_key().as_bytes())
+    }
+
+    /// Base64 signature of some data
+    fn sign(key: &SigningKey, data: &[u8]) -> String {
+        BASE64.encode(key.sign(data).to_bytes())
+    }
+
+    /// Writes a file into a fresh temporary directory
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let path: PathBuf = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Loads a trusted-keys file with the given contents
+    fn policy(contents: &str) -> (TrustPolicy, Vec<String>) {
+        let path: PathBuf = temp_file("trusted_keys", contents);
+        let loaded = TrustPolicy::load(&path, false);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn accepts_a_signature_from_a_trusted_key() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (policy, warnings) = policy(&format!("# release key\n\n{} alice\n", public_key(&key)));
+        assert!(warnings.is_empty());
+
+        let signed_by: Option<String> = policy.verify(b"release", &sign(&key, b"release")).unwrap();
+        assert_eq!(signed_by.as_deref(), Some(public_key(&key).as_str()));
+        assert_eq!(policy.key_name(&public_key(&key)), Some("alice"));
+    }
+
+    #[test]
+    fn rejects_tampered_data_and_untrusted_keys() {
+        let trusted: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let other: SigningKey = SigningKey::from_bytes(&[2; 32]);
+        let (policy, _) = policy(&format!("{} alice\n", public_key(&trusted)));
+
+        assert_eq!(
+            policy.verify(b"tampered", &sign(&trusted, b"release")),
+            Ok(None)
+        );
+        assert_eq!(
+            policy.verify(b"release", &sign(&other, b"release")),
+            Ok(None)
+        );
+        assert_eq!(policy.key_name(&public_key(&other)), None);
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let (policy, _) = policy(&public_key(&key));
+
+        assert!(policy.verify(b"release", "not base64!").is_err());
+        assert!(policy.verify(b"release", &BASE64.encode([0; 10])).is_err());
+    }
+
+    #[test]
+    fn warns_about_bad_key_lines_with_their_location() {
+        let key: SigningKey = SigningKey::from_bytes(&[1; 32]);
+        let path: PathBuf = temp_file(
+            "trusted_keys",
+            &format!(
+                "# keys\nnot-base64! bob\n{} alice\n{} carol\n",
+                public_key(&key),
+                BASE64.encode([0; 16])
+            ),
+        );
+        let (policy, warnings) = TrustPolicy::load(&path, true);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with(&format!("{}:2: ", path.display())));
+        assert!(warnings[1].starts_with(&format!("{}:4: ", path.display())));
+        assert_eq!(policy.key_name(&public_key(&key)), Some("alice"));
+        assert!(policy.require_signed());
+    }
+
+    #[test]
+    fn checksum_changes_with_the_file() {
+        let path: PathBuf = temp_file("plugin.wasm", "module");
+        let before: String = checksum(&path).unwrap();
+        assert!(before.starts_with("sha256:"));
+        assert_eq!(checksum(&path).unwrap(), before);
+
+        fs::write(&path, "modified").unwrap();
+        assert_ne!(checksum(&path).unwrap(), before);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,944 @@
+use super::ExecutionContext;
+use flux_plugin::hooks::{REWRITE, VETO};
+use flux_plugin::wasm::{DENIED, FAILED, IMPORT_MODULE, STDERR, WASM_ABI_VERSION};
+use flux_plugin::{Host, Preexec, Stream};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wasmi::core::TrapCode;
+use wasmi::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+/// Work a plugin may do in one call, in fuel units of about one instruction
+/// each, so a plugin stuck in a loop cannot hang the shell; a second or two
+/// of interpreted code
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+
+/// Most bytes a plugin's memory may grow to, so a plugin cannot exhaust the
+/// shell's memory
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+/// Most bytes one `read` call copies from stdin; plugins call it again for
+/// the rest
+const MAX_READ: usize = 64 * 1024;
+
+/// What a WebAssembly plugin is allowed to do, from `plugin_permissions`
+///
+/// Plugins start with no capabilities; anything not granted here is denied.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Directories the plugin may read and write files in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filesystem: Vec<String>,
+    /// Environment variables the plugin may read; `*` allows all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environment: Vec<String>,
+    /// Programs the plugin may run, as typed; `*` allows all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub process: Vec<String>,
+}
+
+impl Capabilities {
+    /// Resolves a file path if it lies inside one of the granted directories
+    ///
+    /// Both sides are canonicalized, so `..` and symlinks cannot escape. A
+    /// file that does not exist yet is checked by its parent directory; a
+    /// symlink whose target does not exist is refused, since writing to it
+    /// would create the target wherever it points.
+    ///
+    /// # Returns
+    /// * `Option<PathBuf>` - The canonical path to use, or None if denied
+    fn granted_path(&self, path: &Path) -> Option<PathBuf> {
+        let resolved: PathBuf = match fs::symlink_metadata(path) {
+            Ok(_) => path.canonicalize().ok()?,
+            Err(_) => {
+                let parent: &Path = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                parent.canonicalize().ok()?.join(path.file_name()?)
+            }
+        };
+        self.filesystem
+            .iter()
+            .any(|dir| {
+                expand_home(dir)
+                    .canonicalize()
+                    .is_ok_and(|dir| resolved.starts_with(dir))
+            })
+            .then_some(resolved)
+    }
+
+    fn allows_variable(&self, name: &str) -> bool {
+        self.environment
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == name)
+    }
+
+    fn allows_program(&self, program: &str) -> bool {
+        self.process
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == program)
+    }
+}
+
+/// Expands a leading `~` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// State the host functions of one plugin share
+struct HostState {
+    name: String,
+    capabilities: Capabilities,
+    /// Value the plugin passed to `output` during the current call
+    output: Vec<u8>,
+    /// Context of the command being executed, if any
+    context: Option<ExecutionContext>,
+    /// Limits on how far the plugin's memory and tables may grow
+    limits: StoreLimits,
+}
+
+impl HostState {
+    /// Tells the user a capability was missing, so they know what to grant
+    fn deny(&self, capability: &str, target: &str) -> i32 {
+        eprintln!(
+            "flux: plugin '{}' was denied {} access to {} (grant it in plugin_permissions.{}.{})",
+            self.name, capability, target, self.name, capability
+        );
+        DENIED
+    }
+}
+
+/// A plugin compiled to WebAssembly, run in an interpreter
+///
+/// The plugin only reaches the system through the host functions in
+/// `flux_plugin::wasm`, each checked against its `Capabilities`.
+pub struct WasmPlugin {
+    store: RefCell<Store<HostState>>,
+    instance: Instance,
+    memory: Memory,
+    /// Fuel each call starts with
+    fuel: u64,
+}
+
+impl WasmPlugin {
+    /// Compiles and instantiates a WebAssembly plugin
+    ///
+    /// # Arguments
+    /// * `path` - Path to the `.wasm` module
+    /// * `name` - Name of the plugin, from its manifest
+    /// * `capabilities` - What the plugin is allowed to do
+    ///
+    /// # Returns
+    /// * `Result<WasmPlugin, String>` - The plugin, or why it cannot be loaded
+    pub fn load(path: &Path, name: &str, capabilities: Capabilities) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let bytes: Vec<u8> = std::fs::read(path).map_err(|e| error(&e))?;
+        let mut config: Config = Config::default();
+        config.consume_fuel(true);
+        let engine: Engine = Engine::new(&config);
+        let module: Module = Module::new(&engine, &bytes[..]).map_err(|e| error(&e))?;
+
+        let mut store: Store<HostState> = Store::new(
+            &engine,
+            HostState {
+                name: name.to_string(),
+                capabilities,
+                output: Vec::new(),
+                context: None,
+                limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        let mut linker: Linker<HostState> = Linker::new(&engine);
+        define_host_functions(&mut linker).map_err(|e| error(&e))?;
+        store.set_fuel(FUEL_PER_CALL).map_err(|e| error(&e))?;
+        let instance: Instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| error(&trap_message(&e)))?;
+
+        let abi_version: i32 = instance
+            .get_typed_func::<(), i32>(&store, "flux_abi_version")
+            .and_then(|version| version.call(&mut store, ()))
+            .map_err(|_| error(&"not a flux plugin (no flux_abi_version export)"))?;
+        if abi_version != WASM_ABI_VERSION {
+            return Err(error(&format!(
+                "plugin interface version {} is not supported (this flux uses version {}); rebuild the plugin against a matching flux-plugin",
+                abi_version, WASM_ABI_VERSION
+            )));
+        }
+        let memory: Memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| error(&"plugin does not export its memory"))?;
+
+        Ok(WasmPlugin {
+            store: RefCell::new(store),
+            instance,
+            memory,
+            fuel: FUEL_PER_CALL,
+        })
+    }
+
+    /// Replaces the plugin's capabilities, such as after a reload
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.store.borrow_mut().data_mut().capabilities = capabilities;
+    }
+
+    pub fn init(&mut self) -> Result<(), String> {
+        self.call("flux_init", &[]).map(|_| ())
+    }
+
+    /// Runs a command
+    ///
+    /// # Returns
+    /// * `Result<i32, String>` - The exit code, or the error the command
+    ///   returned
+    pub fn execute(&self, context: &mut ExecutionContext, args: &[String]) -> Result<i32, String> {
+        match self.invoke_with_context("flux_execute", context, args)? {
+            Some((code, output)) if output.is_empty() => Ok(code),
+            Some((_, output)) => Err(output),
+            None => Err("plugin does not export flux_execute".to_string()),
+        }
+    }
+
+    /// Calls a hook
+    ///
+    /// # Arguments
+    /// * `context` - What the hook runs with
+    /// * `args` - The hook's name followed by its arguments
+    ///
+    /// # Returns
+    /// * `Result<Preexec, String>` - What to do with the command, for
+    ///   `preexec`, or the hook's error
+    pub fn hook(&self, context: &mut ExecutionContext, args: &[String]) -> Result<Preexec, String> {
+        match self.invoke_with_context("flux_hook", context, args)? {
+            None => Ok(Preexec::Run),
+            Some((FAILED, output)) => Err(output),
+            Some((REWRITE, output)) => Ok(Preexec::Rewrite(output)),
+            Some((VETO, _)) => Ok(Preexec::Veto),
+            Some(_) => Ok(Preexec::Run),
+        }
+    }
+
+    /// Calls an export taking a list of arguments and a context
+    ///
+    /// The plugin only sees the variables it may read, and reaches the
+    /// context through the host functions while the call runs.
+    fn invoke_with_context(
+        &self,
+        export: &str,
+        context: &mut ExecutionContext,
+        args: &[String],
+    ) -> Result<Option<(i32, String)>, String> {
+        let capabilities: Capabilities = self.store.borrow().data().capabilities.clone();
+        let terminal = context.terminal();
+        let mut fields: Vec<String> = vec![
+            context.cwd().to_string_lossy().into_owned(),
+            flag(terminal.stdin_tty),
+            flag(terminal.stdout_tty),
+            terminal.columns.to_string(),
+            terminal.rows.to_string(),
+        ];
+        fields.extend(
+            context
+                .env()
+                .iter()
+                .filter(|(name, _)| capabilities.allows_variable(name))
+                .map(|(name, value)| format!("{}={}", name, value)),
+        );
+
+        self.store.borrow_mut().data_mut().context = Some(std::mem::take(context));
+        let result: Result<Option<(i32, String)>, String> = self.invoke(export, &[args, &fields]);
+        *context = self
+            .store
+            .borrow_mut()
+            .data_mut()
+            .context
+            .take()
+            .unwrap_or_default();
+        result
+    }
+
+    /// Completion candidates for the last of `args`
+    pub fn complete(&self, args: &[String]) -> Vec<String> {
+        match self.call("flux_complete", &[args]) {
+            Ok(Some(output)) if !output.is_empty() => {
+                output.split('\0').map(String::from).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Prompt segments as (name, value) pairs
+    pub fn prompt_segments(&self) -> Vec<(String, String)> {
+        let Ok(Some(output)) = self.call("flux_prompt_segments", &[]) else {
+            return Vec::new();
+        };
+        let items: Vec<&str> = output.split('\0').collect();
+        items
+            .chunks_exact(2)
+            .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+            .collect()
+    }
+
+    pub fn cleanup(&mut self) -> Result<(), String> {
+        self.call("flux_cleanup", &[]).map(|_| ())
+    }
+
+    /// Calls an export that returns 0 on success
+    ///
+    /// # Returns
+    /// * `Result<Option<String>, String>` - What the plugin passed to `output`,
+    ///   None if it does not have the export, or its error or trap
+    fn call(&self, export: &str, lists: &[&[String]]) -> Result<Option<String>, String> {
+        match self.invoke(export, lists)? {
+            None => Ok(None),
+            Some((0, output)) => Ok(Some(output)),
+            Some((code, output)) if output.is_empty() => {
+                Err(format!("{} failed with code {}", export, code))
+            }
+            Some((_, output)) => Err(output),
+        }
+    }
+
+    /// Calls an export, passing each list as a NUL-joined pointer and length
+    ///
+    /// # Returns
+    /// * `Result<Option<(i32, String)>, String>` - What the export returned
+    ///   and what it passed to `output`, None if it does not have the export,
+    ///   or its trap
+    fn invoke(&self, export: &str, lists: &[&[String]]) -> Result<Option<(i32, String)>, String> {
+        let mut store = self.store.borrow_mut();
+        if self.instance.get_func(&*store, export).is_none() {
+            return Ok(None);
+        }
+        store.data_mut().output.clear();
+        store
+            .set_fuel(self.fuel)
+            .map_err(|e| format!("plugin could not be given fuel: {}", e))?;
+
+        let mut params: Vec<i32> = Vec::new();
+        for list in lists {
+            let joined: String = list.join("\0");
+            let len: i32 = joined.len() as i32;
+            let ptr: i32 = self
+                .instance
+                .get_typed_func::<i32, i32>(&*store, "flux_alloc")
+                .and_then(|alloc| alloc.call(&mut *store, len))
+                .map_err(|e| format!("plugin failed to allocate memory: {}", trap_message(&e)))?;
+            self.memory
+                .write(&mut *store, ptr as usize, joined.as_bytes())
+                .map_err(|e| format!("plugin returned an invalid buffer: {}", e))?;
+            params.extend([ptr, len]);
+        }
+
+        let code: i32 = match params[..] {
+            [] => self
+                .instance
+                .get_typed_func::<(), i32>(&*store, export)
+                .and_then(|func| func.call(&mut *store, ())),
+            [ptr, len] => self
+                .instance
+                .get_typed_func::<(i32, i32), i32>(&*store, export)
+                .and_then(|func| func.call(&mut *store, (ptr, len))),
+            [ptr, len, ptr2, len2] => self
+                .instance
+                .get_typed_func::<(i32, i32, i32, i32), i32>(&*store, export)
+                .and_then(|func| func.call(&mut *store, (ptr, len, ptr2, len2))),
+            _ => unreachable!("exports take at most two lists"),
+        }
+        .map_err(|e| format!("plugin crashed: {}", trap_message(&e)))?;
+
+        let output: String = String::from_utf8_lossy(&store.data().output).into_owned();
+        Ok(Some((code, output)))
+    }
+}
+
+/// Describes a trap, naming the fuel limit when that is what stopped the
+/// plugin
+fn trap_message(error: &wasmi::Error) -> String {
+    match error.as_trap_code() {
+        Some(TrapCode::OutOfFuel) => format!(
+            "stopped after running too long without returning (more than {} instructions)",
+            FUEL_PER_CALL
+        ),
+        _ => error.to_string(),
+    }
+}
+
+/// Encodes a flag in the context passed to `flux_execute`
+fn flag(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
+
+/// Defines the functions a plugin imports from the `flux` module
+fn define_host_functions(linker: &mut Linker<HostState>) -> Result<(), wasmi::errors::LinkerError> {
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "write",
+        |mut caller: Caller<'_, HostState>, stream: i32, ptr: i32, len: i32| {
+            let Some(bytes) = read_bytes(&caller, ptr, len) else {
+                return;
+            };
+            let stream: Stream = if stream == STDERR {
+                Stream::Stderr
+            } else {
+                Stream::Stdout
+            };
+            match (&mut caller.data_mut().context, stream) {
+                (Some(context), stream) => {
+                    let _ = context.write(stream, &bytes);
+                }
+                (None, Stream::Stderr) => {
+                    let _ = std::io::stderr().write_all(&bytes);
+                }
+                (None, Stream::Stdout) => {
+                    let mut stdout = std::io::stdout();
+                    let _ = stdout.write_all(&bytes).and_then(|_| stdout.flush());
+                }
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "read",
+        |mut caller: Caller<'_, HostState>, buf: i32, cap: i32| -> i32 {
+            // The plugin chooses `cap`, so never allocate more than fits in
+            // its memory after `buf`, nor more than `MAX_READ`
+            let room: usize = caller
+                .get_export("memory")
+                .and_then(Extern::into_memory)
+                .map_or(0, |memory| {
+                    let size: usize = memory.data(&caller).len();
+                    size.saturating_sub(usize::try_from(buf).unwrap_or(size))
+                });
+            let len: usize = usize::try_from(cap).unwrap_or(0).min(room).min(MAX_READ);
+            let Some(context) = &mut caller.data_mut().context else {
+                return 0;
+            };
+            let mut data: Vec<u8> = vec![0; len];
+            match context.read(&mut data) {
+                Ok(len) => write_bytes(&mut caller, buf, cap, &data[..len]),
+                Err(_) => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "set_env",
+        |mut caller: Caller<'_, HostState>,
+         name: i32,
+         name_len: i32,
+         value: i32,
+         value_len: i32|
+         -> i32 {
+            let (Some(name), Some(value)) = (
+                read_string(&caller, name, name_len),
+                read_string(&caller, value, value_len),
+            ) else {
+                return FAILED;
+            };
+            if !caller.data().capabilities.allows_variable(&name) {
+                return caller.data().deny("environment", &name);
+            }
+            match &mut caller.data_mut().context {
+                Some(context) => {
+                    context.set_var(&name, Some(&value));
+                    0
+                }
+                None => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "unset_env",
+        |mut caller: Caller<'_, HostState>, name: i32, name_len: i32| -> i32 {
+            let Some(name) = read_string(&caller, name, name_len) else {
+                return FAILED;
+            };
+            if !caller.data().capabilities.allows_variable(&name) {
+                return caller.data().deny("environment", &name);
+            }
+            match &mut caller.data_mut().context {
+                Some(context) => {
+                    context.set_var(&name, None);
+                    0
+                }
+                None => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "chdir",
+        |mut caller: Caller<'_, HostState>, path: i32, path_len: i32| -> i32 {
+            let Some(path) = read_string(&caller, path, path_len) else {
+                return FAILED;
+            };
+            let Some(resolved) = caller.data().capabilities.granted_path(Path::new(&path)) else {
+                return caller.data().deny("filesystem", &path);
+            };
+            match &mut caller.data_mut().context {
+                Some(context) => match context.set_current_dir(&resolved) {
+                    Ok(()) => 0,
+                    Err(_) => FAILED,
+                },
+                None => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "output",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            if let Some(bytes) = read_bytes(&caller, ptr, len) {
+                caller.data_mut().output = bytes;
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "env_var",
+        |mut caller: Caller<'_, HostState>, name: i32, name_len: i32, buf: i32, cap: i32| -> i32 {
+            let Some(name) = read_string(&caller, name, name_len) else {
+                return FAILED;
+            };
+            if !caller.data().capabilities.allows_variable(&name) {
+                return caller.data().deny("environment", &name);
+            }
+            match std::env::var(&name) {
+                Ok(value) => write_bytes(&mut caller, buf, cap, value.as_bytes()),
+                Err(_) => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "read_file",
+        |mut caller: Caller<'_, HostState>, path: i32, path_len: i32, buf: i32, cap: i32| -> i32 {
+            let Some(path) = read_string(&caller, path, path_len) else {
+                return FAILED;
+            };
+            let Some(resolved) = caller.data().capabilities.granted_path(Path::new(&path)) else {
+                return caller.data().deny("filesystem", &path);
+            };
+            match fs::read(resolved) {
+                Ok(contents) => write_bytes(&mut caller, buf, cap, &contents),
+                Err(_) => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "write_file",
+        |caller: Caller<'_, HostState>,
+         path: i32,
+         path_len: i32,
+         data: i32,
+         data_len: i32|
+         -> i32 {
+            let (Some(path), Some(data)) = (
+                read_string(&caller, path, path_len),
+                read_bytes(&caller, data, data_len),
+            ) else {
+                return FAILED;
+            };
+            let Some(resolved) = caller.data().capabilities.granted_path(Path::new(&path)) else {
+                return caller.data().deny("filesystem", &path);
+            };
+            // Should the path become a symlink after the check, the write
+            // fails rather than following it
+            let mut options: OpenOptions = OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            options.custom_flags(libc::O_NOFOLLOW);
+            match options
+                .open(&resolved)
+                .and_then(|mut file| file.write_all(&data))
+            {
+                Ok(()) => 0,
+                Err(_) => FAILED,
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "spawn",
+        |caller: Caller<'_, HostState>, argv: i32, argv_len: i32| -> i32 {
+            let Some(argv) = read_string(&caller, argv, argv_len) else {
+                return FAILED;
+            };
+            let argv: Vec<&str> = argv.split('\0').collect();
+            if !caller.data().capabilities.allows_program(argv[0]) {
+                return caller.data().deny("process", argv[0]);
+            }
+            // The program sees only the variables the plugin may read, and
+            // runs in the command's directory
+            let state: &HostState = caller.data();
+            let mut command: Command = Command::new(argv[0]);
+            command.args(&argv[1..]).env_clear();
+            match &state.context {
+                Some(context) => {
+                    command.current_dir(context.cwd()).envs(
+                        context
+                            .env()
+                            .iter()
+                            .filter(|(name, _)| state.capabilities.allows_variable(name)),
+                    );
+                }
+                None => {
+                    command.envs(std::env::vars_os().filter(|(name, _)| {
+                        name.to_str()
+                            .is_some_and(|name| state.capabilities.allows_variable(name))
+                    }));
+                }
+            }
+            match command.status() {
+                Ok(status) => status.code().unwrap_or(FAILED),
+                Err(_) => FAILED,
+            }
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Copies bytes out of the plugin's memory
+fn read_bytes(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Option<Vec<u8>> {
+    let memory: Memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+    let start: usize = usize::try_from(ptr).ok()?;
+    let end: usize = start.checked_add(usize::try_from(len).ok()?)?;
+    memory.data(caller).get(start..end).map(<[u8]>::to_vec)
+}
+
+fn read_string(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    read_bytes(caller, ptr, len).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Copies a result into the plugin's buffer if it fits
+///
+/// # Returns
+/// * `i32` - Length of the result, so the plugin can retry with a larger
+///   buffer, or `FAILED` if the buffer is invalid
+fn write_bytes(caller: &mut Caller<'_, HostState>, buf: i32, cap: i32, data: &[u8]) -> i32 {
+    let Ok(len) = i32::try_from(data.len()) else {
+        return FAILED;
+    };
+    if len > cap {
+        return len;
+    }
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return FAILED;
+    };
+    match memory.write(caller, buf as usize, data) {
+        Ok(()) => len,
+        Err(_) => FAILED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    /// A plugin whose command never returns
+    const RUNAWAY: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "flux_abi_version") (result i32) i32.const 1)
+          (func (export "flux_alloc") (param i32) (result i32) i32.const 1024)
+          (func (export "flux_execute") (param i32 i32 i32 i32) (result i32)
+            (loop $forever (br $forever))
+            i32.const 0))
+    "#;
+
+    /// A plugin whose command grows its memory by 2048 pages, returning the
+    /// old size in pages or -1 if it could not grow
+    const GROWER: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "flux_abi_version") (result i32) i32.const 1)
+          (func (export "flux_alloc") (param i32) (result i32) i32.const 1024)
+          (func (export "flux_execute") (param i32 i32 i32 i32) (result i32)
+            (memory.grow (i32.const 2048))))
+    "#;
+
+    /// A plugin whose command reads stdin into a buffer near the end of its
+    /// memory, claiming the buffer is far larger, and exits with the length
+    const GREEDY: &str = r#"
+        (module
+          (import "flux" "read" (func $read (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "flux_abi_version") (result i32) i32.const 1)
+          (func (export "flux_alloc") (param i32) (result i32) i32.const 1024)
+          (func (export "flux_execute") (param i32 i32 i32 i32) (result i32)
+            (call $read (i32.const 65000) (i32.const 0x7fffffff))))
+    "#;
+
+    /// A plugin whose command runs the program and arguments in `argv`
+    /// and exits with its status
+    fn spawner(argv: &[&str]) -> String {
+        let argv: String = argv.join("\0");
+        let data: String = argv.bytes().map(|byte| format!("\\{:02x}", byte)).collect();
+        format!(
+            r#"
+            (module
+              (import "flux" "spawn" (func $spawn (param i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 0) "{}")
+              (func (export "flux_abi_version") (result i32) i32.const 1)
+              (func (export "flux_alloc") (param i32) (result i32) i32.const 1024)
+              (func (export "flux_execute") (param i32 i32 i32 i32) (result i32)
+                (call $spawn (i32.const 0) (i32.const {}))))
+            "#,
+            data,
+            argv.len()
+        )
+    }
+
+    /// A plugin whose command writes `contents` to `path` and exits with
+    /// the result of `write_file`
+    fn writer(path: &Path, contents: &str) -> String {
+        let path: String = path.display().to_string();
+        let data: String = format!("{}{}", path, contents)
+            .bytes()
+            .map(|byte| format!("\\{:02x}", byte))
+            .collect();
+        format!(
+            r#"
+            (module
+              (import "flux" "write_file" (func $write_file (param i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 0) "{}")
+              (func (export "flux_abi_version") (result i32) i32.const 1)
+              (func (export "flux_alloc") (param i32) (result i32) i32.const 1024)
+              (func (export "flux_execute") (param i32 i32 i32 i32) (result i32)
+                (call $write_file
+                  (i32.const 0) (i32.const {})
+                  (i32.const {}) (i32.const {}))))
+            "#,
+            data,
+            path.len(),
+            path.len(),
+            contents.len()
+        )
+    }
+
+    fn load(wat: &str, capabilities: Capabilities) -> WasmPlugin {
+        let path: PathBuf = std::env::temp_dir().join(format!("{}.wasm", Uuid::new_v4()));
+        std::fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        let plugin: Result<WasmPlugin, String> = WasmPlugin::load(&path, "test", capabilities);
+        std::fs::remove_file(&path).unwrap();
+        plugin.unwrap()
+    }
+
+    #[test]
+    fn stops_a_plugin_that_runs_too_long() {
+        let mut plugin: WasmPlugin = load(RUNAWAY, Capabilities::default());
+        plugin.fuel = 1_000_000;
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        let e: String = plugin.execute(&mut context, &[]).unwrap_err();
+        assert!(e.contains("running too long"), "{}", e);
+    }
+
+    #[test]
+    fn reads_no_more_than_fits_in_memory() {
+        let plugin: WasmPlugin = load(GREEDY, Capabilities::default());
+        let mut context: ExecutionContext = ExecutionContext::new(Some(vec![b'x'; 4096]), true);
+        // 536 bytes are left in the single page of memory after the buffer
+        assert_eq!(plugin.execute(&mut context, &[]), Ok(536));
+    }
+
+    #[test]
+    fn runs_programs_with_only_the_granted_variables_in_the_command_directory() {
+        let dir: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir: PathBuf = dir.canonicalize().unwrap();
+        let script: String = format!(
+            "test -z \"$FLUX_TEST_HIDDEN\" && test \"$FLUX_TEST_SHOWN\" = yes && test \"$(pwd -P)\" = '{}'",
+            dir.display()
+        );
+        let plugin: WasmPlugin = load(
+            &spawner(&["/bin/sh", "-c", &script]),
+            Capabilities {
+                environment: vec!["FLUX_TEST_SHOWN".to_string()],
+                process: vec!["/bin/sh".to_string()],
+                ..Capabilities::default()
+            },
+        );
+
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        context.set_var("FLUX_TEST_HIDDEN", Some("secret"));
+        context.set_var("FLUX_TEST_SHOWN", Some("yes"));
+        context.set_current_dir(&dir).unwrap();
+        assert_eq!(plugin.execute(&mut context, &[]), Ok(0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Capabilities granting one fresh temporary directory, with a sibling
+    /// directory that is not granted
+    fn granted_dir() -> (Capabilities, PathBuf, PathBuf) {
+        let root: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let granted: PathBuf = root.join("granted");
+        let outside: PathBuf = root.join("outside");
+        std::fs::create_dir_all(&granted).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        let capabilities: Capabilities = Capabilities {
+            filesystem: vec![granted.display().to_string()],
+            ..Capabilities::default()
+        };
+        (capabilities, granted, outside)
+    }
+
+    #[test]
+    fn allows_paths_inside_granted_directories() {
+        let (capabilities, granted, outside) = granted_dir();
+        std::fs::write(granted.join("notes.txt"), "notes").unwrap();
+
+        assert!(capabilities
+            .granted_path(&granted.join("notes.txt"))
+            .is_some());
+        assert!(capabilities
+            .granted_path(&granted.join("new.txt"))
+            .is_some());
+        assert!(capabilities
+            .granted_path(&outside.join("new.txt"))
+            .is_none());
+        assert!(capabilities
+            .granted_path(&granted.join("missing").join("new.txt"))
+            .is_none());
+        assert!(Capabilities::default()
+            .granted_path(&granted.join("notes.txt"))
+            .is_none());
+        std::fs::remove_dir_all(granted.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn denies_paths_that_escape_with_dot_dot() {
+        let (capabilities, granted, outside) = granted_dir();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+
+        assert!(capabilities
+            .granted_path(&granted.join("../outside/secret.txt"))
+            .is_none());
+        assert!(capabilities
+            .granted_path(&granted.join("../outside/new.txt"))
+            .is_none());
+        assert!(capabilities
+            .granted_path(&granted.join("../granted/new.txt"))
+            .is_some());
+        std::fs::remove_dir_all(granted.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn denies_symlinks_out_of_granted_directories() {
+        let (capabilities, granted, outside) = granted_dir();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), granted.join("file")).unwrap();
+        std::os::unix::fs::symlink(&outside, granted.join("dir")).unwrap();
+
+        assert!(capabilities.granted_path(&granted.join("file")).is_none());
+        assert!(capabilities
+            .granted_path(&granted.join("dir").join("secret.txt"))
+            .is_none());
+        assert!(capabilities
+            .granted_path(&granted.join("dir").join("new.txt"))
+            .is_none());
+        std::fs::remove_dir_all(granted.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn allows_only_granted_variables_and_programs() {
+        let capabilities: Capabilities = Capabilities {
+            environment: vec!["EDITOR".to_string()],
+            process: vec!["git".to_string()],
+            ..Capabilities::default()
+        };
+        assert!(capabilities.allows_variable("EDITOR"));
+        assert!(!capabilities.allows_variable("HOME"));
+        assert!(capabilities.allows_program("git"));
+        assert!(!capabilities.allows_program("/usr/bin/git"));
+        assert!(!capabilities.allows_program("sh"));
+
+        let everything: Capabilities = Capabilities {
+            environment: vec!["*".to_string()],
+            process: vec!["*".to_string()],
+            ..Capabilities::default()
+        };
+        assert!(everything.allows_variable("HOME"));
+        assert!(everything.allows_program("sh"));
+        assert!(!Capabilities::default().allows_variable("HOME"));
+        assert!(!Capabilities::default().allows_program("git"));
+    }
+
+    #[test]
+    fn denies_spawning_programs_that_are_not_granted() {
+        let plugin: WasmPlugin = load(
+            &spawner(&["/bin/sh", "-c", "exit 0"]),
+            Capabilities {
+                process: vec!["git".to_string()],
+                ..Capabilities::default()
+            },
+        );
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        assert_eq!(plugin.execute(&mut context, &[]), Ok(DENIED));
+    }
+
+    #[test]
+    fn refuses_to_write_through_dangling_symlinks() {
+        let (capabilities, granted, outside) = granted_dir();
+        std::os::unix::fs::symlink(outside.join("planted.txt"), granted.join("link")).unwrap();
+        assert!(capabilities.granted_path(&granted.join("link")).is_none());
+
+        let plugin: WasmPlugin = load(&writer(&granted.join("link"), "planted"), capabilities);
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        assert_eq!(plugin.execute(&mut context, &[]), Ok(DENIED));
+        assert!(!outside.join("planted.txt").exists());
+        std::fs::remove_dir_all(granted.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn writes_files_inside_granted_directories() {
+        let (capabilities, granted, _) = granted_dir();
+        let plugin: WasmPlugin = load(&writer(&granted.join("notes.txt"), "notes"), capabilities);
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        assert_eq!(plugin.execute(&mut context, &[]), Ok(0));
+        assert_eq!(
+            std::fs::read_to_string(granted.join("notes.txt")).unwrap(),
+            "notes"
+        );
+        std::fs::remove_dir_all(granted.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn limits_how_far_memory_grows() {
+        let plugin: WasmPlugin = load(GROWER, Capabilities::default());
+        let mut context: ExecutionContext = ExecutionContext::new(Some(Vec::new()), true);
+        // 2048 more pages would be 128 MiB, past `MAX_MEMORY`
+        assert_eq!(plugin.execute(&mut context, &[]), Ok(-1));
+    }
+}
@@ -1,11 +1,37 @@
-use crate::config::FluxConfig;
+use crate::config::{paint, Abbreviation, FluxConfig};
+use crate::plugin::ExecutionContext;
 use crate::shell::Shell;
 use crate::utils::env::expand_env_vars;
-use colored::*;
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::time::Instant;
-use which;
+
+/// Commands handled by the shell itself, which come before plugins and
+/// programs of the same name
+pub(crate) const BUILTINS: [&str; 10] = [
+    "exit", "cd", "alias", "abbr", "clear", "pwd", "help", "reload", "type", "which",
+];
+
+/// Names plugin commands cannot take, with what each one is
+///
+/// # Arguments
+/// * `config` - Shell configuration with the aliases
+///
+/// # Returns
+/// * `HashMap<String, String>` - Builtins and aliases
+pub(crate) fn reserved_names(config: &FluxConfig) -> HashMap<String, String> {
+    let mut reserved: HashMap<String, String> = config
+        .aliases
+        .keys()
+        .map(|alias| (alias.clone(), "an alias".to_string()))
+        .collect();
+    for builtin in BUILTINS {
+        reserved.insert(builtin.to_string(), "a shell builtin".to_string());
+    }
+    reserved
+}
 
 /// Gets the system shell command and arguments
 fn get_system_shell() -> (&'static str, &'static str) {
@@ -18,25 +44,50 @@ fn get_system_shell() -> (&'static str, &'static str) {
 
 /// Executes a shell command with the given configuration
 ///
+/// Plugins' `preexec` hooks may rewrite or veto the command first; `chpwd`
+/// and `precmd` hooks run once it has finished.
+///
 /// # Arguments
 /// * `cmd` - Command string to execute
 /// * `config` - Shell configuration settings
 ///
 /// # Returns
 /// * `bool` - Whether the shell should continue running
-pub(crate) fn execute_command(cmd: &str, shell: &Shell) -> bool {
+pub(crate) fn execute_command(cmd: &str, shell: &mut Shell) -> bool {
     let start_time: Instant = Instant::now();
-    let cmd: String = expand_env_vars(cmd);
+    let Some(typed) = shell.plugin_manager.borrow().preexec(cmd) else {
+        return true;
+    };
+    let old_cwd: Option<PathBuf> = std::env::current_dir().ok();
+    let cmd: String = expand_env_vars(&typed);
 
     let commands: Vec<CommandPart> = parse_command_chain(&cmd);
-    let mut last_success: bool = true;
-    let mut last_output: Option<std::process::Output> = None;
+    let mut last_status: i32 = 0;
+    // Output of the previous command, when it is piped into the next one
+    let mut piped_output: Option<Vec<u8>> = None;
+    // Operator between the previous command and this one
+    let mut connector: Operator = Operator::None;
+    let mut skipping_pipeline: bool = false;
 
     for command in commands {
-        match command.operator {
-            Operator::And if !last_success => continue,
-            Operator::Or if last_success => continue,
-            _ => {}
+        let previous: Operator = std::mem::replace(&mut connector, command.operator);
+        let stdin: Option<Vec<u8>> = if previous.is_pipe() {
+            piped_output.take()
+        } else {
+            None
+        };
+        let capture: bool = command.operator.is_pipe();
+
+        // A skipped command takes the rest of its pipeline with it
+        let skip: bool = match previous {
+            Operator::And => last_status != 0,
+            Operator::Or => last_status == 0,
+            Operator::Pipe => skipping_pipeline,
+            Operator::None => false,
+        };
+        skipping_pipeline = skip && capture;
+        if skip {
+            continue;
         }
 
         let args: Vec<&str> = command.command.split_whitespace().collect();
@@ -45,164 +96,201 @@ pub(crate) fn execute_command(cmd: &str, shell: &Shell) -> bool {
         }
 
         // Try built-in commands first
-        if handle_builtin_command(&args, &shell.config) {
-            last_success = true;
+        if handle_builtin_command(&args, shell) {
+            last_status = 0;
             continue;
         }
 
-        // Then try plugin commands
-        if let Some(plugin_name) = args.first() {
-            match shell.plugin_manager.execute_plugin(
-                plugin_name,
-                &args[1..].iter().map(|s| s.to_string()).collect::<Vec<_>>(),
-            ) {
-                Ok(_) => {
-                    last_success = true;
-                    continue;
+        // Then try plugins, by name or by one of their commands
+        let plugin: Option<(String, Option<String>)> =
+            shell.plugin_manager.borrow().resolve(args[0]);
+        if let Some((plugin, subcommand)) = plugin {
+            let mut words: Vec<&str> = subcommand.as_deref().into_iter().collect();
+            words.extend(&args[1..]);
+            match run_plugin_command(&plugin, &words, stdin, capture, shell) {
+                Ok((code, output)) => {
+                    last_status = code;
+                    piped_output = output;
                 }
-                Err(e) if e.contains("not found") => {}
                 Err(e) => {
                     print_error(&format!("Plugin error: {}", e), &shell.config);
-                    last_success = false;
-                    continue;
+                    last_status = 1;
                 }
             }
+            continue;
         }
 
         // Check if this is an interactive command
-        if let Some(cmd_name) = args.first() {
-            if is_interactive_command(cmd_name) {
-                let (shell_cmd, shell_arg) = get_system_shell();
-                let status: Result<std::process::ExitStatus, std::io::Error> =
-                    Command::new(shell_cmd)
-                        .arg(shell_arg)
-                        .arg(&command.command)
-                        .status();
-
-                match status {
-                    Ok(exit_status) => {
-                        last_success = exit_status.success();
-                    }
-                    Err(e) => {
-                        print_error(&format!("Failed to execute command: {}", e), &shell.config);
-                        last_success = false;
-                    }
+        if stdin.is_none() && !capture && is_interactive_command(args[0]) {
+            let (shell_cmd, shell_arg) = get_system_shell();
+            let status: Result<std::process::ExitStatus, std::io::Error> = Command::new(shell_cmd)
+                .arg(shell_arg)
+                .arg(&command.command)
+                .status();
+
+            match status {
+                Ok(exit_status) => {
+                    last_status = exit_status.code().unwrap_or(1);
+                }
+                Err(e) => {
+                    print_error(&format!("Failed to execute command: {}", e), &shell.config);
+                    last_status = 1;
                 }
-                continue;
             }
+            continue;
         }
 
-        // Handle non-interactive commands with pipes
+        // Handle non-interactive commands, feeding in piped output
         let (shell_cmd, shell_arg) = get_system_shell();
         let mut command_builder: Command = Command::new(shell_cmd);
-        command_builder.arg(shell_arg).arg(&command.command);
-
-        match command.operator {
-            Operator::Pipe => {
-                if let Some(previous_output) = last_output.clone() {
-                    command_builder.stdin(Stdio::piped());
-                    match command_builder
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::inherit())
-                        .spawn()
-                    {
-                        Ok(mut child) => {
-                            if let Some(mut stdin) = child.stdin.take() {
-                                if let Err(e) = stdin.write_all(&previous_output.stdout) {
-                                    print_error(
-                                        &format!("Failed to pipe data: {}", e),
-                                        &shell.config,
-                                    );
-                                    last_success = false;
-                                    continue;
-                                }
-                            }
-
-                            match child.wait_with_output() {
-                                Ok(output) => {
-                                    last_success = output.status.success();
-                                    if !matches!(command.operator, Operator::Pipe) {
-                                        if let Err(e) = std::io::stdout().write_all(&output.stdout)
-                                        {
-                                            print_error(
-                                                &format!("Failed to write output: {}", e),
-                                                &shell.config,
-                                            );
-                                            last_success = false;
-                                        }
-                                    }
-                                    last_output = Some(output);
-                                }
-                                Err(e) => {
-                                    print_error(
-                                        &format!("Failed to wait for command: {}", e),
-                                        &shell.config,
-                                    );
-                                    last_success = false;
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            print_error(&format!("Failed to spawn command: {}", e), &shell.config);
-                            last_success = false;
-                        }
-                    }
+        command_builder
+            .arg(shell_arg)
+            .arg(&command.command)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+
+        let output: std::io::Result<std::process::Output> =
+            command_builder.spawn().and_then(|mut child| {
+                if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+                    // Written from another thread so a command that fills its
+                    // output pipe before reading all its input cannot deadlock
+                    std::thread::spawn(move || pipe.write_all(&input));
                 }
-            }
-            _ => {
-                match command_builder
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::inherit())
-                    .output()
-                {
-                    Ok(output) => {
-                        last_success = output.status.success();
-
-                        if !command.operator.is_pipe() {
-                            if let Err(e) = std::io::stdout().write_all(&output.stdout) {
-                                print_error(
-                                    &format!("Failed to write output: {}", e),
-                                    &shell.config,
-                                );
-                                last_success = false;
-                            }
-                        }
-
-                        last_output = Some(output);
-                    }
-                    Err(e) => {
-                        print_error(&format!("Failed to execute command: {}", e), &shell.config);
-                        last_success = false;
-                    }
+                child.wait_with_output()
+            });
+
+        match output {
+            Ok(output) => {
+                last_status = output.status.code().unwrap_or(1);
+                if capture {
+                    piped_output = Some(output.stdout);
+                } else if let Err(e) = std::io::stdout().write_all(&output.stdout) {
+                    print_error(&format!("Failed to write output: {}", e), &shell.config);
+                    last_status = 1;
                 }
             }
+            Err(e) => {
+                print_error(&format!("Failed to execute command: {}", e), &shell.config);
+                last_status = 1;
+            }
         }
     }
 
+    let duration: std::time::Duration = start_time.elapsed();
     if shell.config.show_execution_time {
-        let duration: std::time::Duration = start_time.elapsed();
         print_success(
             &format!("Completed in {:.2}ms", duration.as_secs_f64() * 1000.0),
             &shell.config,
         );
     }
 
+    let plugin_manager = shell.plugin_manager.borrow();
+    if let (Some(old_cwd), Ok(new_cwd)) = (old_cwd, std::env::current_dir()) {
+        if old_cwd != new_cwd {
+            plugin_manager.chpwd(&old_cwd, &new_cwd);
+        }
+    }
+    plugin_manager.precmd(&typed, last_status, duration);
+
     true
 }
 
+/// Runs a plugin command with an execution context
+///
+/// `<`, `>` and `>>` redirect the command's input and output to files, as
+/// the system shell does for other commands.
+///
+/// # Arguments
+/// * `plugin` - Name of the plugin
+/// * `args` - Arguments for the plugin, possibly with redirections
+/// * `stdin` - Output of the previous command when piped into this one
+/// * `capture` - Whether the output is piped into the next command
+/// * `shell` - Running shell
+///
+/// # Returns
+/// * `Result<(i32, Option<Vec<u8>>), String>` - Exit code and captured
+///   output, or the error the command returned
+fn run_plugin_command(
+    plugin: &str,
+    args: &[&str],
+    stdin: Option<Vec<u8>>,
+    capture: bool,
+    shell: &Shell,
+) -> Result<(i32, Option<Vec<u8>>), String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut input: Option<String> = None;
+    let mut output: Option<(String, bool)> = None;
+    let mut tokens = args.iter();
+    while let Some(token) = tokens.next() {
+        let operator: &str = [">>", ">", "<"]
+            .into_iter()
+            .find(|operator| token.starts_with(operator))
+            .unwrap_or_default();
+        if operator.is_empty() {
+            words.push(token.to_string());
+            continue;
+        }
+        let path: String = match &token[operator.len()..] {
+            "" => tokens
+                .next()
+                .ok_or_else(|| format!("missing file name after '{}'", operator))?
+                .to_string(),
+            path => path.to_string(),
+        };
+        match operator {
+            "<" => input = Some(path),
+            ">>" => output = Some((path, true)),
+            _ => output = Some((path, false)),
+        }
+    }
+
+    let stdin: Option<Vec<u8>> = match &input {
+        Some(path) => Some(std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?),
+        None => stdin,
+    };
+    shell.plugin_manager.borrow_mut().load_on_demand(plugin)?;
+    let mut context: ExecutionContext = ExecutionContext::new(stdin, capture || output.is_some());
+    let code: i32 = shell
+        .plugin_manager
+        .borrow()
+        .execute_plugin(plugin, &mut context, &words)?;
+    let captured: Option<Vec<u8>> = context.apply()?;
+
+    if let Some((path, append)) = output {
+        let mut file: std::fs::File = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        file.write_all(&captured.unwrap_or_default())
+            .map_err(|e| format!("{}: {}", path, e))?;
+        return Ok((code, capture.then(Vec::new)));
+    }
+    Ok((code, captured))
+}
+
 /// Handles built-in shell commands
 ///
 /// Processes internal commands like cd, exit, env, etc.
 ///
 /// # Arguments
 /// * `args` - Command arguments split into words
-/// * `config` - Shell configuration settings
+/// * `shell` - Running shell, for builtins that change its state
 ///
 /// # Returns
 /// * `bool` - Whether the command was handled as a builtin
-pub(crate) fn handle_builtin_command(args: &[&str], config: &FluxConfig) -> bool {
+pub(crate) fn handle_builtin_command(args: &[&str], shell: &mut Shell) -> bool {
+    let config: &FluxConfig = &shell.config;
     match args[0] {
         "exit" => {
+            shell.plugin_manager.borrow().exit();
             if args.len() > 1 {
                 let exit_code: i32 = args[1].parse().unwrap_or(0);
                 std::process::exit(exit_code);
@@ -231,6 +319,10 @@ pub(crate) fn handle_builtin_command(args: &[&str], config: &FluxConfig) -> bool
             }
             true
         }
+        "abbr" => {
+            abbr_command(&args[1..], shell);
+            true
+        }
         "clear" => {
             print!("\x1B[2J\x1B[1;1H");
             true
@@ -245,18 +337,125 @@ pub(crate) fn handle_builtin_command(args: &[&str], config: &FluxConfig) -> bool
             println!("Flux Shell - An advanced, customizable shell for modern systems");
             true
         }
+        "type" | "which" => {
+            for name in &args[1..] {
+                type_command(name, shell);
+            }
+            true
+        }
+        "reload" => {
+            match shell.reload() {
+                Ok(()) => print_success("Configuration reloaded", &shell.config),
+                Err(e) => print_error(&format!("Failed to reload config: {}", e), &shell.config),
+            }
+            true
+        }
         _ => false,
     }
 }
 
+/// Shows what a command name runs
+///
+/// Prints every builtin, alias, plugin and program the name could mean, the
+/// one that runs first. Plugin commands that another name takes over can
+/// still be run as `plugin::command`.
+///
+/// # Arguments
+/// * `name` - Command name, possibly `plugin::command`
+/// * `shell` - Running shell
+fn type_command(name: &str, shell: &Shell) {
+    // Each meaning, with the name that reaches it when it is shadowed
+    let mut found: Vec<(String, Option<String>)> = Vec::new();
+    if BUILTINS.contains(&name) {
+        found.push(("a shell builtin".to_string(), None));
+    }
+    if let Some(command) = shell.config.aliases.get(name) {
+        found.push((format!("an alias for '{}'", command), None));
+    }
+    for (plugin, command) in shell.plugin_manager.borrow().lookup(name) {
+        found.push(match command {
+            Some(command) => (
+                format!("command '{}' of plugin '{}'", command, plugin),
+                Some(format!("{}::{}", plugin, command)),
+            ),
+            None => (format!("plugin '{}'", plugin), None),
+        });
+    }
+    if let Ok(path) = which::which(name) {
+        found.push((path.display().to_string(), None));
+    }
+
+    if found.is_empty() {
+        print_error(&format!("{}: not found", name), &shell.config);
+        return;
+    }
+    for (index, (meaning, reach)) in found.iter().enumerate() {
+        match (index, reach) {
+            (0, _) => println!("{} is {}", name, meaning),
+            (_, Some(reach)) if reach != name => {
+                println!("{} is also {} (run it as {})", name, meaning, reach)
+            }
+            _ => println!("{} is also {}", name, meaning),
+        }
+    }
+}
+
+/// Lists, adds and removes abbreviations
+///
+/// Changes are written to the configuration file and applied right away.
+///
+/// # Arguments
+/// * `args` - Arguments after `abbr`
+/// * `shell` - Running shell, reloaded after a change
+fn abbr_command(args: &[&str], shell: &mut Shell) {
+    let result: Result<Vec<String>, String> = match args {
+        [] | ["list"] => {
+            let mut names: Vec<&String> = shell.config.abbreviations.keys().collect();
+            names.sort();
+            for name in names {
+                let abbreviation: &Abbreviation = &shell.config.abbreviations[name];
+                let position: &str = if abbreviation.position == "anywhere" {
+                    " (anywhere)"
+                } else {
+                    ""
+                };
+                println!("{} = '{}'{}", name, abbreviation.expansion, position);
+            }
+            return;
+        }
+        ["add", "--anywhere" | "-a", name, expansion @ ..] if !expansion.is_empty() => {
+            let abbreviation: Abbreviation = Abbreviation::new(expansion.join(" "), true);
+            FluxConfig::set_entry(&shell.config_path, "abbreviations", name, &abbreviation)
+        }
+        ["add", name, expansion @ ..] if !expansion.is_empty() => {
+            let abbreviation: Abbreviation = Abbreviation::new(expansion.join(" "), false);
+            FluxConfig::set_entry(&shell.config_path, "abbreviations", name, &abbreviation)
+        }
+        ["remove" | "rm", name] => {
+            FluxConfig::remove_entry(&shell.config_path, "abbreviations", name)
+        }
+        _ => {
+            println!("Usage: abbr [list]");
+            println!("       abbr add [--anywhere] NAME EXPANSION...");
+            println!("       abbr remove NAME");
+            return;
+        }
+    };
+
+    // Reloading reports any warnings about the updated file
+    if let Err(e) = result.and_then(|_| shell.reload()) {
+        print_error(&e, &shell.config);
+    }
+}
+
 /// Prints an error message with appropriate formatting
 ///
 /// # Arguments
 /// * `message` - Error message to display
 /// * `config` - Shell configuration for styling
 fn print_error(message: &str, config: &FluxConfig) {
-    let prefix: ColoredString = "Error:".color(config.theme.error_color.as_str());
-    let message: ColoredString = message.color(config.theme.error_color.as_str());
+    let prefix: String = paint("Error:", &config.theme.error_color);
+    let message: String = paint(message, &config.theme.error_color);
     println!("{} {}", prefix, message);
 }
 
@@ -266,8 +465,8 @@ fn print_error(message: &str, config: &FluxConfig) {
 /// * `message` - Success message to display
 /// * `config` - Shell configuration for styling
 fn print_success(message: &str, config: &FluxConfig) {
-    let prefix: ColoredString = "Success:".color(config.theme.success_color.as_str());
-    let message: ColoredString = message.color(config.theme.success_color.as_str());
+    let prefix: String = paint("Success:", &config.theme.success_color);
+    let message: String = paint(message, &config.theme.success_color);
     println!("{} {}", prefix, message);
 }
 
@@ -384,7 +583,7 @@ fn is_interactive_command(cmd: &str) -> bool {
     false
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Operator {
     None,
     And,  // &&
@@ -1,24 +1,54 @@
+use crate::config::FluxConfig;
+use crate::plugin::PluginManager;
+use crate::shell::commands::BUILTINS;
+use crate::shell::keymap::{PendingExpansion, ViMode};
+use crate::shell::prompt::{display_width, PromptDisplay};
+use console::Term;
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::highlight::Highlighter;
-use rustyline::hint::Hinter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::line_buffer::LineBuffer;
 use rustyline::validate::Validator;
-use rustyline::Context;
 use rustyline::Helper;
-use std::collections::HashMap;
+use rustyline::{Changeset, Context};
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::fs;
+use std::rc::Rc;
+use std::sync::Mutex;
 
 /// Provides command and filename completion for the shell
 pub struct FluxCompleter {
     filename_completer: FilenameCompleter,
     commands: Vec<String>,
+    prompt: PromptDisplay,
+    /// Keeps the `{mode}` indicator in the prompt up to date
+    vi_mode: ViMode,
+    /// Candidates of the last completion as (replacement, display), when
+    /// `completion_style` is `menu`
+    menu: Option<Mutex<Vec<(String, String)>>>,
+    /// Abbreviation left by the space binding, applied as a completion
+    expansion: PendingExpansion,
+    /// Loaded plugins, which complete their own arguments
+    plugins: Rc<RefCell<PluginManager>>,
 }
 
 impl FluxCompleter {
-    /// Creates a new completer with the given command aliases
+    /// Creates a new completer for the given configuration
     ///
     /// # Arguments
-    /// * `aliases` - Map of command aliases to include in completion
-    pub fn new(aliases: HashMap<String, String>) -> Self {
+    /// * `config` - Shell configuration with the aliases and completion style
+    /// * `prompt` - Handle to the prompt engine, used to redraw the prompt
+    /// * `vi_mode` - Tracker for the vi mode indicator
+    /// * `expansion` - Slot the space binding leaves abbreviations in
+    /// * `plugins` - Loaded plugins
+    pub fn new(
+        config: &FluxConfig,
+        prompt: PromptDisplay,
+        vi_mode: ViMode,
+        expansion: PendingExpansion,
+        plugins: Rc<RefCell<PluginManager>>,
+    ) -> Self {
         // Get all directories in PATH for command completion
         let path_dirs: String = std::env::var("PATH").unwrap_or_default();
         let mut commands: Vec<String> = Vec::new();
@@ -45,22 +75,20 @@ impl FluxCompleter {
         }
 
         // Add built-in commands
-        commands.extend(vec![
-            "cd".to_string(),
-            "exit".to_string(),
-            "clear".to_string(),
-            "pwd".to_string(),
-            "help".to_string(),
-            "alias".to_string(),
-            "env".to_string(),
-        ]);
+        commands.extend(BUILTINS.iter().map(|builtin| builtin.to_string()));
+        commands.push("env".to_string());
 
         // Add aliases to commands
-        commands.extend(aliases.keys().cloned());
+        commands.extend(config.aliases.keys().cloned());
 
         FluxCompleter {
             filename_completer: FilenameCompleter::new(),
             commands,
+            prompt,
+            vi_mode,
+            menu: (config.completion_style == "menu").then(|| Mutex::new(Vec::new())),
+            expansion,
+            plugins,
         }
     }
 }
@@ -82,16 +110,68 @@ impl Completer for FluxCompleter {
         line: &str,
         pos: usize,
         ctx: &Context<'_>,
+    ) -> Result<(usize, Vec<Pair>), rustyline::error::ReadlineError> {
+        // An abbreviation is the only candidate; `update` replaces the word
+        if let Some((_, text)) = self.expansion.lock().ok().and_then(|e| e.clone()) {
+            let pair: Pair = Pair {
+                display: text.clone(),
+                replacement: text,
+            };
+            return Ok((pos, vec![pair]));
+        }
+
+        let (start, matches) = self.candidates(line, pos, ctx)?;
+        if let Some(menu) = &self.menu {
+            if let Ok(mut menu) = menu.lock() {
+                *menu = matches
+                    .iter()
+                    .map(|pair| (pair.replacement.clone(), pair.display.clone()))
+                    .collect();
+            }
+        }
+        Ok((start, matches))
+    }
+
+    /// Applies the elected candidate, or replaces the abbreviation before the
+    /// cursor with its expansion
+    fn update(&self, line: &mut LineBuffer, start: usize, elected: &str, cl: &mut Changeset) {
+        let expansion: Option<(usize, String)> =
+            self.expansion.lock().ok().and_then(|mut e| e.take());
+        let start: usize = expansion.map_or(start, |(word, _)| word);
+        let end: usize = line.pos();
+        line.replace(start..end, elected, cl);
+    }
+}
+
+impl FluxCompleter {
+    /// Commands for the first word, file names for the others
+    fn candidates(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
     ) -> Result<(usize, Vec<Pair>), rustyline::error::ReadlineError> {
         let (start, words) =
             rustyline::completion::extract_word(line, pos, None, |c| c == ' ' || c == '\t');
 
-        // If this is the first word, complete commands
+        let Ok(plugins) = self.plugins.try_borrow() else {
+            return self.filename_completer.complete(line, pos, ctx);
+        };
+
+        // If this is the first word, complete commands, plugin names and
+        // plugin commands; `plugin::command` only once `::` is typed
         if !line[..start].contains(char::is_whitespace) {
+            let mut names: Vec<String> = plugins.plugin_names();
+            names.extend(plugins.command_names());
+            names.retain(|name| !self.commands.contains(name));
+            names.retain(|name| !name.contains("::") || words.contains("::"));
+            names.sort();
+            names.dedup();
             let matches: Vec<Pair> = self
                 .commands
                 .iter()
-                .filter(|cmd| cmd.starts_with(&words))
+                .chain(&names)
+                .filter(|cmd| cmd.starts_with(words))
                 .map(|cmd| Pair {
                     display: cmd.clone(),
                     replacement: cmd.clone(),
@@ -100,14 +180,131 @@ impl Completer for FluxCompleter {
             return Ok((start, matches));
         }
 
+        // Arguments of a plugin are completed by the plugin
+        let mut typed: Vec<String> = line[..start].split_whitespace().map(String::from).collect();
+        let Some((plugin, subcommand)) = plugins.resolve(&typed.remove(0)) else {
+            return self.filename_completer.complete(line, pos, ctx);
+        };
+        typed.splice(0..0, subcommand);
+        typed.push(words.to_string());
+        let matches: Vec<Pair> = plugins
+            .complete(&plugin, &typed)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(words))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        if !matches.is_empty() {
+            return Ok((start, matches));
+        }
+
         // Otherwise, use filename completion
         self.filename_completer.complete(line, pos, ctx)
     }
 }
 
-impl Highlighter for FluxCompleter {}
+impl Highlighter for FluxCompleter {
+    /// Draws the right-side prompt flush-right on the input line
+    ///
+    /// The right prompt is written with the cursor saved and restored, so the
+    /// editor's layout is unaffected. It is hidden once the input reaches it.
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let Some((right, left_width)) = self.prompt.right() else {
+            return Cow::Borrowed(line);
+        };
+        let Some((_, cols)) = Term::stdout().size_checked() else {
+            return Cow::Borrowed(line);
+        };
+
+        let right_width: usize = display_width(&right);
+        let cols: usize = cols as usize;
+        if right_width == 0
+            || line.contains('\n')
+            || left_width + display_width(line) + right_width >= cols
+        {
+            return Cow::Borrowed(line);
+        }
+
+        Cow::Owned(format!(
+            "{}\x1b7\x1b[{}G{}\x1b8",
+            line,
+            cols - right_width + 1,
+            right
+        ))
+    }
+
+    /// Forces a full redraw on every keystroke while a right prompt or a vi
+    /// mode indicator is shown
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        self.prompt.right().is_some() || self.vi_mode.shown()
+    }
+
+    /// Displays the prompt with the indicator of the current vi mode
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        default: bool,
+    ) -> Cow<'b, str> {
+        if !default {
+            return Cow::Borrowed(prompt);
+        }
+        if !self.vi_mode.shown() {
+            return Cow::Borrowed(prompt);
+        }
+        self.vi_mode
+            .apply(prompt)
+            .map_or(Cow::Borrowed(prompt), Cow::Owned)
+    }
+}
+
+/// Completion menu drawn below the input line
+///
+/// It is only displayed: accepting a hint must not insert the menu.
+pub struct MenuHint(String);
+
+impl Hint for MenuHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
 impl Hinter for FluxCompleter {
-    type Hint = String;
+    type Hint = MenuHint;
+
+    /// Lists the candidates below the line while cycling through them, with
+    /// the one in the line highlighted
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<MenuHint> {
+        let menu = self.menu.as_ref()?.lock().ok()?;
+        if menu.len() < 2 {
+            return None;
+        }
+        let start: usize = line[..pos]
+            .char_indices()
+            .rfind(|(_, c)| c.is_whitespace())
+            .map_or(0, |(index, c)| index + c.len_utf8());
+        let word: &str = &line[start..pos];
+        if !menu.iter().any(|(replacement, _)| replacement == word) {
+            return None;
+        }
+
+        let items: Vec<String> = menu
+            .iter()
+            .map(|(replacement, display)| {
+                if replacement == word {
+                    format!("\x1b[7m{}\x1b[0m", display)
+                } else {
+                    display.clone()
+                }
+            })
+            .collect();
+        Some(MenuHint(format!("\n{}", items.join("  "))))
+    }
 }
 impl Validator for FluxCompleter {}
 impl Helper for FluxCompleter {}
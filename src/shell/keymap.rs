@@ -0,0 +1,275 @@
+use super::completion::FluxCompleter;
+use crate::config::{editor_action, expand_at, parse_chord, Abbreviation, FluxConfig, KeyBinding};
+use rustyline::config::Configurer;
+use rustyline::history::FileHistory;
+use rustyline::{
+    Cmd, ConditionalEventHandler, Editor, Event, EventContext, EventHandler, InputMode, KeyCode,
+    KeyEvent, Modifiers, RepeatCount,
+};
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A `run` binding that was pressed: the command and the input to restore
+pub(crate) type PendingRun = Arc<Mutex<Option<(String, String)>>>;
+
+/// An abbreviation being expanded: the start of the word and its replacement
+///
+/// The editor has no command that replaces text and moves the cursor past it,
+/// except applying a completion, so the space binding leaves the expansion
+/// here and completes; `FluxCompleter` then offers it as the only candidate.
+pub(crate) type PendingExpansion = Arc<Mutex<Option<(usize, String)>>>;
+
+/// Tracks the vi input mode so the prompt's `{mode}` indicator can follow it
+///
+/// The line editor has no mode-change callback, so every key is inspected
+/// before the editor handles it and the mode it switches to is predicted.
+#[derive(Clone)]
+pub(crate) struct ViMode {
+    mode: Arc<AtomicU8>,
+    /// Indicators for insert, normal and replace mode, padded to one width
+    indicators: [String; 3],
+    /// Whether the prompt shows `{mode}` at all
+    shown: bool,
+}
+
+impl ViMode {
+    /// Creates the tracker for a configuration
+    ///
+    /// # Arguments
+    /// * `config` - Shell configuration with the edit mode and indicators
+    pub(crate) fn new(config: &FluxConfig) -> Self {
+        let raw: [&str; 3] = [
+            &config.vi_insert_indicator,
+            &config.vi_normal_indicator,
+            &config.vi_replace_indicator,
+        ];
+        // Every indicator has the same width so the cursor stays in place
+        let width: usize = raw.iter().map(|i| i.chars().count()).max().unwrap_or(0);
+        let indicators: [String; 3] = raw.map(|i| format!("{:<width$}", i, width = width));
+        let templates: [&str; 3] = [
+            &config.prompt_template,
+            &config.powerline_suffix,
+            &config.right_prompt_template,
+        ];
+
+        ViMode {
+            mode: Arc::new(AtomicU8::new(0)),
+            indicators,
+            shown: config.edit_mode == "vi" && templates.iter().any(|t| t.contains("{mode}")),
+        }
+    }
+
+    /// Whether the prompt has a mode indicator to keep up to date
+    pub(crate) fn shown(&self) -> bool {
+        self.shown
+    }
+
+    /// Text for the `{mode}` placeholder when a prompt is first drawn
+    ///
+    /// # Arguments
+    /// * `config` - Shell configuration with the edit mode and indicators
+    pub(crate) fn placeholder(config: &FluxConfig) -> String {
+        if config.edit_mode == "vi" {
+            ViMode::new(config).indicators[0].clone()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Puts the tracker back in insert mode, where every new line starts
+    pub(crate) fn reset(&self) {
+        self.mode.store(0, Ordering::SeqCst);
+    }
+
+    /// Swaps the insert indicator in a drawn prompt for the current mode's
+    ///
+    /// # Arguments
+    /// * `prompt` - Prompt as drawn at the start of the line
+    ///
+    /// # Returns
+    /// * `Option<String>` - The updated prompt, or None if it is unchanged
+    pub(crate) fn apply(&self, prompt: &str) -> Option<String> {
+        let mode: usize = self.mode.load(Ordering::SeqCst) as usize;
+        if !self.shown || mode == 0 {
+            return None;
+        }
+        Some(prompt.replacen(&self.indicators[0], &self.indicators[mode], 1))
+    }
+}
+
+impl ConditionalEventHandler for ViMode {
+    fn handle(&self, evt: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        let key: Option<&KeyEvent> = evt.get(0);
+        let next: InputMode = match (ctx.input_mode(), key) {
+            (_, Some(KeyEvent(KeyCode::Esc, _))) => InputMode::Command,
+            (InputMode::Command, Some(KeyEvent(KeyCode::Char(c), Modifiers::NONE))) => match c {
+                'i' | 'a' | 'I' | 'A' | 'o' | 'O' | 's' | 'S' | 'c' | 'C' => InputMode::Insert,
+                'R' => InputMode::Replace,
+                _ => InputMode::Command,
+            },
+            (mode, _) => mode,
+        };
+        let index: u8 = match next {
+            InputMode::Insert => 0,
+            InputMode::Command => 1,
+            InputMode::Replace => 2,
+        };
+        self.mode.store(index, Ordering::SeqCst);
+        // Let the editor handle the key as usual
+        None
+    }
+}
+
+/// Runs a flux command from a key binding
+///
+/// The editor can't run commands itself, so the binding accepts the line and
+/// leaves the command for the shell loop, which restores the input afterwards.
+struct RunCommand {
+    command: String,
+    pending: PendingRun,
+}
+
+impl ConditionalEventHandler for RunCommand {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if let Ok(mut pending) = self.pending.lock() {
+            *pending = Some((self.command.clone(), ctx.line().to_string()));
+        }
+        Some(Cmd::AcceptLine)
+    }
+}
+
+/// Inserts the output of a shell command, such as a fuzzy file picker
+struct InsertOutput {
+    command: String,
+}
+
+impl ConditionalEventHandler for InsertOutput {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd.exe", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        // Pickers draw on the terminal through stderr or /dev/tty
+        let output = Command::new(shell)
+            .args([flag, &self.command])
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output();
+
+        match output {
+            Ok(output) if output.status.success() => {
+                let text: String = String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .replace('\n', " ");
+                Some(Cmd::Insert(1, text))
+            }
+            Ok(_) => Some(Cmd::Noop),
+            Err(e) => {
+                eprintln!("\r\nFailed to run {}: {}", self.command, e);
+                Some(Cmd::Noop)
+            }
+        }
+    }
+}
+
+/// Expands the abbreviation before the cursor when space is pressed
+struct ExpandAbbreviation {
+    abbreviations: HashMap<String, Abbreviation>,
+    pending: PendingExpansion,
+}
+
+impl ConditionalEventHandler for ExpandAbbreviation {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.input_mode() != InputMode::Insert {
+            return None;
+        }
+        let (start, expansion) = expand_at(&self.abbreviations, ctx.line(), ctx.pos())?;
+        let mut pending = self.pending.lock().ok()?;
+        *pending = Some((start, format!("{} ", expansion)));
+        Some(Cmd::Complete)
+    }
+}
+
+/// Applies the edit mode, completion style, abbreviations and key bindings
+/// to the editor
+///
+/// Bindings from a previous configuration are removed first.
+///
+/// # Arguments
+/// * `editor` - Line editor to configure
+/// * `config` - Shell configuration
+/// * `vi_mode` - Tracker for the `{mode}` indicator
+/// * `pending` - Slot `run` bindings leave their command in
+/// * `expansion` - Slot the space binding leaves abbreviations in
+/// * `bound` - Key sequences bound so far; replaced with the new ones
+pub(crate) fn configure(
+    editor: &mut Editor<FluxCompleter, FileHistory>,
+    config: &FluxConfig,
+    vi_mode: &ViMode,
+    pending: &PendingRun,
+    expansion: &PendingExpansion,
+    bound: &mut Vec<Event>,
+) {
+    for event in bound.drain(..) {
+        editor.unbind_sequence(event);
+    }
+
+    editor.set_edit_mode(if config.edit_mode == "vi" {
+        rustyline::EditMode::Vi
+    } else {
+        rustyline::EditMode::Emacs
+    });
+    editor.set_completion_type(match config.completion_style.as_str() {
+        "circular" | "menu" => rustyline::CompletionType::Circular,
+        _ => rustyline::CompletionType::List,
+    });
+
+    if vi_mode.shown() {
+        editor.bind_sequence(
+            Event::Any,
+            EventHandler::Conditional(Box::new(vi_mode.clone())),
+        );
+        bound.push(Event::Any);
+    }
+
+    if !config.abbreviations.is_empty() {
+        let event: Event = Event::from(KeyEvent(KeyCode::Char(' '), Modifiers::NONE));
+        let handler: ExpandAbbreviation = ExpandAbbreviation {
+            abbreviations: config.abbreviations.clone(),
+            pending: Arc::clone(expansion),
+        };
+        editor.bind_sequence(event.clone(), EventHandler::Conditional(Box::new(handler)));
+        bound.push(event);
+    }
+
+    for (chord, binding) in &config.keybindings {
+        // Invalid bindings were reported when the configuration loaded
+        let Ok(keys) = parse_chord(chord) else {
+            continue;
+        };
+        let Some(handler) = handler(binding, pending) else {
+            continue;
+        };
+        let event: Event = Event::KeySeq(keys);
+        editor.bind_sequence(event.clone(), handler);
+        bound.push(event);
+    }
+}
+
+/// Builds the handler for a key binding
+fn handler(binding: &KeyBinding, pending: &PendingRun) -> Option<EventHandler> {
+    match (&binding.action, &binding.run, &binding.insert) {
+        (Some(action), None, None) => editor_action(action).map(EventHandler::Simple),
+        (None, Some(command), None) => Some(EventHandler::Conditional(Box::new(RunCommand {
+            command: command.clone(),
+            pending: Arc::clone(pending),
+        }))),
+        (None, None, Some(command)) => Some(EventHandler::Conditional(Box::new(InsertOutput {
+            command: command.clone(),
+        }))),
+        _ => None,
+    }
+}
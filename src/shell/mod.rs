@@ -1,24 +1,50 @@
 /// Shell module containing core shell functionality and components
 mod commands;
 mod completion;
+mod keymap;
 mod prompt;
 
-use crate::config::FluxConfig;
-use crate::plugin::PluginManager;
+pub(crate) use commands::reserved_names;
+pub(crate) use prompt::preview_themes;
+
+use crate::config::{expand_line, ConfigWatcher, FluxConfig, LoadedConfig};
+use crate::plugin::{PluginManager, TrustPolicy};
 use crate::shell::completion::FluxCompleter;
-use crate::utils::env::set_initial_env_vars;
+use crate::shell::keymap::{PendingExpansion, PendingRun, ViMode};
+use crate::shell::prompt::PromptEngine;
+use crate::utils::env::{reapply_env_vars, set_initial_env_vars};
+use crate::utils::paths;
+use colored::*;
 use rustyline::config::Configurer;
 use rustyline::history::FileHistory;
-use rustyline::{error::ReadlineError, Editor};
+use rustyline::{error::ReadlineError, Editor, Event};
+use std::cell::RefCell;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
 
 /// Main shell implementation
 pub struct Shell {
     /// Shell configuration settings
     config: FluxConfig,
+    /// Path the configuration was loaded from
+    config_path: PathBuf,
+    /// Watches the configuration files when `watch_config` is enabled
+    config_watcher: Option<ConfigWatcher>,
     /// Line editor with history and completion
     editor: Editor<FluxCompleter, FileHistory>,
-    plugin_manager: PluginManager,
+    /// Loaded plugins, shared with the completer for argument completion
+    plugin_manager: Rc<RefCell<PluginManager>>,
+    /// Renders the prompt, computing slow segments in the background
+    prompt_engine: PromptEngine,
+    /// Tracks the vi input mode for the `{mode}` indicator
+    vi_mode: ViMode,
+    /// Command left by a `run` key binding for the loop to execute
+    pending_run: PendingRun,
+    /// Abbreviation being expanded, shared by the space binding and completer
+    expansion: PendingExpansion,
+    /// Key sequences bound from `keybindings`, unbound on reload
+    bound_keys: Vec<Event>,
 }
 
 impl Shell {
@@ -27,26 +53,57 @@ impl Shell {
     /// Initializes the line editor, loads history, and sets up
     /// command completion and environment variables.
     pub fn new() -> Self {
-        let config_path: PathBuf = Self::get_config_path();
+        let config_path: PathBuf = paths::config_file();
         let config: FluxConfig = FluxConfig::load(&config_path);
 
-        // Create history file in config directory
-        let mut history_path: PathBuf = config_path.parent().unwrap().to_path_buf();
-        history_path.push("history.txt");
+        let history_path: PathBuf = paths::history_file();
+        if let Some(parent) = history_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                eprintln!("Failed to create {}: {}", parent.display(), e);
+            }
+        }
 
-        // Initialize editor with custom completer
-        let completer: FluxCompleter = FluxCompleter::new(config.aliases.clone());
         let mut editor: Editor<FluxCompleter, FileHistory> =
             Editor::new().expect("Failed to create editor");
 
+        let prompt_engine: PromptEngine = PromptEngine::new();
+
+        let mut plugin_manager = PluginManager::new(paths::plugin_dir(), paths::plugin_build_dir());
+        plugin_manager.set_reserved(reserved_names(&config));
+        plugin_manager.set_trust(trust_policy(&config));
+        if let Err(e) = plugin_manager.load_plugins(&config.plugin_permissions) {
+            eprintln!("Failed to load plugins: {}", e);
+        }
+        let plugin_manager: Rc<RefCell<PluginManager>> = Rc::new(RefCell::new(plugin_manager));
+
+        // Initialize editor with custom completer
+        let vi_mode: ViMode = ViMode::new(&config);
+        let expansion: PendingExpansion = PendingExpansion::default();
+        let completer: FluxCompleter = FluxCompleter::new(
+            &config,
+            prompt_engine.display(),
+            vi_mode.clone(),
+            Arc::clone(&expansion),
+            Rc::clone(&plugin_manager),
+        );
+
         // Configure editor
         editor.set_helper(Some(completer));
         let _ = editor.set_max_history_size(config.history_size);
-        editor.set_auto_add_history(true);
+        // Entries are added by the loop, after abbreviations are expanded
+        editor.set_auto_add_history(false);
 
-        // Enable completion features
-        editor.set_completion_type(rustyline::CompletionType::List);
-        editor.set_edit_mode(rustyline::EditMode::Emacs);
+        // Edit mode, completion style, abbreviations and key bindings
+        let pending_run: PendingRun = PendingRun::default();
+        let mut bound_keys: Vec<Event> = Vec::new();
+        keymap::configure(
+            &mut editor,
+            &config,
+            &vi_mode,
+            &pending_run,
+            &expansion,
+            &mut bound_keys,
+        );
 
         // Load history from file
         if let Err(e) = editor.load_history(&history_path) {
@@ -59,34 +116,86 @@ impl Shell {
         // Set environment variables from config
         set_initial_env_vars(&config.environment_variables);
 
-        let mut plugin_manager = PluginManager::new();
-        if let Err(e) = plugin_manager.load_plugins() {
-            eprintln!("Failed to load plugins: {}", e);
-        }
+        let config_watcher: Option<ConfigWatcher> = config.watch_config.then(|| {
+            let files: Vec<PathBuf> = FluxConfig::check(&config_path)
+                .map(|loaded| loaded.files)
+                .unwrap_or_else(|_| vec![config_path.clone()]);
+            ConfigWatcher::spawn(files)
+        });
 
         Shell {
             config,
+            config_path,
+            config_watcher,
             editor,
             plugin_manager,
+            prompt_engine,
+            vi_mode,
+            pending_run,
+            expansion,
+            bound_keys,
         }
     }
 
-    /// Gets the path to the shell configuration file
+    /// Reloads the configuration into the running shell
     ///
-    /// Creates necessary directories if they don't exist.
+    /// Rebuilds the completer's alias list and key bindings, re-applies
+    /// environment variables and plugin permissions and refreshes the prompt.
+    /// If the configuration fails to load, the current one is kept.
     ///
     /// # Returns
-    /// * Path to the configuration file
-    pub fn get_config_path() -> PathBuf {
-        let mut path: PathBuf = if let Some(app_data) = dirs::config_dir() {
-            app_data
-        } else {
-            PathBuf::from(".")
+    /// * `Result<(), String>` - Error that stopped the configuration loading
+    pub(crate) fn reload(&mut self) -> Result<(), String> {
+        let LoadedConfig {
+            config,
+            warnings,
+            files,
+        } = FluxConfig::check(&self.config_path)?;
+        for warning in &warnings {
+            eprintln!("{} {}", "warning:".yellow(), warning);
+        }
+
+        reapply_env_vars(
+            &self.config.environment_variables,
+            &config.environment_variables,
+        );
+
+        self.vi_mode = ViMode::new(&config);
+        let completer: FluxCompleter = FluxCompleter::new(
+            &config,
+            self.prompt_engine.display(),
+            self.vi_mode.clone(),
+            Arc::clone(&self.expansion),
+            Rc::clone(&self.plugin_manager),
+        );
+        self.editor.set_helper(Some(completer));
+        keymap::configure(
+            &mut self.editor,
+            &config,
+            &self.vi_mode,
+            &self.pending_run,
+            &self.expansion,
+            &mut self.bound_keys,
+        );
+        let _ = self.editor.set_max_history_size(config.history_size);
+        let mut plugin_manager = self.plugin_manager.borrow_mut();
+        plugin_manager.set_reserved(reserved_names(&config));
+        plugin_manager.set_capabilities(&config.plugin_permissions);
+        plugin_manager.set_trust(trust_policy(&config));
+        plugin_manager.reset_crashes();
+        drop(plugin_manager);
+        self.prompt_engine.clear_cache();
+
+        self.config_watcher = match (config.watch_config, self.config_watcher.take()) {
+            (false, _) => None,
+            (true, Some(watcher)) => {
+                watcher.watch(files);
+                Some(watcher)
+            }
+            (true, None) => Some(ConfigWatcher::spawn(files)),
         };
-        path.push("sh.lrr.flux");
-        std::fs::create_dir_all(&path).expect("Failed to create config directory");
-        path.push("config.fl");
-        path
+        self.config = config;
+        Ok(())
     }
 
     /// Runs the main shell loop
@@ -94,23 +203,73 @@ impl Shell {
     /// Continuously reads commands, processes them, and maintains
     /// command history until exit is requested.
     pub fn run(&mut self) {
-        let config_path: PathBuf = Self::get_config_path();
-        let history_path: PathBuf = config_path.parent().unwrap().join("history.txt");
+        let history_path: PathBuf = paths::history_file();
+        // Input to restore after a `run` key binding
+        let mut restore: Option<String> = None;
 
         loop {
-            let formatted_prompt: String = prompt::format_prompt(&self.config);
-            match self.editor.readline(&formatted_prompt) {
+            if self
+                .config_watcher
+                .as_ref()
+                .is_some_and(ConfigWatcher::take_changed)
+            {
+                if let Err(e) = self.reload() {
+                    eprintln!("{} {}", "error:".red(), e);
+                }
+            }
+
+            let formatted_prompt: String = self
+                .prompt_engine
+                .render(&self.config, self.plugin_manager.borrow().prompt_segments());
+            self.vi_mode.reset();
+            let input: Result<String, ReadlineError> = match restore.take() {
+                Some(initial) => self
+                    .editor
+                    .readline_with_initial(&formatted_prompt, (&initial, "")),
+                None => self.editor.readline(&formatted_prompt),
+            };
+            let shown: Option<String> = self.prompt_engine.finish();
+            let pending: Option<(String, String)> = self
+                .pending_run
+                .lock()
+                .ok()
+                .and_then(|mut pending| pending.take());
+            if let Some((command, line)) = pending {
+                // The line was accepted by the binding, not entered
+                commands::execute_command(&command, self);
+                restore = Some(line);
+                continue;
+            }
+            // An abbreviation that ends the line expands when it is entered
+            let input: Result<String, ReadlineError> = input.map(|typed| {
+                let line: String = expand_line(&self.config.abbreviations, &typed)
+                    .unwrap_or_else(|| typed.clone());
+                match (shown, self.config.transient_prompt) {
+                    (Some(shown), true) => {
+                        self.prompt_engine
+                            .collapse(&self.config, &shown, &typed, &line)
+                    }
+                    (Some(shown), false) if line != typed => {
+                        self.prompt_engine.rewrite(&shown, &typed, &line)
+                    }
+                    _ => {}
+                }
+                line
+            });
+            match input {
                 Ok(line) => {
                     let trimmed = line.trim();
                     if !trimmed.is_empty() {
-                        if let Err(e) = self.editor.add_history_entry(trimmed) {
-                            eprintln!("Failed to add history entry: {}", e);
+                        match self.editor.add_history_entry(trimmed) {
+                            Ok(true) => self.plugin_manager.borrow().history_add(trimmed),
+                            Ok(false) => {}
+                            Err(e) => eprintln!("Failed to add history entry: {}", e),
                         }
                         // Save history after each command
                         if let Err(e) = self.editor.save_history(&history_path) {
                             eprintln!("Failed to save history: {}", e);
                         }
-                        commands::execute_command(trimmed, &self);
+                        commands::execute_command(trimmed, self);
                     }
                 }
                 Err(ReadlineError::Interrupted) => {
@@ -132,6 +291,17 @@ impl Shell {
 
 impl Drop for Shell {
     fn drop(&mut self) {
-        self.plugin_manager.cleanup();
+        self.plugin_manager.borrow().exit();
+        self.plugin_manager.borrow_mut().cleanup();
+    }
+}
+
+/// Reads the trusted plugin keys, reporting lines that are not keys
+pub(crate) fn trust_policy(config: &FluxConfig) -> TrustPolicy {
+    let (trust, warnings): (TrustPolicy, Vec<String>) =
+        TrustPolicy::load(&paths::trusted_keys_file(), config.require_signed_plugins);
+    for warning in warnings {
+        eprintln!("{} {}", "warning:".yellow(), warning);
     }
+    trust
 }
@@ -0,0 +1,206 @@
+use super::git::find_work_tree;
+use crate::config::FluxConfig;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+/// Formats a directory for the `{dir}` placeholder
+///
+/// The path is anchored at the repository root (`dir_repo_relative`), the
+/// longest matching path alias (`dir_use_path_aliases`) or the home directory,
+/// in that order. The remaining components are then truncated to
+/// `dir_max_depth` and abbreviated fish-style (`dir_abbreviate`).
+///
+/// # Arguments
+/// * `cwd` - Directory to format
+/// * `config` - Shell configuration containing directory display options
+///
+/// # Returns
+/// * Directory text without colors
+pub(super) fn format_dir(cwd: &Path, config: &FluxConfig) -> String {
+    let (anchor, rest) = split_anchor(cwd, config);
+
+    let mut parts: Vec<String> = rest
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut truncated: bool = false;
+    if config.dir_max_depth > 0 && parts.len() > config.dir_max_depth {
+        parts.drain(..parts.len() - config.dir_max_depth);
+        truncated = true;
+    }
+
+    if config.dir_abbreviate {
+        let last: usize = parts.len().saturating_sub(1);
+        for part in parts.iter_mut().take(last) {
+            *part = abbreviate(part);
+        }
+    }
+
+    if truncated {
+        parts.insert(0, "…".to_string());
+    }
+
+    match anchor {
+        Some(anchor) if parts.is_empty() => anchor,
+        Some(anchor) if anchor.ends_with('/') => format!("{}{}", anchor, parts.join("/")),
+        Some(anchor) => format!("{}/{}", anchor, parts.join("/")),
+        None => parts.join("/"),
+    }
+}
+
+/// Splits a path into its display anchor and the remaining relative path
+///
+/// # Returns
+/// * `(anchor, rest)` - Anchor text such as `~`, `@dl`, `flux` or `/`, and
+///   the path below it
+fn split_anchor(cwd: &Path, config: &FluxConfig) -> (Option<String>, PathBuf) {
+    if config.dir_repo_relative {
+        if let Some(root) = find_work_tree(cwd) {
+            if let (Some(name), Ok(rest)) = (root.file_name(), cwd.strip_prefix(&root)) {
+                return (Some(name.to_string_lossy().to_string()), rest.to_path_buf());
+            }
+        }
+    }
+
+    if config.dir_use_path_aliases {
+        // Path::starts_with compares whole components, so `/mnt/home/user`
+        // never matches a `/home/user` alias
+        let best: Option<(&String, &Path)> = config
+            .path_aliases
+            .iter()
+            .filter(|(_, target)| !target.is_empty())
+            .map(|(alias, target)| (alias, Path::new(target.as_str())))
+            .filter(|(_, target)| cwd.starts_with(target))
+            .max_by_key(|(_, target)| target.components().count());
+
+        if let Some((alias, target)) = best {
+            if let Ok(rest) = cwd.strip_prefix(target) {
+                return (Some(alias.clone()), rest.to_path_buf());
+            }
+        }
+    }
+
+    if let Some(home) = home_dir() {
+        if let Ok(rest) = cwd.strip_prefix(&home) {
+            return (Some("~".to_string()), rest.to_path_buf());
+        }
+    }
+
+    let root: Option<String> = cwd.has_root().then(|| {
+        cwd.components()
+            .take_while(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .to_string()
+    });
+    let rest: PathBuf = cwd
+        .components()
+        .skip_while(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+        .collect();
+    (root, rest)
+}
+
+/// Shortens a directory name to its first character, keeping a leading dot
+fn abbreviate(part: &str) -> String {
+    let mut chars = part.chars();
+    match chars.next() {
+        Some('.') => chars.next().map(|c| format!(".{}", c)).unwrap_or_default(),
+        Some(c) => c.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Gets the home directory, preferring `$HOME`/`$USERPROFILE`
+fn home_dir() -> Option<PathBuf> {
+    env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .ok()
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .or_else(dirs::home_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn config(aliases: &[(&str, &str)]) -> FluxConfig {
+        FluxConfig {
+            dir_use_path_aliases: true,
+            path_aliases: aliases
+                .iter()
+                .map(|(alias, target)| (alias.to_string(), target.to_string()))
+                .collect::<HashMap<String, String>>(),
+            ..FluxConfig::default()
+        }
+    }
+
+    #[test]
+    fn anchors_at_the_longest_alias() {
+        let config: FluxConfig =
+            config(&[("@w", "/srv/work"), ("@p", "/srv/work/proj"), ("@none", "")]);
+        assert_eq!(
+            split_anchor(Path::new("/srv/work/proj/src"), &config),
+            (Some("@p".to_string()), PathBuf::from("src"))
+        );
+        assert_eq!(
+            split_anchor(Path::new("/srv/work/other"), &config),
+            (Some("@w".to_string()), PathBuf::from("other"))
+        );
+    }
+
+    #[test]
+    fn matches_aliases_on_whole_components() {
+        let config: FluxConfig = config(&[("@w", "/srv/work")]);
+        assert_eq!(
+            split_anchor(Path::new("/srv/workshop"), &config),
+            (Some("/".to_string()), PathBuf::from("srv/workshop"))
+        );
+    }
+
+    #[test]
+    fn ignores_aliases_when_disabled() {
+        let config: FluxConfig = FluxConfig {
+            dir_use_path_aliases: false,
+            ..config(&[("@w", "/srv/work")])
+        };
+        assert_eq!(
+            split_anchor(Path::new("/srv/work/a"), &config),
+            (Some("/".to_string()), PathBuf::from("srv/work/a"))
+        );
+    }
+
+    #[test]
+    fn anchors_at_home() {
+        let Some(home) = home_dir() else {
+            return;
+        };
+        assert_eq!(
+            split_anchor(&home.join("a/b"), &config(&[])),
+            (Some("~".to_string()), PathBuf::from("a/b"))
+        );
+    }
+
+    #[test]
+    fn anchors_at_the_repository_root() {
+        let repo: PathBuf = env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join("src/shell")).unwrap();
+        let config: FluxConfig = FluxConfig {
+            dir_repo_relative: true,
+            ..config(&[])
+        };
+
+        let name: String = repo.file_name().unwrap().to_string_lossy().to_string();
+        let anchored: (Option<String>, PathBuf) = split_anchor(&repo.join("src/shell"), &config);
+        fs::remove_dir_all(&repo).unwrap();
+        assert_eq!(anchored, (Some(name), PathBuf::from("src/shell")));
+    }
+}
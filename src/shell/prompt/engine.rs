@@ -0,0 +1,482 @@
+use super::git::GitStatus;
+use super::{format_prompt, render_main, used_commands, uses_git};
+use crate::config::FluxConfig;
+use crate::plugin::PromptSegments;
+use crate::utils::process::output_with_timeout;
+use crate::utils::terminal::KeyWatch;
+use console::Term;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
+
+/// Maximum number of directories kept in the slow segment cache
+const CACHE_CAPACITY: usize = 128;
+
+/// How often a drawn prompt checks whether its slow segments have finished
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Last output of each command segment, keyed by segment name and directory
+type CommandCache = HashMap<(String, PathBuf), (Instant, String)>;
+
+/// Values for segments that aren't computed while formatting the prompt
+///
+/// Slow segments (git, command segments, process plugins) come from
+/// background threads; other plugin segments are collected on the shell
+/// thread before rendering.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SegmentValues {
+    /// Git status, or None outside a repository
+    pub git: Option<GitStatus>,
+    /// Trimmed output of command segments, by segment name
+    pub commands: HashMap<String, String>,
+    /// Values provided by plugins, by placeholder name
+    pub plugins: HashMap<String, String>,
+}
+
+impl SegmentValues {
+    /// Computes every slow segment the prompt template uses
+    ///
+    /// A segment that times out keeps its previous value so a slow repository
+    /// shows stale information rather than none at all.
+    ///
+    /// Command segments are only rerun once their `ttl_secs` has expired.
+    ///
+    /// # Arguments
+    /// * `config` - Shell configuration
+    /// * `cwd` - Directory the prompt is rendered for
+    /// * `previous` - Cached results for the same directory, if any
+    /// * `command_cache` - Last output of each command segment
+    pub(super) fn collect(
+        config: &FluxConfig,
+        cwd: &Path,
+        previous: Option<&SegmentValues>,
+        command_cache: &Mutex<CommandCache>,
+    ) -> Self {
+        let timeout: Duration = Duration::from_millis(config.prompt_timeout_ms);
+        let mut segments: SegmentValues = SegmentValues::default();
+
+        if uses_git(config) {
+            segments.git = match GitStatus::collect(cwd, timeout) {
+                Ok(status) => status,
+                Err(_) => previous.and_then(|p| p.git.clone()),
+            };
+        }
+
+        for (name, command, ttl) in used_commands(config) {
+            let key: (String, PathBuf) = (name.to_string(), cwd.to_path_buf());
+            let fresh: Option<String> = command_cache.lock().ok().and_then(|cache| {
+                cache
+                    .get(&key)
+                    .filter(|(ran_at, _)| ran_at.elapsed() < ttl)
+                    .map(|(_, value)| value.clone())
+            });
+
+            let value: String = match fresh {
+                Some(value) => value,
+                None => match run_segment_command(command, cwd, timeout) {
+                    Some(value) => {
+                        if let Ok(mut cache) = command_cache.lock() {
+                            cache.insert(key, (Instant::now(), value.clone()));
+                        }
+                        value
+                    }
+                    None => previous
+                        .and_then(|p| p.commands.get(name).cloned())
+                        .unwrap_or_default(),
+                },
+            };
+            segments.commands.insert(name.to_string(), value);
+        }
+
+        segments
+    }
+}
+
+/// State shared between the shell loop, background workers and the line editor
+#[derive(Default)]
+struct SharedState {
+    /// Incremented for every prompt so stale workers can detect they are late
+    generation: u64,
+    /// Results delivered by a worker before the shell stopped waiting
+    result: Option<SegmentValues>,
+    /// The prompt handed to the line editor once the shell stopped waiting
+    drawn: Option<String>,
+    /// Right-side prompt for the current input line
+    right: Option<String>,
+}
+
+/// Handle used by the line editor to display the most recent prompt
+#[derive(Clone)]
+pub(crate) struct PromptDisplay {
+    shared: Arc<(Mutex<SharedState>, Condvar)>,
+}
+
+impl PromptDisplay {
+    /// Returns the right-side prompt and the width of the left prompt's last line
+    pub(crate) fn right(&self) -> Option<(String, usize)> {
+        let state: MutexGuard<SharedState> = self.shared.0.lock().ok()?;
+        let right: String = state.right.clone()?;
+        let (_, left_width) = prompt_shape(&state.drawn.clone().unwrap_or_default());
+        Some((right, left_width))
+    }
+}
+
+/// Renders the prompt without blocking on slow segments
+///
+/// Fast segments (user, host, directory, time) render immediately. Slow
+/// segments (git) run in a background thread; the engine waits a short time
+/// for them, then draws the prompt with the last results cached for the
+/// directory. Once the worker finishes, the prompt is drawn again with its
+/// results, whatever their width, and handed to the line editor; a key
+/// pressed first hands over the cached prompt at once, and the results show
+/// on the next prompt.
+pub(crate) struct PromptEngine {
+    shared: Arc<(Mutex<SharedState>, Condvar)>,
+    cache: Arc<Mutex<HashMap<PathBuf, SegmentValues>>>,
+    command_cache: Arc<Mutex<CommandCache>>,
+}
+
+impl PromptEngine {
+    /// Creates a new prompt engine
+    pub(crate) fn new() -> Self {
+        PromptEngine {
+            shared: Arc::new((Mutex::new(SharedState::default()), Condvar::new())),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            command_cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns a handle for the line editor's prompt highlighter
+    pub(crate) fn display(&self) -> PromptDisplay {
+        PromptDisplay {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Renders the prompt for the next line of input
+    ///
+    /// The right-side prompt is rendered alongside and handed to the line
+    /// editor through `PromptDisplay`.
+    ///
+    /// # Arguments
+    /// * `config` - Shell configuration containing prompt settings
+    /// * `plugins` - Placeholder values provided by plugins, and the process
+    ///   plugins to ask for theirs
+    ///
+    /// # Returns
+    /// * Prompt to pass to the line editor
+    pub(crate) fn render(&self, config: &FluxConfig, plugins: PromptSegments) -> String {
+        let cwd: PathBuf = std::env::current_dir().unwrap_or_default();
+        let cached: Option<SegmentValues> = self.cached(&cwd);
+
+        let (lock, condvar) = &*self.shared;
+        let generation: u64 = {
+            let mut state: MutexGuard<SharedState> = lock.lock().unwrap();
+            state.generation += 1;
+            state.result = None;
+            state.drawn = None;
+            state.right = None;
+            state.generation
+        };
+
+        let slow: bool =
+            uses_git(config) || !used_commands(config).is_empty() || plugins.has_queries();
+        let mut segments: SegmentValues = if slow {
+            self.spawn_worker(
+                config.clone(),
+                cwd,
+                cached.clone(),
+                plugins.clone(),
+                generation,
+            );
+
+            let wait: Duration = Duration::from_millis(config.prompt_wait_ms);
+            let mut state: MutexGuard<SharedState> = condvar
+                .wait_timeout_while(lock.lock().unwrap(), wait, |s| s.result.is_none())
+                .unwrap()
+                .0;
+            match state.result.take() {
+                Some(segments) => segments,
+                None => {
+                    drop(state);
+                    let mut cached: SegmentValues = cached.unwrap_or_default();
+                    cached.plugins.extend(plugins.values.clone());
+                    let timeout: Duration = Duration::from_millis(config.prompt_timeout_ms);
+                    self.wait_while_drawn(&render_main(config, &cached), timeout)
+                        .unwrap_or(cached)
+                }
+            }
+        } else {
+            SegmentValues::default()
+        };
+        segments.plugins.extend(plugins.values);
+
+        let prompt: String = render_main(config, &segments);
+        let mut state: MutexGuard<SharedState> = lock.lock().unwrap();
+        state.drawn = Some(prompt.clone());
+        state.right = render_right(config, &segments);
+        prompt
+    }
+
+    /// Marks the current prompt as finished so late workers don't redraw it
+    ///
+    /// # Returns
+    /// * The prompt as it was last displayed
+    pub(crate) fn finish(&self) -> Option<String> {
+        let mut state: MutexGuard<SharedState> = self.shared.0.lock().ok()?;
+        state.generation += 1;
+        state.right = None;
+        state.drawn.take()
+    }
+
+    /// Replaces the submitted prompt in scrollback with the transient prompt
+    ///
+    /// Must be called right after the line editor returns, while the cursor
+    /// is on the line below the submitted input.
+    ///
+    /// # Arguments
+    /// * `config` - Shell configuration containing the transient template
+    /// * `shown` - The full prompt that was on screen
+    /// * `typed` - The input as it was on screen
+    /// * `line` - The submitted input, after abbreviations were expanded
+    pub(crate) fn collapse(&self, config: &FluxConfig, shown: &str, typed: &str, line: &str) {
+        let cwd: PathBuf = std::env::current_dir().unwrap_or_default();
+        let segments: SegmentValues = self.cached(&cwd).unwrap_or_default();
+        let transient: String = format_prompt(&config.transient_prompt_template, config, &segments);
+        redraw(
+            &format!("{}{}", shown, typed),
+            &format!("{}{}", transient, line),
+        );
+    }
+
+    /// Redraws the submitted input in scrollback after it was changed on
+    /// submission, such as by an abbreviation
+    ///
+    /// Must be called right after the line editor returns, while the cursor
+    /// is on the line below the submitted input.
+    ///
+    /// # Arguments
+    /// * `shown` - The full prompt that was on screen
+    /// * `typed` - The input as it was on screen
+    /// * `line` - The input to show instead
+    pub(crate) fn rewrite(&self, shown: &str, typed: &str, line: &str) {
+        redraw(
+            &format!("{}{}", shown, typed),
+            &format!("{}{}", shown, line),
+        );
+    }
+
+    /// Drops all cached segment values so the next prompt recomputes them
+    pub(crate) fn clear_cache(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.clear();
+        }
+        if let Ok(mut cache) = self.command_cache.lock() {
+            cache.clear();
+        }
+    }
+
+    /// Returns the cached slow segments for a directory
+    fn cached(&self, cwd: &PathBuf) -> Option<SegmentValues> {
+        self.cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(cwd).cloned())
+    }
+
+    /// Shows a prompt built from cached values until the slow segments
+    /// finish or a key is pressed, then clears it for the line editor
+    ///
+    /// # Arguments
+    /// * `cached` - The prompt with the last results cached for the directory
+    /// * `timeout` - Longest time to keep the terminal from the line editor
+    ///
+    /// # Returns
+    /// * `Option<SegmentValues>` - The finished segments, or None if a key
+    ///   was pressed first, they took too long, or stdin is not a terminal
+    fn wait_while_drawn(&self, cached: &str, timeout: Duration) -> Option<SegmentValues> {
+        let watch: KeyWatch = KeyWatch::start()?;
+        let term: Term = Term::stdout();
+        let _ = term.write_str(cached);
+
+        let deadline: Instant = Instant::now() + timeout;
+        let finished: Option<SegmentValues> = loop {
+            let result: Option<SegmentValues> = self
+                .shared
+                .0
+                .lock()
+                .ok()
+                .and_then(|mut state| state.result.take());
+            if result.is_some() {
+                break result;
+            }
+            if Instant::now() >= deadline || watch.key_pressed(POLL_INTERVAL) {
+                break None;
+            }
+        };
+        drop(watch);
+        clear(&term, cached);
+        finished
+    }
+
+    /// Computes slow segments in a background thread
+    fn spawn_worker(
+        &self,
+        config: FluxConfig,
+        cwd: PathBuf,
+        cached: Option<SegmentValues>,
+        plugins: PromptSegments,
+        generation: u64,
+    ) {
+        let shared = Arc::clone(&self.shared);
+        let cache = Arc::clone(&self.cache);
+        let command_cache = Arc::clone(&self.command_cache);
+
+        thread::spawn(move || {
+            let mut segments: SegmentValues =
+                SegmentValues::collect(&config, &cwd, cached.as_ref(), &command_cache);
+            segments.plugins = plugins.query();
+
+            if let Ok(mut cache) = cache.lock() {
+                if cache.len() >= CACHE_CAPACITY && !cache.contains_key(&cwd) {
+                    cache.clear();
+                }
+                cache.insert(cwd, segments.clone());
+            }
+
+            // Once the prompt is with the line editor, the results are only
+            // cached for the next one
+            let (lock, condvar) = &*shared;
+            let Ok(mut state) = lock.lock() else {
+                return;
+            };
+            if state.generation == generation && state.drawn.is_none() {
+                state.result = Some(segments);
+                condvar.notify_all();
+            }
+        });
+    }
+}
+
+/// Runs a command segment through the system shell
+///
+/// # Arguments
+/// * `command` - Command line to run
+/// * `cwd` - Directory the prompt is rendered for, which the command runs in
+/// * `timeout` - Maximum time the command may take
+///
+/// # Returns
+/// * `Option<String>` - Trimmed stdout (empty on failure), or None on timeout
+fn run_segment_command(command: &str, cwd: &Path, timeout: Duration) -> Option<String> {
+    match output_with_timeout(
+        Command::new("sh").current_dir(cwd).args(["-c", command]),
+        timeout,
+    ) {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(_) => Some(String::new()),
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => None,
+        Err(_) => Some(String::new()),
+    }
+}
+
+/// Renders the right-side prompt, if one is configured
+fn render_right(config: &FluxConfig, segments: &SegmentValues) -> Option<String> {
+    if config.right_prompt_template.is_empty() {
+        return None;
+    }
+    Some(format_prompt(
+        &config.right_prompt_template,
+        config,
+        segments,
+    ))
+}
+
+/// Erases a prompt the engine drew, leaving the cursor where it started
+fn clear(term: &Term, drawn: &str) {
+    let Some((_, cols)) = term.size_checked() else {
+        return;
+    };
+    let up: usize = rows_used(drawn, cols as usize) - 1;
+    let _ = match up {
+        0 => term.write_str("\r\x1b[J"),
+        up => term.write_str(&format!("\x1b[{}A\r\x1b[J", up)),
+    };
+}
+
+/// Counts the terminal rows taken by text that ended with the cursor on a new line
+///
+/// # Arguments
+/// * `text` - Prompt and input as displayed
+/// * `cols` - Terminal width
+fn rows_used(text: &str, cols: usize) -> usize {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let last: usize = lines.len() - 1;
+
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let width: usize = display_width(line);
+            if i == last {
+                // The editor starts a fresh row when input ends exactly at the edge
+                width / cols + 1
+            } else {
+                width.div_ceil(cols).max(1)
+            }
+        })
+        .sum()
+}
+
+/// Returns the number of lines and the display width of the last line
+///
+/// # Arguments
+/// * `prompt` - Prompt text, possibly containing ANSI escape sequences
+pub(crate) fn prompt_shape(prompt: &str) -> (usize, usize) {
+    let lines: Vec<&str> = prompt.split('\n').collect();
+    let last: &str = lines.last().copied().unwrap_or_default();
+    (lines.len(), display_width(last))
+}
+
+/// Computes the visible width of text, ignoring ANSI escape sequences
+///
+/// # Arguments
+/// * `text` - Text to measure
+pub(crate) fn display_width(text: &str) -> usize {
+    let mut visible: String = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            visible.push(c);
+        }
+    }
+
+    visible.width()
+}
+
+/// Replaces text drawn just above the cursor with other text
+///
+/// # Arguments
+/// * `drawn` - Prompt and input as they were drawn
+/// * `text` - Text to draw in their place
+fn redraw(drawn: &str, text: &str) {
+    let term: Term = Term::stdout();
+    let Some((_, cols)) = term.size_checked() else {
+        return;
+    };
+    let rows: usize = rows_used(drawn, cols as usize);
+    let _ = term.write_str(&format!("\x1b[{}A\r\x1b[J{}\n", rows, text));
+}
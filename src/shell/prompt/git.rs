@@ -0,0 +1,429 @@
+use crate::utils::process::output_with_timeout;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
+
+/// What `HEAD` currently points at
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum GitHead {
+    /// A checked-out branch
+    Branch(String),
+    /// A detached HEAD, with the short SHA and an exactly matching tag if any
+    Detached { sha: String, tag: Option<String> },
+}
+
+/// Snapshot of the working tree state used by the git prompt segments
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GitStatus {
+    /// Branch name or detached commit
+    pub head: GitHead,
+    /// Number of files with staged changes
+    pub staged: usize,
+    /// Number of files with unstaged changes (including conflicts)
+    pub unstaged: usize,
+    /// Number of untracked files
+    pub untracked: usize,
+    /// Commits ahead of the upstream branch
+    pub ahead: usize,
+    /// Commits behind the upstream branch
+    pub behind: usize,
+    /// Number of stash entries
+    pub stash: usize,
+    /// In-progress operation such as `REBASE 2/5` or `MERGING`
+    pub operation: Option<String>,
+}
+
+impl GitStatus {
+    /// Collects the git status for a directory
+    ///
+    /// Runs `git status --porcelain=v2 --branch --show-stash` once and reads
+    /// in-progress operation markers straight from the git directory. Git
+    /// older than 2.35 lacks `--show-stash`, so there the stash is counted
+    /// separately.
+    ///
+    /// # Arguments
+    /// * `cwd` - Directory to collect the status for
+    /// * `timeout` - Maximum time each git invocation may take
+    ///
+    /// # Returns
+    /// * `io::Result<Option<GitStatus>>` - Status if inside a git work tree,
+    ///   None otherwise, or an error if git timed out
+    pub(crate) fn collect(cwd: &Path, timeout: Duration) -> io::Result<Option<Self>> {
+        let mut args: Vec<&str> = vec![
+            "--no-optional-locks",
+            "status",
+            "--porcelain=v2",
+            "--branch",
+            "--show-stash",
+        ];
+        let mut output: Output = match run_git(cwd, &args, timeout)? {
+            Some(output) => output,
+            None => return Ok(None),
+        };
+        // Git exits with 129 for an unknown option
+        let show_stash: bool = output.status.code() != Some(129);
+        if !show_stash {
+            args.pop();
+            output = match run_git(cwd, &args, timeout)? {
+                Some(output) => output,
+                None => return Ok(None),
+            };
+        }
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        let mut status: GitStatus = Self::parse_porcelain(&stdout);
+        if !show_stash {
+            status.stash = count_stash(cwd, timeout);
+        }
+
+        if let GitHead::Detached { tag, .. } = &mut status.head {
+            *tag = get_exact_tag(cwd, timeout);
+        }
+
+        status.operation = find_git_dir(cwd).and_then(|git_dir| detect_operation(&git_dir));
+
+        Ok(Some(status))
+    }
+
+    /// Parses the output of `git status --porcelain=v2 --branch --show-stash`
+    ///
+    /// # Arguments
+    /// * `porcelain` - Raw command output
+    ///
+    /// # Returns
+    /// * Parsed status without tag or operation information
+    fn parse_porcelain(porcelain: &str) -> Self {
+        let mut oid: String = String::new();
+        let mut branch: Option<String> = None;
+        let mut status: GitStatus = GitStatus {
+            head: GitHead::Branch(String::new()),
+            staged: 0,
+            unstaged: 0,
+            untracked: 0,
+            ahead: 0,
+            behind: 0,
+            stash: 0,
+            operation: None,
+        };
+
+        for line in porcelain.lines() {
+            if let Some(header) = line.strip_prefix("# ") {
+                let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+                match key {
+                    "branch.oid" => oid = value.to_string(),
+                    "branch.head" if value != "(detached)" => branch = Some(value.to_string()),
+                    "branch.ab" => {
+                        for part in value.split_whitespace() {
+                            if let Some(n) = part.strip_prefix('+') {
+                                status.ahead = n.parse().unwrap_or(0);
+                            } else if let Some(n) = part.strip_prefix('-') {
+                                status.behind = n.parse().unwrap_or(0);
+                            }
+                        }
+                    }
+                    "stash" => status.stash = value.parse().unwrap_or(0),
+                    _ => {}
+                }
+                continue;
+            }
+
+            let mut fields = line.split(' ');
+            match fields.next() {
+                Some("1") | Some("2") => {
+                    let xy: Vec<char> = fields.next().unwrap_or("..").chars().collect();
+                    if xy.first().is_some_and(|c| *c != '.') {
+                        status.staged += 1;
+                    }
+                    if xy.get(1).is_some_and(|c| *c != '.') {
+                        status.unstaged += 1;
+                    }
+                }
+                Some("u") => status.unstaged += 1,
+                Some("?") => status.untracked += 1,
+                _ => {}
+            }
+        }
+
+        status.head = match branch {
+            Some(name) => GitHead::Branch(name),
+            None => GitHead::Detached {
+                sha: oid.chars().take(7).collect(),
+                tag: None,
+            },
+        };
+
+        status
+    }
+
+    /// Returns the text shown for `HEAD`: the branch, tag or short SHA
+    pub(crate) fn head_label(&self) -> String {
+        match &self.head {
+            GitHead::Branch(name) => name.clone(),
+            GitHead::Detached { tag: Some(tag), .. } => tag.clone(),
+            GitHead::Detached { sha, .. } => sha.clone(),
+        }
+    }
+
+    /// Whether `HEAD` is detached from any branch
+    pub(crate) fn is_detached(&self) -> bool {
+        matches!(self.head, GitHead::Detached { .. })
+    }
+}
+
+/// Runs git in a directory
+///
+/// # Returns
+/// * `io::Result<Option<Output>>` - The output, None if git could not be
+///   run, or an error if it timed out
+fn run_git(cwd: &Path, args: &[&str], timeout: Duration) -> io::Result<Option<Output>> {
+    match output_with_timeout(Command::new("git").current_dir(cwd).args(args), timeout) {
+        Ok(output) => Ok(Some(output)),
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(e),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Counts stash entries from the reflog of `refs/stash`; 0 if there is none
+fn count_stash(cwd: &Path, timeout: Duration) -> usize {
+    let output: Option<Output> = output_with_timeout(
+        Command::new("git").current_dir(cwd).args([
+            "rev-list",
+            "--walk-reflogs",
+            "--count",
+            "refs/stash",
+        ]),
+        timeout,
+    )
+    .ok();
+
+    match output {
+        Some(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Looks up a tag pointing exactly at `HEAD`
+fn get_exact_tag(cwd: &Path, timeout: Duration) -> Option<String> {
+    let output: Output = output_with_timeout(
+        Command::new("git")
+            .current_dir(cwd)
+            .args(["describe", "--tags", "--exact-match", "HEAD"]),
+        timeout,
+    )
+    .ok()?;
+
+    if output.status.success() {
+        String::from_utf8(output.stdout)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    } else {
+        None
+    }
+}
+
+/// Finds the git directory for a path, following `.git` files used by worktrees
+///
+/// # Arguments
+/// * `start` - Directory to search upwards from
+///
+/// # Returns
+/// * `Option<PathBuf>` - Path to the git directory if found
+pub(crate) fn find_git_dir(start: &Path) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let dot_git: PathBuf = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        if dot_git.is_file() {
+            let contents: String = fs::read_to_string(&dot_git).ok()?;
+            let target: &str = contents.trim().strip_prefix("gitdir:")?.trim();
+            return Some(dir.join(target));
+        }
+    }
+    None
+}
+
+/// Finds the root of the work tree containing a path
+///
+/// # Arguments
+/// * `start` - Directory to search upwards from
+///
+/// # Returns
+/// * `Option<PathBuf>` - Directory containing `.git` if found
+pub(crate) fn find_work_tree(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Detects an in-progress rebase, merge, cherry-pick, revert or bisect
+///
+/// Uses the same marker files as git's own `git-prompt.sh`.
+///
+/// # Arguments
+/// * `git_dir` - Path to the repository's git directory
+///
+/// # Returns
+/// * `Option<String>` - Label for the operation, with progress when known
+fn detect_operation(git_dir: &Path) -> Option<String> {
+    let rebase_merge: PathBuf = git_dir.join("rebase-merge");
+    if rebase_merge.is_dir() {
+        let label: &str = if rebase_merge.join("interactive").exists() {
+            "REBASE-i"
+        } else {
+            "REBASE-m"
+        };
+        return Some(with_progress(label, &rebase_merge, "msgnum", "end"));
+    }
+
+    let rebase_apply: PathBuf = git_dir.join("rebase-apply");
+    if rebase_apply.is_dir() {
+        let label: &str = if rebase_apply.join("rebasing").exists() {
+            "REBASE"
+        } else if rebase_apply.join("applying").exists() {
+            "AM"
+        } else {
+            "AM/REBASE"
+        };
+        return Some(with_progress(label, &rebase_apply, "next", "last"));
+    }
+
+    let markers: [(&str, &str); 4] = [
+        ("MERGE_HEAD", "MERGING"),
+        ("CHERRY_PICK_HEAD", "CHERRY-PICKING"),
+        ("REVERT_HEAD", "REVERTING"),
+        ("BISECT_LOG", "BISECTING"),
+    ];
+    markers
+        .iter()
+        .find(|(file, _)| git_dir.join(file).exists())
+        .map(|(_, label)| label.to_string())
+}
+
+/// Appends `step/total` progress read from a rebase state directory
+fn with_progress(label: &str, state_dir: &Path, step_file: &str, total_file: &str) -> String {
+    let read_number = |file: &str| -> Option<usize> {
+        fs::read_to_string(state_dir.join(file))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+
+    match (read_number(step_file), read_number(total_file)) {
+        (Some(step), Some(total)) => format!("{} {}/{}", label, step, total),
+        _ => label.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn parses_a_branch_with_changes() {
+        let porcelain: &str = "\
+# branch.oid 1234567890abcdef1234567890abcdef12345678
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +2 -3
+# stash 4
+1 M. N... 100644 100644 100644 a b src/staged.rs
+1 .M N... 100644 100644 100644 a b src/unstaged.rs
+1 MM N... 100644 100644 100644 a b src/both.rs
+2 R. N... 100644 100644 100644 a b R100 new.rs\told.rs
+u UU N... 100644 100644 100644 100644 a b c conflict.rs
+? untracked.rs
+? other.rs
+! ignored.rs
+";
+        assert_eq!(
+            GitStatus::parse_porcelain(porcelain),
+            GitStatus {
+                head: GitHead::Branch("main".to_string()),
+                staged: 3,
+                unstaged: 3,
+                untracked: 2,
+                ahead: 2,
+                behind: 3,
+                stash: 4,
+                operation: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_a_detached_head() {
+        let porcelain: &str = "\
+# branch.oid 1234567890abcdef1234567890abcdef12345678
+# branch.head (detached)
+";
+        let status: GitStatus = GitStatus::parse_porcelain(porcelain);
+        assert_eq!(
+            status.head,
+            GitHead::Detached {
+                sha: "1234567".to_string(),
+                tag: None,
+            }
+        );
+        assert_eq!(
+            (status.staged, status.unstaged, status.untracked),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn parses_a_branch_without_commits_or_upstream() {
+        let porcelain: &str = "# branch.oid (initial)\n# branch.head dev\n? a\n";
+        let status: GitStatus = GitStatus::parse_porcelain(porcelain);
+        assert_eq!(status.head, GitHead::Branch("dev".to_string()));
+        assert_eq!((status.ahead, status.behind, status.stash), (0, 0, 0));
+        assert_eq!(status.untracked, 1);
+    }
+
+    #[test]
+    fn counts_stash_entries_from_the_reflog() {
+        let repo: PathBuf = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status: std::process::ExitStatus = Command::new("git")
+                .current_dir(&repo)
+                .args(["-c", "user.name=flux", "-c", "user.email=flux@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        let timeout: Duration = Duration::from_secs(10);
+
+        git(&["init", "--quiet"]);
+        fs::write(repo.join("notes.txt"), "one").unwrap();
+        git(&["add", "notes.txt"]);
+        git(&["commit", "--quiet", "-m", "notes"]);
+        assert_eq!(count_stash(&repo, timeout), 0);
+
+        for text in ["two", "three"] {
+            fs::write(repo.join("notes.txt"), text).unwrap();
+            git(&["stash", "--quiet"]);
+        }
+        assert_eq!(count_stash(&repo, timeout), 2);
+        assert_eq!(
+            GitStatus::collect(&repo, timeout).unwrap().unwrap().stash,
+            2
+        );
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+mod dir;
+mod engine;
+mod git;
+mod powerline;
+
+use super::keymap::ViMode;
+use crate::config::{paint, FluxConfig, Theme};
+use chrono::Local;
+use engine::SegmentValues;
+pub(crate) use engine::{display_width, PromptDisplay, PromptEngine};
+use git::GitStatus;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Every git placeholder, cleared when the directory is not a repository
+const GIT_PLACEHOLDERS: [&str; 9] = [
+    "{git_status}",
+    "{git_staged}",
+    "{git_unstaged}",
+    "{git_untracked}",
+    "{git_ahead}",
+    "{git_behind}",
+    "{git_stash}",
+    "{git_state}",
+    "{git}",
+];
+
+/// Formats a prompt template according to the configuration
+///
+/// Used for the main, right-side and transient prompts alike.
+/// Replaces placeholders in the template with actual values:
+/// - {user}: Current username
+/// - {host}: System hostname
+/// - {dir}: Current directory
+/// - {git}: Git branch, tag or short SHA (if applicable)
+/// - {git_status}: Staged/unstaged/untracked counts, ahead/behind, stash and
+///   in-progress operation (see `replace_git_placeholders`)
+/// - {time}: Current time
+/// - {mode}: Vi input mode indicator (empty in emacs mode)
+/// - {name}: User-defined segments from `prompt_segments` and plugin segments
+///
+/// # Arguments
+/// * `template` - Prompt template containing placeholders
+/// * `config` - Shell configuration containing prompt settings
+/// * `values` - Slow and plugin segment values gathered by the prompt engine
+///
+/// # Returns
+/// * Formatted prompt string with colors and replacements
+fn format_prompt(template: &str, config: &FluxConfig, values: &SegmentValues) -> String {
+    let mut prompt: String = template.to_string();
+
+    // Username with separator
+    if config.show_username {
+        let username: String = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "user".to_string());
+        let formatted_username: String = paint(&username, &config.theme.username_color);
+        prompt = prompt.replace("{user}", &formatted_username);
+    }
+
+    // Hostname with separator
+    if config.show_hostname {
+        let hostname: String = hostname::get()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let formatted_hostname: String = paint(&hostname, &config.theme.hostname_color);
+        prompt = prompt.replace("{host}", &formatted_hostname);
+    }
+
+    // Current directory
+    let current_dir: String = dir::format_dir(&env::current_dir().unwrap_or_default(), config);
+
+    let formatted_dir: String = paint(&current_dir, &config.theme.directory_color);
+    prompt = prompt.replace("{dir}", &formatted_dir);
+
+    // Git branch and status
+    if config.show_git_branch {
+        prompt = replace_git_placeholders(prompt, values.git.as_ref(), config);
+    }
+
+    // Time
+    if config.show_time {
+        let time: String = Local::now().format(&config.time_format).to_string();
+        let formatted_time: String = paint(&time, &config.theme.time_color);
+        prompt = prompt.replace("{time}", &formatted_time);
+    }
+
+    // Vi mode indicator, kept up to date while typing by the completer
+    if prompt.contains("{mode}") {
+        let indicator: String = paint(&ViMode::placeholder(config), &config.theme.accent_color);
+        prompt = prompt.replace("{mode}", &indicator);
+    }
+
+    // User-defined segments
+    for (name, segment) in &config.prompt_segments {
+        let placeholder: String = format!("{{{}}}", name);
+        if !prompt.contains(&placeholder) {
+            continue;
+        }
+        let value: String = match (&segment.env, &segment.command) {
+            (Some(var), _) => env::var(var).unwrap_or_default(),
+            (None, Some(_)) => values.commands.get(name).cloned().unwrap_or_default(),
+            (None, None) => String::new(),
+        };
+        let formatted: String = paint(&value, &segment.color);
+        prompt = prompt.replace(&placeholder, &formatted);
+    }
+
+    // Plugin-provided segments
+    for (name, value) in &values.plugins {
+        prompt = prompt.replace(&format!("{{{}}}", name), value);
+    }
+
+    // Add final prompt symbol with accent color
+    prompt = prompt.replace("λ", &paint("λ", &config.theme.accent_color));
+
+    prompt
+}
+
+/// Renders the main prompt in the configured `prompt_mode`
+///
+/// # Arguments
+/// * `config` - Shell configuration containing prompt settings
+/// * `values` - Slow and plugin segment values gathered by the prompt engine
+fn render_main(config: &FluxConfig, values: &SegmentValues) -> String {
+    if config.prompt_mode == "powerline" {
+        powerline::render(config, values)
+    } else {
+        format_prompt(&config.prompt_template, config, values)
+    }
+}
+
+/// Renders the prompt once per theme, for `flux theme preview`
+///
+/// Slow segments are collected once for the current directory and shared by
+/// every theme. Plugin segments are left empty.
+///
+/// # Arguments
+/// * `config` - Shell configuration the themes are applied to
+/// * `themes` - Themes to render
+///
+/// # Returns
+/// * The rendered prompt for each theme, in order
+pub(crate) fn preview_themes(config: &FluxConfig, themes: &[Theme]) -> Vec<String> {
+    let cwd: PathBuf = env::current_dir().unwrap_or_default();
+    let values: SegmentValues = SegmentValues::collect(config, &cwd, None, &Mutex::default());
+
+    themes
+        .iter()
+        .map(|theme| {
+            let mut themed: FluxConfig = config.clone();
+            themed.theme = theme.clone();
+            render_main(&themed, &values)
+        })
+        .collect()
+}
+
+/// Whether any prompt template contains the given text
+fn templates_contain(config: &FluxConfig, needle: &str) -> bool {
+    let main: String = if config.prompt_mode == "powerline" {
+        powerline::template(config)
+    } else {
+        config.prompt_template.clone()
+    };
+    [
+        &main,
+        &config.right_prompt_template,
+        &config.transient_prompt_template,
+    ]
+    .iter()
+    .any(|template| template.contains(needle))
+}
+
+/// Whether any prompt template needs the (slow) git status
+fn uses_git(config: &FluxConfig) -> bool {
+    config.show_git_branch && templates_contain(config, "{git")
+}
+
+/// Command segments used by any prompt template
+///
+/// # Returns
+/// * `(name, command, ttl)` for each command segment
+fn used_commands(config: &FluxConfig) -> Vec<(&str, &str, Duration)> {
+    config
+        .prompt_segments
+        .iter()
+        .filter(|(_, segment)| segment.env.is_none())
+        .filter(|(name, _)| templates_contain(config, &format!("{{{}}}", name)))
+        .filter_map(|(name, segment)| {
+            let command: &str = segment.command.as_deref()?;
+            Some((
+                name.as_str(),
+                command,
+                Duration::from_secs(segment.ttl_secs),
+            ))
+        })
+        .collect()
+}
+
+/// Replaces all git placeholders in the prompt
+///
+/// `{git}` shows the branch, or the tag/short SHA on a detached HEAD.
+/// `{git_status}` combines every non-empty status part, prefixed with a space.
+/// Each part is also available on its own: `{git_staged}`, `{git_unstaged}`,
+/// `{git_untracked}`, `{git_ahead}`, `{git_behind}`, `{git_stash}` and `{git_state}`.
+///
+/// # Arguments
+/// * `prompt` - Prompt with placeholders
+/// * `status` - Git status, or None outside a repository
+/// * `config` - Shell configuration containing theme colors
+///
+/// # Returns
+/// * Prompt with git placeholders replaced
+fn replace_git_placeholders(
+    mut prompt: String,
+    status: Option<&GitStatus>,
+    config: &FluxConfig,
+) -> String {
+    let theme: &Theme = &config.theme;
+    let Some(status) = status else {
+        prompt = prompt.replace(" on {git}", "");
+        for placeholder in GIT_PLACEHOLDERS {
+            prompt = prompt.replace(placeholder, "");
+        }
+        return prompt;
+    };
+
+    let head_color: &str = if status.is_detached() {
+        theme.git_detached_color.as_str()
+    } else {
+        theme.git_branch_color.as_str()
+    };
+    let head: String = paint(&status.head_label(), head_color);
+
+    let count = |symbol: &str, value: usize, color: &str| -> String {
+        if value == 0 {
+            String::new()
+        } else {
+            paint(&format!("{}{}", symbol, value), color)
+        }
+    };
+    let staged: String = count("+", status.staged, &theme.git_staged_color);
+    let unstaged: String = count("!", status.unstaged, &theme.git_unstaged_color);
+    let untracked: String = count("?", status.untracked, &theme.git_untracked_color);
+    let ahead: String = count("⇡", status.ahead, &theme.git_ahead_behind_color);
+    let behind: String = count("⇣", status.behind, &theme.git_ahead_behind_color);
+    let stash: String = count("*", status.stash, &theme.git_stash_color);
+    let state: String = status
+        .operation
+        .as_deref()
+        .map(|op| paint(op, &theme.git_operation_color))
+        .unwrap_or_default();
+
+    let summary: String = [
+        staged.clone(),
+        unstaged.clone(),
+        untracked.clone(),
+        format!("{}{}", ahead, behind),
+        stash.clone(),
+        state.clone(),
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .map(|part| format!(" {}", part))
+    .collect();
+
+    prompt
+        .replace("{git_status}", &summary)
+        .replace("{git_staged}", &staged)
+        .replace("{git_unstaged}", &unstaged)
+        .replace("{git_untracked}", &untracked)
+        .replace("{git_ahead}", &ahead)
+        .replace("{git_behind}", &behind)
+        .replace("{git_stash}", &stash)
+        .replace("{git_state}", &state)
+        .replace("{git}", &head)
+}
@@ -0,0 +1,132 @@
+use super::engine::SegmentValues;
+use super::format_prompt;
+use crate::config::{FluxConfig, Style, Theme};
+use std::env;
+
+/// Separator glyph of powerline-patched fonts
+const POWERLINE_GLYPH: &str = "\u{e0b0}";
+
+/// Separator used when the font or terminal lacks powerline glyphs
+const ASCII_GLYPH: &str = ">";
+
+/// Renders the main prompt as a row of filled powerline segments
+///
+/// Each segment is drawn with its theme style as the fill (see
+/// `Style::powerline_fill`), and separators take the fill of the segment
+/// before them as their color and the next fill as their background. Segments
+/// that render empty, such as `git` outside a repository, are skipped.
+///
+/// # Arguments
+/// * `config` - Shell configuration containing the segment list and theme
+/// * `values` - Slow and plugin segment values gathered by the prompt engine
+///
+/// # Returns
+/// * Formatted prompt string, followed by `powerline_suffix`
+pub(super) fn render(config: &FluxConfig, values: &SegmentValues) -> String {
+    let plain: FluxConfig = unstyled(config);
+    let glyph: &str = separator(config);
+
+    let blocks: Vec<(String, Style)> = config
+        .powerline_segments
+        .iter()
+        .filter_map(|name| {
+            let template: String = segment_template(name);
+            let text: String = format_prompt(&template, &plain, values);
+            // Placeholders that are switched off are left in the text as-is
+            if text.trim().is_empty() || text == template {
+                return None;
+            }
+            let style: Style = segment_style(name, config, values)
+                .parse::<Style>()
+                .unwrap_or_default();
+            Some((text.trim().to_string(), style.powerline_fill()))
+        })
+        .collect();
+
+    let mut prompt: String = String::new();
+    for (index, (text, fill)) in blocks.iter().enumerate() {
+        prompt.push_str(&fill.paint(&format!(" {} ", text)));
+        let next: Option<&Style> = blocks.get(index + 1).map(|(_, fill)| fill);
+        prompt.push_str(&Style::powerline_separator(fill, next).paint(glyph));
+    }
+
+    prompt.push_str(&format_prompt(&config.powerline_suffix, config, values));
+    prompt
+}
+
+/// Every placeholder the segment list uses, for deciding which slow segments
+/// to compute
+pub(super) fn template(config: &FluxConfig) -> String {
+    let mut template: String = config
+        .powerline_segments
+        .iter()
+        .map(|name| segment_template(name))
+        .collect();
+    template.push_str(&config.powerline_suffix);
+    template
+}
+
+/// Template rendered for a segment name
+fn segment_template(name: &str) -> String {
+    match name {
+        "git" => "{git}{git_status}".to_string(),
+        other => format!("{{{}}}", other),
+    }
+}
+
+/// Theme style for a segment name
+fn segment_style<'a>(name: &str, config: &'a FluxConfig, values: &SegmentValues) -> &'a str {
+    let theme: &Theme = &config.theme;
+    match name {
+        "user" => &theme.username_color,
+        "host" => &theme.hostname_color,
+        "dir" => &theme.directory_color,
+        "time" => &theme.time_color,
+        "git" if values.git.as_ref().is_some_and(|git| git.is_detached()) => {
+            &theme.git_detached_color
+        }
+        "git" => &theme.git_branch_color,
+        other => config
+            .prompt_segments
+            .get(other)
+            .map_or(&theme.accent_color, |segment| &segment.color),
+    }
+}
+
+/// Copy of the configuration with every style cleared, so segments render
+/// as plain text inside their fill
+fn unstyled(config: &FluxConfig) -> FluxConfig {
+    let mut plain: FluxConfig = config.clone();
+    plain.theme = Theme::plain();
+    for segment in plain.prompt_segments.values_mut() {
+        segment.color.clear();
+    }
+    plain
+}
+
+/// Chooses the separator glyph from `powerline_separator`
+///
+/// `auto` uses the powerline glyph unless the locale is not UTF-8 or the
+/// terminal is the Linux console, whose font lacks it.
+fn separator(config: &FluxConfig) -> &'static str {
+    match config.powerline_separator.as_str() {
+        "powerline" => POWERLINE_GLYPH,
+        "ascii" => ASCII_GLYPH,
+        _ => {
+            let locale: String = ["LC_ALL", "LC_CTYPE", "LANG"]
+                .iter()
+                .filter_map(|var| env::var(var).ok())
+                .find(|value| !value.is_empty())
+                .unwrap_or_default()
+                .to_lowercase();
+            let utf8: bool =
+                locale.is_empty() || locale.contains("utf-8") || locale.contains("utf8");
+            let console: bool = env::var("TERM").is_ok_and(|term| term == "linux");
+            if utf8 && !console {
+                POWERLINE_GLYPH
+            } else {
+                ASCII_GLYPH
+            }
+        }
+    }
+}
@@ -1,10 +1,11 @@
+use super::paths;
+use crate::plugin::set_env_var;
 use base64::{engine::general_purpose, Engine as _};
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::PathBuf;
 
-const INTERNAL_ENV_FILE: &str = ".env";
 const ENCRYPTION_KEY: &[u8] = b"flux-shell-secret-key"; // You might want to generate this dynamically
 
 /// Gets the path to the internal environment variable storage file
@@ -12,11 +13,7 @@ const ENCRYPTION_KEY: &[u8] = b"flux-shell-secret-key"; // You might want to gen
 /// # Returns
 /// * `PathBuf` - Path to the environment variable file
 fn get_internal_env_path() -> PathBuf {
-    let mut path: PathBuf = dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("sh.lrr.flux");
-    path.push(INTERNAL_ENV_FILE);
-    path
+    paths::internal_env_file()
 }
 
 /// Encodes a value using XOR encryption and base64 encoding
@@ -166,18 +163,35 @@ pub fn expand_env_vars(input: &str) -> String {
     result
 }
 
+/// Applies a new set of environment variables from a reloaded configuration
+///
+/// Variables dropped from the configuration are removed, unless something
+/// else has changed their value since they were set.
+///
+/// # Arguments
+/// * `previous` - Variables set by the previous configuration
+/// * `vars` - Variables from the new configuration
+pub fn reapply_env_vars(previous: &HashMap<String, String>, vars: &HashMap<String, String>) {
+    for (key, value) in previous {
+        if !vars.contains_key(key) && std::env::var(key).as_deref() == Ok(value.as_str()) {
+            set_env_var(key, None);
+        }
+    }
+    set_initial_env_vars(vars);
+}
+
 /// Sets initial environment variables from configuration
 ///
 /// # Arguments
 /// * `vars` - Map of environment variables to set
 pub fn set_initial_env_vars(vars: &HashMap<String, String>) {
     for (key, value) in vars {
-        std::env::set_var(key, value);
+        set_env_var(key, Some(value));
     }
 
     if std::env::var("SHELL").is_err() {
         if let Ok(exe) = std::env::current_exe() {
-            std::env::set_var("SHELL", exe.to_string_lossy().to_string());
+            set_env_var("SHELL", Some(&exe.to_string_lossy()));
         }
     }
 }
@@ -1,2 +1,5 @@
 /// Utility functions and modules for the shell
 pub mod env;
+pub mod paths;
+pub mod process;
+pub mod terminal;
@@ -0,0 +1,122 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Directory name used under each base directory
+const APP_DIR: &str = "sh.lrr.flux";
+
+/// Config file given with `--config`, if any
+static CONFIG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Uses a specific configuration file instead of the one in `config_dir`
+///
+/// Has no effect if called more than once.
+///
+/// # Arguments
+/// * `path` - Path given with `--config`
+pub fn set_config_file(path: PathBuf) {
+    let _ = CONFIG_FILE.set(path);
+}
+
+/// Directory holding the configuration file and its includes
+///
+/// `$FLUX_CONFIG_DIR` if set, otherwise `$XDG_CONFIG_HOME/sh.lrr.flux`,
+/// otherwise the platform's config directory.
+pub fn config_dir() -> PathBuf {
+    flux_dir().unwrap_or_else(|| base_dir("XDG_CONFIG_HOME", dirs::config_dir()))
+}
+
+/// Directory holding history and plugins
+///
+/// `$FLUX_CONFIG_DIR` if set, otherwise `$XDG_DATA_HOME/sh.lrr.flux`,
+/// otherwise the platform's data directory.
+pub fn data_dir() -> PathBuf {
+    flux_dir().unwrap_or_else(|| base_dir("XDG_DATA_HOME", dirs::data_dir()))
+}
+
+/// Directory holding state that can be recreated, such as plugin builds
+///
+/// `$FLUX_CONFIG_DIR` if set, otherwise `$XDG_STATE_HOME/sh.lrr.flux`,
+/// otherwise the platform's state (or data) directory.
+pub fn state_dir() -> PathBuf {
+    flux_dir()
+        .unwrap_or_else(|| base_dir("XDG_STATE_HOME", dirs::state_dir().or_else(dirs::data_dir)))
+}
+
+/// Path to the configuration file
+///
+/// The `--config` file if one was given, otherwise `config.toml` in
+/// `config_dir` if it exists, otherwise `config.fl`.
+pub fn config_file() -> PathBuf {
+    if let Some(path) = CONFIG_FILE.get() {
+        return path.clone();
+    }
+
+    // A hand-written TOML config takes precedence over the generated JSON one
+    let dir: PathBuf = config_dir();
+    let toml_path: PathBuf = dir.join("config.toml");
+    if toml_path.exists() {
+        return toml_path;
+    }
+    dir.join("config.fl")
+}
+
+/// Directory holding user theme files
+pub fn themes_dir() -> PathBuf {
+    config_dir().join("themes")
+}
+
+/// Path to the command history file
+pub fn history_file() -> PathBuf {
+    with_legacy_fallback(data_dir().join("history.txt"), "history.txt")
+}
+
+/// Directory installed plugins are kept in
+pub fn plugin_dir() -> PathBuf {
+    with_legacy_fallback(data_dir().join("plugins"), "plugins")
+}
+
+/// Directory plugins are cloned and built in before installing
+pub fn plugin_build_dir() -> PathBuf {
+    state_dir().join("plugin-builds")
+}
+
+/// Path to the keys plugin releases may be signed with
+pub fn trusted_keys_file() -> PathBuf {
+    config_dir().join("trusted-keys")
+}
+
+/// Path to the encoded store of internal environment variables
+pub fn internal_env_file() -> PathBuf {
+    config_dir().join(".env")
+}
+
+/// Reads `$FLUX_CONFIG_DIR`, which keeps every file in one directory
+fn flux_dir() -> Option<PathBuf> {
+    env::var_os("FLUX_CONFIG_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Resolves `$var/sh.lrr.flux`, falling back to a platform directory
+///
+/// Relative XDG values are ignored, as the XDG specification requires.
+fn base_dir(var: &str, fallback: Option<PathBuf>) -> PathBuf {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or(fallback)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR)
+}
+
+/// Keeps using a file from the config directory, where older versions of
+/// flux stored everything, until it exists at its new location
+fn with_legacy_fallback(path: PathBuf, name: &str) -> PathBuf {
+    let legacy: PathBuf = config_dir().join(name);
+    if !path.exists() && legacy.exists() {
+        legacy
+    } else {
+        path
+    }
+}
@@ -0,0 +1,64 @@
+use crate::plugin::with_env_unchanged;
+use std::io::{self, Read};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Runs a command to completion, killing it if it exceeds the timeout
+///
+/// Stdout and stderr are captured; stdin is closed.
+///
+/// # Arguments
+/// * `command` - Command to run
+/// * `timeout` - Maximum time the command may run
+///
+/// # Returns
+/// * `io::Result<Output>` - Command output, or an `ErrorKind::TimedOut` error
+pub fn output_with_timeout(command: &mut Command, timeout: Duration) -> io::Result<Output> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child: Child = with_env_unchanged(|| command.spawn())?;
+
+    // Drain the pipes on separate threads so a chatty child can't block on a full pipe
+    let stdout_reader = child.stdout.take().map(spawn_reader);
+    let stderr_reader = child.stderr.take().map(spawn_reader);
+
+    let deadline: Instant = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("command timed out after {}ms", timeout.as_millis()),
+            ));
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+
+    let collect = |reader: Option<thread::JoinHandle<Vec<u8>>>| -> Vec<u8> {
+        reader
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    };
+
+    Ok(Output {
+        status,
+        stdout: collect(stdout_reader),
+        stderr: collect(stderr_reader),
+    })
+}
+
+/// Reads a pipe to the end on a background thread
+fn spawn_reader<R: Read + Send + 'static>(mut pipe: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer: Vec<u8> = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        buffer
+    })
+}
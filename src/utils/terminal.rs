@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+/// Watches for a key press without reading it, while the terminal is not in
+/// the line editor's hands
+///
+/// Typed keys are neither echoed nor held back until Enter while this is
+/// alive; the original terminal settings come back when it is dropped, and
+/// the keys are left for the line editor to read.
+#[cfg(unix)]
+pub struct KeyWatch {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl KeyWatch {
+    /// Starts watching stdin
+    ///
+    /// # Returns
+    /// * `Option<KeyWatch>` - The watch, or None if stdin is not a terminal
+    pub fn start() -> Option<Self> {
+        // SAFETY: termios is plain data, filled in by tcgetattr before use
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return None;
+        }
+        let mut watching: libc::termios = original;
+        watching.c_lflag &= !(libc::ICANON | libc::ECHO);
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &watching) } != 0 {
+            return None;
+        }
+        Some(KeyWatch { original })
+    }
+
+    /// Waits for a key press
+    ///
+    /// # Arguments
+    /// * `timeout` - Longest time to wait
+    ///
+    /// # Returns
+    /// * `bool` - Whether a key is waiting to be read
+    pub fn key_pressed(&self, timeout: Duration) -> bool {
+        let mut stdin: libc::pollfd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout: libc::c_int = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        unsafe { libc::poll(&mut stdin, 1, timeout) > 0 }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyWatch {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Watches for a key press; elsewhere than Unix the prompt is handed to the
+/// line editor straight away
+#[cfg(not(unix))]
+pub struct KeyWatch;
+
+#[cfg(not(unix))]
+impl KeyWatch {
+    pub fn start() -> Option<Self> {
+        None
+    }
+
+    pub fn key_pressed(&self, _timeout: Duration) -> bool {
+        true
+    }
+}